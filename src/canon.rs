@@ -0,0 +1,81 @@
+use std::rc::Rc;
+
+use crate::expr::{BinopKind, BoolExpr, CmpKind, Expr, Signedness, Variable};
+
+/// Maps candidates to a canonical representative, so that candidates
+/// that only differ in the order of commutative operands, the grouping
+/// of associative chains or in subtracting instead of adding a constant
+/// get the same form.
+///
+/// The canonical form is a key, not a replacement: it denotes the same
+/// family of functions as the original candidate (unknown constants are
+/// existential), but its unknown constants may be in another order.
+pub struct Canonicalizer {
+    width: u32,
+    signedness: Signedness,
+}
+
+impl Canonicalizer {
+    pub fn new(width: u32, signedness: Signedness) -> Self {
+        Self { width, signedness }
+    }
+
+    pub fn canonicalize(&self, expr: &Expr) -> Expr {
+        expr.walk_expr(
+            &mut |v| match v {
+                Variable::Const(x) => Variable::Const(self.signedness.truncate(*x, self.width)),
+                v => *v,
+            },
+            &mut |unop_kind, e| Expr::Unop(unop_kind, Rc::new(e)),
+            &mut |binop_kind, l, r| self.binop(binop_kind, l, r),
+            &mut |cmp_kind, l, r| match cmp_kind {
+                CmpKind::Eq if r < l => BoolExpr::Cmp(cmp_kind, Rc::new(r), Rc::new(l)),
+                _ => BoolExpr::Cmp(cmp_kind, Rc::new(l), Rc::new(r)),
+            },
+            &mut |c, t, e| Expr::Ite(c, Rc::new(t), Rc::new(e)),
+            &mut |x| Expr::Variable(x),
+        )
+    }
+
+    fn binop(&self, kind: BinopKind, l: Expr, r: Expr) -> Expr {
+        // x - c is x + (-c). An unknown constant can absorb the negation.
+        let (kind, r) = match (kind, r) {
+            (BinopKind::Minus, Expr::Variable(Variable::Const(c))) => {
+                let c = self.signedness.truncate(c.wrapping_neg(), self.width);
+                (BinopKind::Plus, Expr::Variable(Variable::Const(c)))
+            },
+            (BinopKind::Minus, r @ Expr::Variable(Variable::UnknownConst)) => {
+                (BinopKind::Plus, r)
+            },
+            (kind, r) => (kind, r),
+        };
+
+        if !kind.is_associative() || !kind.is_commutative() {
+            return Expr::Binop(kind, Rc::new(l), Rc::new(r));
+        }
+
+        let mut operands = Vec::new();
+        Self::flatten(kind, l, &mut operands);
+        Self::flatten(kind, r, &mut operands);
+        operands.sort();
+
+        operands.into_iter()
+            .reduce(|acc, x| Expr::Binop(kind, Rc::new(acc), Rc::new(x)))
+            .unwrap()
+    }
+
+    /// Collect the operands of a chain of `kind` operations.
+    fn flatten(kind: BinopKind, expr: Expr, operands: &mut Vec<Expr>) {
+        let mut stack = vec![expr];
+
+        while let Some(expr) = stack.pop() {
+            match &expr {
+                Expr::Binop(k, l, r) if *k == kind => {
+                    stack.push(Expr::clone(r));
+                    stack.push(Expr::clone(l));
+                },
+                _ => operands.push(expr),
+            }
+        }
+    }
+}
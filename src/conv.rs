@@ -1,14 +1,15 @@
-use std::collections::HashMap;
-
 use log::trace;
 
-use crate::expr::{AnswerExpr, Expr, ExprVal, Value, Variable, BITS_PER_VAL};
+use crate::expr::{val_from_z3, AnswerExpr, Expr, ExprVal, Signedness, Value, Variable};
 
 /// A structure for quickly converting various flavours of `Expr` into
 /// z3.
 pub struct Z3ToExpr<'ctx> {
     z3: &'ctx z3::Context,
-    arguments: HashMap<String, usize>,
+    width: u32,
+    signedness: Signedness,
+    arguments: Vec<String>,
+    results: Vec<String>,
     z3_consts: Vec<z3::ast::BV<'ctx>>,
     z3_args: Vec<z3::ast::BV<'ctx>>,
 }
@@ -16,25 +17,35 @@ pub struct Z3ToExpr<'ctx> {
 impl<'ctx> Z3ToExpr<'ctx> {
     pub fn new(
         z3: &'ctx z3::Context,
+        width: u32,
+        signedness: Signedness,
         arguments: impl IntoIterator<Item = String>,
+        results: impl IntoIterator<Item = String>,
     ) -> Self {
-        let arguments =
-            arguments.into_iter()
-                .enumerate()
-                .map(|(idx, arg_name)| (arg_name, idx))
-                .collect::<HashMap<_, _>>();
-        let z3_args = arguments.keys()
-            .map(|name| Self::new_z3_arg(z3, name))
+        let arguments = arguments.into_iter().collect::<Vec<_>>();
+        let z3_args = arguments.iter()
+            .map(|name| Self::new_z3_arg(z3, name, width))
             .collect();
 
         Self {
             z3,
+            width,
+            signedness,
             arguments,
+            results: results.into_iter().collect(),
             z3_args,
             z3_consts: Vec::new(),
         }
     }
 
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn signedness(&self) -> Signedness {
+        self.signedness
+    }
+
     pub fn z3_args(&self) -> &[z3::ast::BV<'ctx>] {
         &self.z3_args
     }
@@ -43,43 +54,63 @@ impl<'ctx> Z3ToExpr<'ctx> {
         &self.z3_consts
     }
 
+    pub fn arguments(&self) -> &[String] {
+        &self.arguments
+    }
+
+    pub fn results(&self) -> &[String] {
+        &self.results
+    }
+
     pub fn declaration(&self) -> String {
-        self.arguments.keys()
-            .map(|x| x.as_str())
-            .chain(std::iter::once("res"))
-            .map(|x| format!("(declare-const {x:} (_ BitVec {BITS_PER_VAL:}))"))
+        let width = self.width;
+
+        self.arguments.iter()
+            .chain(&self.results)
+            .map(|x| format!("(declare-const {x:} (_ BitVec {width:}))"))
             .collect::<Vec<_>>()
             .join("\n")
     }
 
     pub fn get_argument(&self, x: &str) -> Option<&z3::ast::BV<'ctx>> {
-        let id = self.arguments.get(x)?;
+        let id = self.arguments.iter().position(|arg| arg == x)?;
 
-        // NOTE: yes, this may panic, but z3_args not having an entry
-        // at this point would be a bug.
-        Some(&self.z3_args[*id])
+        Some(&self.z3_args[id])
     }
 
-    pub fn build_answer(&mut self, expr: &Expr, model: &z3::Model) -> AnswerExpr {
-        let args = &self.arguments;
-        let consts = &mut self.z3_consts;
+    /// Fill the constants of a candidate in from `model`. The candidate
+    /// has an expression for every result, their constants are numbered
+    /// consecutively, like [Z3ToExpr::cand_to_z3] does.
+    pub fn build_answer(&self, cand: &[Expr], model: &z3::Model) -> Vec<AnswerExpr> {
         let mut next_const_idx = 0;
 
+        cand.iter()
+            .map(|expr| self.build_answer_from(expr, model, &mut next_const_idx))
+            .collect()
+    }
+
+    fn build_answer_from(
+        &self,
+        expr: &Expr,
+        model: &z3::Model,
+        next_const_idx: &mut usize,
+    ) -> AnswerExpr {
+        let signedness = self.signedness;
+        let args = &self.arguments;
+        let consts = &self.z3_consts;
+
         expr.to_ans(
             |v| {
                 match v {
-                    Variable::Argument(idx) =>
-                        args.iter()
-                            .find(|(_, other)| **other == idx)
-                            .map(|(name, _)| name.clone())
-                            .map(Value::Arg)
-                            .unwrap(),
+                    Variable::Argument(idx) => Value::Arg(args[idx].clone()),
                     Variable::UnknownConst => {
-                        let c = &consts[next_const_idx];
-                        let interp = model.get_const_interp(c).unwrap();
-                        let val = interp.as_i64().unwrap() as i32;
+                        let c = &consts[*next_const_idx];
+                        // NOTE: the constant may be irrelevant for the
+                        // candidate, so we let z3 pick any value for it.
+                        let interp = model.eval(c, true).unwrap();
+                        let val = val_from_z3(&interp, signedness).unwrap();
 
-                        next_const_idx += 1;
+                        *next_const_idx += 1;
 
                         Value::Const(val)
                     },
@@ -91,40 +122,58 @@ impl<'ctx> Z3ToExpr<'ctx> {
         )
     }
 
+    /// The values of the arguments in `model`. Any value fits
+    /// the arguments z3 has left out of it.
     pub fn build_counter_example(&self, model: &z3::Model) -> Vec<ExprVal> {
         self.z3_args.iter()
-            .map(|x| model.get_const_interp(x).expect("No val for arg"))
-            .map(|x| x.as_i64().unwrap() as ExprVal)
+            .map(|x| model.eval(x, true).expect("No val for arg"))
+            .map(|x| val_from_z3(&x, self.signedness).unwrap())
             .collect()
     }
 
     pub fn ans_expr_to_z3(&self, expr: &AnswerExpr) -> z3::ast::BV<'ctx> {
         expr.to_z3_ans(
-            &self.z3,
+            self.z3,
+            self.width,
             |v| self.get_argument(v).unwrap().clone()
         )
     }
 
     pub fn expr_to_z3(&mut self, expr: &Expr) -> z3::ast::BV<'ctx> {
+        self.expr_to_z3_from(expr, &mut 0)
+    }
+
+    /// Convert a candidate with an expression for every result. The
+    /// constants of the expressions are distinct.
+    pub fn cand_to_z3(&mut self, cand: &[Expr]) -> Vec<z3::ast::BV<'ctx>> {
+        let mut next_const_idx = 0;
+
+        cand.iter()
+            .map(|expr| self.expr_to_z3_from(expr, &mut next_const_idx))
+            .collect()
+    }
+
+    fn expr_to_z3_from(&mut self, expr: &Expr, next_const_idx: &mut usize) -> z3::ast::BV<'ctx> {
+        let width = self.width;
         let args = &self.z3_args;
         let consts = &mut self.z3_consts;
-        let mut next_const_idx = 0;
 
         trace!("Convert to z3: {expr:?}");
 
         expr.to_z3(
-            &self.z3,
-            |ctx, v| {
-                let res = match consts.get(next_const_idx) {
+            self.z3,
+            width,
+            |ctx, _| {
+                let res = match consts.get(*next_const_idx) {
                     Some(x) => x.clone(),
                     None => {
-                        let c = Self::new_z3_const(ctx, next_const_idx);
+                        let c = Self::new_z3_const(ctx, *next_const_idx, width);
                         consts.push(c.clone());
                         c
                     },
                 };
 
-                next_const_idx += 1;
+                *next_const_idx += 1;
 
                 res
             },
@@ -132,19 +181,19 @@ impl<'ctx> Z3ToExpr<'ctx> {
         )
     }
 
-    fn new_z3_const(ctx: &z3::Context, idx: usize) -> z3::ast::BV<'_> {
+    fn new_z3_const(ctx: &z3::Context, idx: usize, width: u32) -> z3::ast::BV<'_> {
         z3::ast::BV::new_const(
             ctx,
             format!("c{idx:}"),
-            BITS_PER_VAL,
+            width,
         )
     }
 
-    fn new_z3_arg<'a>(ctx: &'a z3::Context, name: &str) -> z3::ast::BV<'a> {
+    fn new_z3_arg<'a>(ctx: &'a z3::Context, name: &str, width: u32) -> z3::ast::BV<'a> {
         z3::ast::BV::new_const(
             ctx,
             name,
-            BITS_PER_VAL,
+            width,
         )
     }
 }
\ No newline at end of file
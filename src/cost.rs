@@ -0,0 +1,169 @@
+use crate::expr::{BinopKind, CmpKind, Expr, ExprVal, UnopKind};
+use crate::simplify::ConstVar;
+
+/// Assigns a cost to every operator, so that the expressions can be
+/// compared. The cost of an expression is the sum of the costs of its
+/// nodes, see [Expr::cost]. The arguments are free.
+pub trait CostModel {
+    fn unop(&self, kind: UnopKind) -> u32;
+
+    fn binop(&self, kind: BinopKind) -> u32;
+
+    /// The cost of an if-then-else on the comparison `kind`.
+    fn ite(&self, kind: CmpKind) -> u32;
+
+    /// The cost of materializing a constant. Free by default.
+    fn constant(&self, _val: ExprVal) -> u32 {
+        0
+    }
+}
+
+/// The built-in cost models. Apart from [Profile::Nodes], the costs
+/// are rough estimates of the instruction sequences a compiler would
+/// emit for a typical core of the architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Profile {
+    /// Every operator costs 1
+    Nodes,
+    /// Latency in cycles on a modern out-of-order x86-64 core
+    X86Latency,
+    /// Reciprocal throughput in quarter-cycles on a modern x86-64 core
+    X86Throughput,
+    /// Latency in cycles on an AArch64 core without the CSSC extension
+    Aarch64,
+    /// Instruction count on RV64GC, i.e. without the bit-manipulation
+    /// extensions
+    Riscv,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 5] = [
+        Profile::Nodes,
+        Profile::X86Latency,
+        Profile::X86Throughput,
+        Profile::Aarch64,
+        Profile::Riscv,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Nodes => "nodes",
+            Profile::X86Latency => "x86-64 latency",
+            Profile::X86Throughput => "x86-64 throughput",
+            Profile::Aarch64 => "aarch64",
+            Profile::Riscv => "risc-v",
+        }
+    }
+}
+
+impl CostModel for Profile {
+    fn unop(&self, kind: UnopKind) -> u32 {
+        use UnopKind::*;
+
+        match (self, kind) {
+            (Profile::Nodes, _) => 1,
+            (Profile::X86Latency, Not | Negate | Bswap) => 1,
+            (Profile::X86Latency, Popcnt | Clz | Ctz) => 3,
+            (Profile::X86Throughput, Not | Negate) => 1,
+            (Profile::X86Throughput, Bswap) => 2,
+            (Profile::X86Throughput, Popcnt | Clz | Ctz) => 4,
+            (Profile::Aarch64, Not | Negate | Clz | Bswap) => 1,
+            // rbit + clz
+            (Profile::Aarch64, Ctz) => 2,
+            // A round trip through the vector unit
+            (Profile::Aarch64, Popcnt) => 8,
+            (Profile::Riscv, Not | Negate) => 1,
+            (Profile::Riscv, Bswap) => 10,
+            (Profile::Riscv, Popcnt | Clz | Ctz) => 12,
+        }
+    }
+
+    fn binop(&self, kind: BinopKind) -> u32 {
+        use BinopKind::*;
+
+        match (self, kind) {
+            (Profile::Nodes, _) => 1,
+            (Profile::X86Latency, Mul) => 3,
+            (Profile::X86Latency, SDiv | UDiv | SRem | URem) => 26,
+            (Profile::X86Latency, _) => 1,
+            (Profile::X86Throughput, And | Or | Xor | Plus | Minus) => 1,
+            (Profile::X86Throughput, Shl | ShrA | ShrL | Rotl | Rotr) => 2,
+            (Profile::X86Throughput, Mul) => 4,
+            (Profile::X86Throughput, SDiv | UDiv | SRem | URem) => 24,
+            (Profile::Aarch64, Mul) => 2,
+            (Profile::Aarch64, SDiv | UDiv) => 12,
+            // A division followed by msub
+            (Profile::Aarch64, SRem | URem) => 14,
+            // There is only ror, so the amount is negated first
+            (Profile::Aarch64, Rotl) => 2,
+            (Profile::Aarch64, _) => 1,
+            (Profile::Riscv, Mul) => 3,
+            (Profile::Riscv, SDiv | UDiv | SRem | URem) => 20,
+            // Two shifts, an or and the complementary amount
+            (Profile::Riscv, Rotl | Rotr) => 4,
+            (Profile::Riscv, _) => 1,
+        }
+    }
+
+    fn ite(&self, kind: CmpKind) -> u32 {
+        match (self, kind) {
+            (Profile::Nodes, _) => 1,
+            // cmp + cmov
+            (Profile::X86Latency, _) => 2,
+            (Profile::X86Throughput, _) => 3,
+            // cmp + csel
+            (Profile::Aarch64, _) => 2,
+            // No conditional moves, so the branches get masked
+            (Profile::Riscv, CmpKind::Eq) => 5,
+            (Profile::Riscv, CmpKind::Slt | CmpKind::Ult) => 4,
+        }
+    }
+
+    fn constant(&self, val: ExprVal) -> u32 {
+        match self {
+            Profile::Nodes => 0,
+            // Immediates are sign-extended from 32 bits, the rest need movabs
+            Profile::X86Latency | Profile::X86Throughput => {
+                u32::from(i32::try_from(val).is_err())
+            },
+            // A movz/movk for every non-zero 16-bit chunk of a 64-bit value,
+            // unless it fits into the 12-bit immediate of an arithmetic op
+            Profile::Aarch64 if (0..4096).contains(&val) => 0,
+            Profile::Aarch64 => (0..4)
+                .filter(|chunk| (val as u64 >> (chunk * 16)) & 0xffff != 0)
+                .count() as u32,
+            // lui + addi, unless it fits into a 12-bit immediate
+            Profile::Riscv if (-2048..2048).contains(&val) => 0,
+            Profile::Riscv => 2,
+        }
+    }
+}
+
+impl<V: ConstVar> Expr<V> {
+    /// The cost of the expression under `model`. Constants, which are
+    /// not known yet, are free, so for a candidate this is a lower bound
+    /// of the cost of any answer built from it. Simplifying the answer
+    /// can make it cheaper than that.
+    pub fn cost(&self, model: &dyn CostModel) -> u32 {
+        self.walk_expr(
+            &mut |v| v.as_const().map_or(0, |x| model.constant(x)),
+            &mut |unop_kind, e| e + model.unop(unop_kind),
+            &mut |binop_kind, l, r| l + r + model.binop(binop_kind),
+            &mut |cmp_kind, l, r| (cmp_kind, l + r),
+            &mut |(cmp_kind, c), t, e| c + t + e + model.ite(cmp_kind),
+            &mut |x| x,
+        )
+    }
+}
+
+/// The cost of a tuple of expressions, e.g. an answer with several
+/// results. Their common subterms are counted once per expression.
+pub fn total_cost<V: ConstVar>(model: &dyn CostModel, exprs: &[Expr<V>]) -> u32 {
+    exprs.iter().map(|x| x.cost(model)).sum()
+}
+
+/// Sort `exprs` from the cheapest to the most expensive one.
+/// Expressions of equal cost keep their order.
+pub fn rank_by_cost<V: ConstVar>(model: &dyn CostModel, exprs: &mut [Expr<V>]) {
+    exprs.sort_by_cached_key(|x| x.cost(model));
+}
@@ -0,0 +1,239 @@
+use std::{collections::HashMap, fmt, hash::Hash, rc::Rc};
+
+use crate::expr::{
+    z3_binop, z3_cmp, z3_unop, BinopKind, BoolExpr, CmpKind, Expr, UnopKind, Value, Variable,
+};
+
+/// A reference to a node of a [Dag].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// A single operator, whose operands are other nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node<V = Variable> {
+    Variable(V),
+    Unop(UnopKind, NodeId),
+    Binop(BinopKind, NodeId, NodeId),
+    /// If-then-else on the comparison of the first two operands
+    Ite(CmpKind, NodeId, NodeId, NodeId, NodeId),
+}
+
+impl<V> Node<V> {
+    fn operands(&self) -> Vec<NodeId> {
+        match self {
+            Node::Variable(_) => vec![],
+            Node::Unop(_, e) => vec![*e],
+            Node::Binop(_, l, r) => vec![*l, *r],
+            Node::Ite(_, l, r, t, e) => vec![*l, *r, *t, *e],
+        }
+    }
+}
+
+/// Expressions with explicit sharing. The nodes are hash-consed, so each
+/// distinct subterm is stored exactly once, no matter how many times it
+/// is used. Since a node can only refer to the nodes added before it,
+/// the arena is always in topological order.
+///
+/// Note, that the leaves are shared too. In particular, all the occurrences
+/// of [Variable::UnknownConst] become the same unknown constant.
+#[derive(Debug, Clone)]
+pub struct Dag<V = Variable> {
+    nodes: Vec<Node<V>>,
+    ids: HashMap<Node<V>, NodeId>,
+}
+
+pub type AnswerDag = Dag<Value>;
+
+impl<V> Default for Dag<V> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+}
+
+impl<V: Clone + Eq + Hash> Dag<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node, unless an identical one is already there.
+    pub fn add(&mut self, node: Node<V>) -> NodeId {
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+
+        let id = NodeId(self.nodes.len());
+        assert!(node.operands().iter().all(|x| *x < id), "Operand of {id:?} is not in the DAG");
+
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node<V> {
+        &self.nodes[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Add all the subterms of `expr`. Returns the node of `expr` itself.
+    pub fn add_expr(&mut self, expr: &Expr<V>) -> NodeId {
+        let dag = std::cell::RefCell::new(self);
+        let add = |node| dag.borrow_mut().add(node);
+
+        expr.walk_expr(
+            &mut |v| add(Node::Variable(v.clone())),
+            &mut |unop_kind, e| add(Node::Unop(unop_kind, e)),
+            &mut |binop_kind, l, r| add(Node::Binop(binop_kind, l, r)),
+            &mut |cmp_kind, l, r| (cmp_kind, l, r),
+            &mut |(cmp_kind, l, r), t, e| add(Node::Ite(cmp_kind, l, r, t, e)),
+            &mut |x| x,
+        )
+    }
+
+    pub fn from_expr(expr: &Expr<V>) -> (Self, NodeId) {
+        let mut dag = Self::new();
+        let root = dag.add_expr(expr);
+
+        (dag, root)
+    }
+
+    /// Fold the nodes `root` depends on, visiting each one once.
+    /// `action` receives a node together with the results for its
+    /// operands.
+    pub fn fold<T, F>(&self, root: NodeId, mut action: F) -> T
+    where
+        T: Clone,
+        F: FnMut(&Node<V>, &[T]) -> T,
+    {
+        let live = self.live(&[root]);
+        let mut res: Vec<Option<T>> = vec![None; root.0 + 1];
+
+        for idx in (0..=root.0).filter(|x| live[*x]) {
+            let node = &self.nodes[idx];
+            let operands = node.operands()
+                .into_iter()
+                .map(|x| res[x.0].clone().unwrap())
+                .collect::<Vec<_>>();
+
+            res[idx] = Some(action(node, &operands));
+        }
+
+        res[root.0].take().unwrap()
+    }
+
+    /// Rebuild the tree form of `root`. The shared subterms
+    /// are shared by the resulting `Rc`s as well.
+    pub fn to_expr(&self, root: NodeId) -> Expr<V> {
+        let res = self.fold(root, |node, ops: &[Rc<Expr<V>>]| Rc::new(match node {
+            Node::Variable(v) => Expr::Variable(v.clone()),
+            Node::Unop(kind, _) => Expr::Unop(*kind, ops[0].clone()),
+            Node::Binop(kind, _, _) => Expr::Binop(*kind, ops[0].clone(), ops[1].clone()),
+            Node::Ite(kind, _, _, _, _) => Expr::Ite(
+                BoolExpr::Cmp(*kind, ops[0].clone(), ops[1].clone()),
+                ops[2].clone(),
+                ops[3].clone(),
+            ),
+        }));
+
+        Rc::unwrap_or_clone(res)
+    }
+
+    /// Encode `root` into z3. Every shared node is encoded once.
+    pub fn to_z3<'ctx, F>(
+        &self,
+        ctx: &'ctx z3::Context,
+        root: NodeId,
+        width: u32,
+        mut var_map: F,
+    ) -> z3::ast::BV<'ctx>
+    where
+        F: FnMut(&'ctx z3::Context, &V) -> z3::ast::BV<'ctx>,
+    {
+        self.fold(root, |node, ops| match node {
+            Node::Variable(v) => var_map(ctx, v),
+            Node::Unop(kind, _) => z3_unop(*kind, &ops[0], width),
+            Node::Binop(kind, _, _) => z3_binop(*kind, &ops[0], &ops[1], width),
+            Node::Ite(kind, _, _, _, _) => z3_cmp(*kind, &ops[0], &ops[1]).ite(&ops[2], &ops[3]),
+        })
+    }
+
+    /// Which nodes the `roots` depend on.
+    fn live(&self, roots: &[NodeId]) -> Vec<bool> {
+        let mut live = vec![false; self.nodes.len()];
+        for root in roots {
+            live[root.0] = true;
+        }
+
+        for idx in (0..self.nodes.len()).rev() {
+            if !live[idx] {
+                continue;
+            }
+
+            for op in self.nodes[idx].operands() {
+                live[op.0] = true;
+            }
+        }
+
+        live
+    }
+}
+
+impl AnswerDag {
+    /// Print `root` as straight-line code: every operator gets bound
+    /// to a temporary `tN`, followed by the final result.
+    pub fn straight_line(&self, root: NodeId) -> StraightLine<'_> {
+        StraightLine { dag: self, root }
+    }
+}
+
+/// See [AnswerDag::straight_line].
+pub struct StraightLine<'a> {
+    dag: &'a AnswerDag,
+    root: NodeId,
+}
+
+impl fmt::Display for StraightLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let live = self.dag.live(&[self.root]);
+        let mut names = HashMap::new();
+        let name = |names: &HashMap<NodeId, String>, id: NodeId| {
+            let operand = match self.dag.node(id) {
+                Node::Variable(v) => v.clone(),
+                _ => Value::Arg(names[&id].clone()),
+            };
+
+            Rc::new(Expr::Variable(operand))
+        };
+
+        for idx in (0..=self.root.0).filter(|x| live[*x]) {
+            let id = NodeId(idx);
+            // The operators are printed with the syntax of `AnswerExpr`
+            let expr = match self.dag.node(id) {
+                Node::Variable(_) => continue,
+                Node::Unop(kind, e) => Expr::Unop(*kind, name(&names, *e)),
+                Node::Binop(kind, l, r) => Expr::Binop(*kind, name(&names, *l), name(&names, *r)),
+                Node::Ite(kind, l, r, t, e) => Expr::Ite(
+                    BoolExpr::Cmp(*kind, name(&names, *l), name(&names, *r)),
+                    name(&names, *t),
+                    name(&names, *e),
+                ),
+            };
+            let tmp = format!("t{}", names.len());
+
+            writeln!(f, "{tmp} = {expr}")?;
+            names.insert(id, tmp);
+        }
+
+        write!(f, "{}", name(&names, self.root))
+    }
+}
@@ -0,0 +1,127 @@
+use std::cell::{Cell, RefCell};
+
+use crate::expr::{Expr, Value, Variable};
+use crate::synth::circuit_enum::{Circuit, CircuitComponent};
+
+/// A leaf, which can be drawn by [Expr::to_dot].
+pub trait DotLeaf {
+    fn dot_label(&self) -> String;
+}
+
+impl DotLeaf for Variable {
+    fn dot_label(&self) -> String {
+        match self {
+            Variable::UnknownConst => "?".to_string(),
+            Variable::Const(x) => x.to_string(),
+            Variable::Argument(idx) => format!("arg{idx}"),
+        }
+    }
+}
+
+impl DotLeaf for Value {
+    fn dot_label(&self) -> String {
+        match self {
+            Value::Arg(x) => x.clone(),
+            Value::Const(x) => x.to_string(),
+        }
+    }
+}
+
+/// Quote `label` as a DOT string.
+fn quote(label: &str) -> String {
+    format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn graph(name: &str, attrs: &[&str], body: Vec<String>) -> String {
+    let mut res = format!("digraph {name} {{\n");
+
+    for line in attrs.iter().copied().chain(body.iter().map(String::as_str)) {
+        res.push_str("    ");
+        res.push_str(line);
+        res.push_str(";\n");
+    }
+    res.push_str("}\n");
+
+    res
+}
+
+impl<V: DotLeaf> Expr<V> {
+    /// Draw the expression tree as a Graphviz digraph. The operators
+    /// point to their operands in order, the leaves are boxes. The
+    /// branches of an if-then-else are labelled.
+    pub fn to_dot(&self) -> String {
+        let body = RefCell::new(Vec::new());
+        let count = Cell::new(0);
+        let node = |label: &str, shape: Option<&str>, operands: &[(usize, &str)]| {
+            let mut body = body.borrow_mut();
+            let id = count.replace(count.get() + 1);
+            let shape = shape.map_or(String::new(), |x| format!(", shape={x}"));
+
+            body.push(format!("n{id} [label={}{shape}]", quote(label)));
+            for (op, edge) in operands {
+                body.push(match *edge {
+                    "" => format!("n{id} -> n{op}"),
+                    edge => format!("n{id} -> n{op} [label={}]", quote(edge)),
+                });
+            }
+
+            id
+        };
+
+        self.walk_expr(
+            &mut |v| node(&v.dot_label(), Some("box"), &[]),
+            &mut |unop_kind, e| node(unop_kind.symbol(), None, &[(e, "")]),
+            &mut |binop_kind, l, r| node(binop_kind.symbol(), None, &[(l, ""), (r, "")]),
+            &mut |cmp_kind, l, r| node(cmp_kind.symbol(), None, &[(l, ""), (r, "")]),
+            &mut |c, t, e| node("?:", Some("diamond"), &[(c, "if"), (t, "then"), (e, "else")]),
+            &mut |x| x,
+        );
+
+        graph("expr", &["ordering=out"], body.into_inner())
+    }
+}
+
+impl CircuitComponent {
+    /// The operator with its constants filled in.
+    /// The inputs are named `i0`, `i1` and so on.
+    fn dot_label(&self) -> String {
+        let mut constants = self.constants.iter();
+        let op = self.template.to_ans(|v| match v {
+            Variable::UnknownConst => Value::Const(*constants.next().unwrap()),
+            Variable::Const(x) => Value::Const(x),
+            Variable::Argument(idx) => Value::Arg(format!("i{idx}")),
+        });
+
+        format!("{}: {op}", self.output)
+    }
+}
+
+impl Circuit {
+    /// Draw the circuit as a Graphviz digraph. Every location is a node,
+    /// labelled with its number and the argument or the component placed
+    /// there. The wires go from a location to the inputs using it. The
+    /// components, which no result depends on, are dashed.
+    pub fn to_dot(&self) -> String {
+        let live = self.live();
+
+        let mut body = Vec::new();
+        for (idx, loc) in self.args.iter().enumerate() {
+            body.push(format!("l{loc} [label={}, shape=box]", quote(&format!("{loc}: arg{idx}"))));
+        }
+        for component in &self.components {
+            let label = quote(&component.dot_label());
+            let style = if live[component.output] { "" } else { ", style=dashed" };
+
+            body.push(format!("l{} [label={label}{style}]", component.output));
+            for (idx, inp) in component.inputs.iter().enumerate() {
+                body.push(format!("l{inp} -> l{} [label=\"i{idx}\"{style}]", component.output));
+            }
+        }
+        for (idx, result) in self.results.iter().enumerate() {
+            body.push(format!("res{idx} [shape=doublecircle]"));
+            body.push(format!("l{result} -> res{idx}"));
+        }
+
+        graph("circuit", &["rankdir=LR"], body)
+    }
+}
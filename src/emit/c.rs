@@ -0,0 +1,273 @@
+use crate::expr::{AnswerExpr, BinopKind, CmpKind, ExprVal, Signedness, UnopKind};
+
+use super::{lower, Target, Width};
+
+/// Emits C99 (plus `unsigned __int128` and the GCC/Clang builtins for
+/// widths above 64 and the intrinsics). All the arithmetic is done on
+/// unsigned types at least as wide as `unsigned int`, so nothing gets
+/// promoted to a signed type, every shift amount is checked and the
+/// signed operations are spelled out in terms of unsigned ones.
+struct C(Width);
+
+impl C {
+    fn ty(&self) -> &'static str {
+        match self.0.container {
+            32 => "uint32_t",
+            64 => "uint64_t",
+            _ => "unsigned __int128",
+        }
+    }
+
+    /// The type of the arguments and the result
+    fn param_ty(&self) -> String {
+        let prefix = match self.0.signedness {
+            Signedness::Signed => "int",
+            Signedness::Unsigned => "uint",
+        };
+
+        match (self.0.width, self.0.signedness) {
+            (8 | 16 | 32 | 64, _) => format!("{prefix}{}_t", self.0.width),
+            (_, Signedness::Unsigned) => self.ty().to_string(),
+            (_, Signedness::Signed) if self.0.container == 128 => "__int128".to_string(),
+            (_, Signedness::Signed) => format!("int{}_t", self.0.container),
+        }
+    }
+
+    fn literal(&self, bits: u128) -> String {
+        let (hi, lo) = ((bits >> 64) as u64, bits as u64);
+
+        match self.0.container {
+            32 => format!("{lo:#x}u"),
+            64 => format!("{lo:#x}ull"),
+            _ if hi == 0 => format!("(({}){lo:#x}ull)", self.ty()),
+            _ => format!("((({}){hi:#x}ull << 64) | {lo:#x}ull)", self.ty()),
+        }
+    }
+
+    fn masked(&self, expr: String) -> String {
+        if self.0.is_exact() {
+            return expr;
+        }
+
+        format!("({expr}) & {}", self.literal(self.0.mask()))
+    }
+
+    fn builtin(&self, name: &str, e: &str) -> String {
+        match self.0.container {
+            32 => format!("__builtin_{name}({e})"),
+            _ => format!("__builtin_{name}ll({e})"),
+        }
+    }
+
+    /// `l - r`, where `r` may be a shift, which binds looser
+    fn sub(l: &str, r: &str) -> String {
+        match r.contains(' ') {
+            true => format!("{l} - ({r})"),
+            false => format!("{l} - {r}"),
+        }
+    }
+
+    fn lo(e: &str) -> String {
+        format!("(uint64_t){e}")
+    }
+
+    fn hi(e: &str) -> String {
+        format!("(uint64_t)({e} >> 64)")
+    }
+}
+
+impl Target for C {
+    fn arg(&self, name: &str) -> String {
+        format!("u_{name}")
+    }
+
+    fn constant(&self, val: ExprVal) -> String {
+        self.literal(self.0.bits(val))
+    }
+
+    fn bind(&self, name: &str, expr: &str) -> String {
+        format!("const {} {name} = {expr};", self.ty())
+    }
+
+    fn unop(&self, kind: UnopKind, e: &str, _tmp: &dyn Fn(String) -> String) -> String {
+        let (width, container) = (self.0.width, self.0.container);
+        let ty = self.ty();
+
+        match kind {
+            UnopKind::Not => self.masked(format!("~{e}")),
+            UnopKind::Negate => self.masked(format!("-{e}")),
+            UnopKind::Popcnt if container == 128 => format!(
+                "({ty})(__builtin_popcountll({}) + __builtin_popcountll({}))",
+                Self::lo(e),
+                Self::hi(e),
+            ),
+            UnopKind::Popcnt => format!("({ty}){}", self.builtin("popcount", e)),
+            UnopKind::Clz => {
+                let clz = match container {
+                    128 => format!(
+                        "({hi} != 0 ? __builtin_clzll({hi}) : 64 + __builtin_clzll({lo}))",
+                        hi = Self::hi(e),
+                        lo = Self::lo(e),
+                    ),
+                    _ => self.builtin("clz", e),
+                };
+
+                match container - width {
+                    0 => format!("{e} == 0 ? {width} : ({ty}){clz}"),
+                    pad => format!("{e} == 0 ? {width} : ({ty})({clz} - {pad})"),
+                }
+            },
+            UnopKind::Ctz => {
+                let ctz = match container {
+                    128 => format!(
+                        "({lo} != 0 ? __builtin_ctzll({lo}) : 64 + __builtin_ctzll({hi}))",
+                        hi = Self::hi(e),
+                        lo = Self::lo(e),
+                    ),
+                    _ => self.builtin("ctz", e),
+                };
+
+                format!("{e} == 0 ? {width} : ({ty}){ctz}")
+            },
+            UnopKind::Bswap => {
+                let swapped = match container {
+                    32 => format!("__builtin_bswap32({e})"),
+                    64 => format!("__builtin_bswap64({e})"),
+                    _ => format!(
+                        "((({ty})__builtin_bswap64({}) << 64) | __builtin_bswap64({}))",
+                        Self::lo(e),
+                        Self::hi(e),
+                    ),
+                };
+
+                match container - self.0.bytes() * 8 {
+                    0 => self.masked(swapped),
+                    pad => self.masked(format!("{swapped} >> {pad}")),
+                }
+            },
+        }
+    }
+
+    fn binop(
+        &self,
+        kind: BinopKind,
+        l: &str,
+        r: &str,
+        tmp: &dyn Fn(String) -> String,
+    ) -> String {
+        let width = self.0.width;
+        let mask = self.literal(self.0.mask());
+        // The sign and the magnitude of the operands
+        let sign_magnitude = || {
+            let (l_sign, r_sign) = (
+                tmp(format!("{l} >> {}", width - 1)),
+                tmp(format!("{r} >> {}", width - 1)),
+            );
+            let (l_mag, r_mag) = (
+                tmp(format!("{l_sign} ? {} : {l}", self.masked(format!("-{l}")))),
+                tmp(format!("{r_sign} ? {} : {r}", self.masked(format!("-{r}")))),
+            );
+
+            (l_sign, r_sign, l_mag, r_mag)
+        };
+
+        match kind {
+            BinopKind::And => format!("{l} & {r}"),
+            BinopKind::Or => format!("{l} | {r}"),
+            BinopKind::Xor => format!("{l} ^ {r}"),
+            BinopKind::Plus => self.masked(format!("{l} + {r}")),
+            BinopKind::Minus => self.masked(format!("{l} - {r}")),
+            BinopKind::Mul => self.masked(format!("{l} * {r}")),
+            BinopKind::Shl => {
+                format!("{r} < {width} ? {} : 0", self.masked(format!("{l} << {r}")))
+            },
+            BinopKind::ShrL => format!("{r} < {width} ? {l} >> {r} : 0"),
+            BinopKind::ShrA => {
+                let amount = tmp(format!("{r} < {width} ? {r} : {}", width - 1));
+
+                self.masked(self.0.ashr(l, &amount, &|x| self.literal(x), &Self::sub))
+            },
+            BinopKind::UDiv => format!("{r} == 0 ? {mask} : {l} / {r}"),
+            BinopKind::URem => format!("{r} == 0 ? {l} : {l} % {r}"),
+            BinopKind::SDiv => {
+                let (l_sign, r_sign, l_mag, r_mag) = sign_magnitude();
+
+                format!(
+                    "{r} == 0 ? ({l_sign} ? 1 : {mask}) : ({l_sign} != {r_sign} ? {} : {l_mag} / {r_mag})",
+                    self.masked(format!("-({l_mag} / {r_mag})")),
+                )
+            },
+            BinopKind::SRem => {
+                let (l_sign, _, l_mag, r_mag) = sign_magnitude();
+
+                format!(
+                    "{r} == 0 ? {l} : ({l_sign} ? {} : {l_mag} % {r_mag})",
+                    self.masked(format!("-({l_mag} % {r_mag})")),
+                )
+            },
+            BinopKind::Rotl | BinopKind::Rotr => {
+                let amount = tmp(format!("{r} % {width}"));
+                let (fwd, back) = match kind {
+                    BinopKind::Rotl => ("<<", ">>"),
+                    _ => (">>", "<<"),
+                };
+
+                format!(
+                    "{amount} == 0 ? {l} : {}",
+                    self.masked(format!("({l} {fwd} {amount}) | ({l} {back} ({width} - {amount}))")),
+                )
+            },
+        }
+    }
+
+    fn cmp(&self, kind: CmpKind, l: &str, r: &str, _tmp: &dyn Fn(String) -> String) -> String {
+        match kind {
+            CmpKind::Eq => format!("{l} == {r}"),
+            CmpKind::Ult => format!("{l} < {r}"),
+            CmpKind::Slt => self.0.slt(l, r, &|x| self.literal(x)),
+        }
+    }
+
+    fn ite(&self, cond: &str, t: &str, e: &str) -> String {
+        format!("{cond} ? {t} : {e}")
+    }
+}
+
+/// Emit `answer` as a C function `name`, taking `args`. The arguments
+/// and the result are `width`-bit integers of the given signedness. For
+/// the widths without a matching `stdint.h` type the next wider type is
+/// used and only the lowest `width` bits of the arguments are read. The
+/// parameters are the arguments prefixed with `a_`, which are unpacked
+/// into the `u_` ones the body computes with.
+pub fn emit_c(
+    answer: &AnswerExpr,
+    name: &str,
+    args: &[String],
+    width: u32,
+    signedness: Signedness,
+) -> String {
+    let target = C(Width::new(width, signedness, &[32, 64, 128]));
+    let (ty, param_ty) = (target.ty(), target.param_ty());
+    let (body, res) = lower(&target, answer);
+
+    let params = args.iter()
+        .map(|x| format!("{param_ty} a_{x}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let unpack = args.iter()
+        .map(|x| format!("const {ty} {} = {};", target.arg(x), target.masked(format!("({ty})a_{x}"))));
+    let res = match signedness {
+        Signedness::Signed if !target.0.is_exact() => {
+            format!("({param_ty})({})", target.0.sign_extend(&res, &|x| target.literal(x), &C::sub))
+        },
+        _ => format!("({param_ty}){res}"),
+    };
+
+    let mut out = format!("#include <stdint.h>\n\n{param_ty} {name}({params}) {{\n");
+    for line in unpack.chain(body) {
+        out += &format!("    {line}\n");
+    }
+    out += &format!("    return {res};\n}}\n");
+
+    out
+}
@@ -0,0 +1,261 @@
+use std::{cell::RefCell, collections::BTreeSet};
+
+use crate::expr::{AnswerExpr, BinopKind, CmpKind, ExprVal, Signedness, UnopKind};
+
+use super::{lower, Target, Width};
+
+/// Emits textual LLVM IR. The instructions, which would produce poison
+/// or be undefined for some inputs (oversized shifts, division by zero,
+/// signed division overflow) get their operands guarded with `select`.
+struct Llvm {
+    width: Width,
+    /// The intrinsics the function calls
+    declarations: RefCell<BTreeSet<String>>,
+}
+
+impl Llvm {
+    fn ty(&self) -> String {
+        format!("i{}", self.width.width)
+    }
+
+    /// Call the overload of an intrinsic for `ty`.
+    fn intrinsic(&self, name: &str, ty: &str, args: &[String]) -> String {
+        let params = args.iter()
+            .map(|_| ty)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let args = args.iter()
+            .map(|x| format!("{ty} {x}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        self.declarations.borrow_mut()
+            .insert(format!("declare {ty} @llvm.{name}.{ty}({params})"));
+
+        format!("call {ty} @llvm.{name}.{ty}({args})")
+    }
+
+    /// Call `ctlz` or `cttz`, which give the width on zero.
+    fn call_zero_defined(&self, name: &str, e: &str) -> String {
+        let ty = self.ty();
+
+        self.declarations.borrow_mut()
+            .insert(format!("declare {ty} @llvm.{name}.{ty}({ty}, i1)"));
+
+        format!("call {ty} @llvm.{name}.{ty}({ty} {e}, i1 false)")
+    }
+
+    /// `bswap` only exists for whole 16-bit words, so the rest of the
+    /// widths get zero-extended to the next one and shifted back.
+    fn bswap(&self, e: &str, tmp: &dyn Fn(String) -> String) -> String {
+        let (width, ty) = (self.width.width, self.ty());
+        let bytes = self.width.bytes();
+
+        if bytes == 1 {
+            return format!("bitcast {ty} {e} to {ty}");
+        }
+
+        if width % 16 == 0 {
+            return self.intrinsic("bswap", &ty, &[e.to_string()]);
+        }
+
+        let wide = bytes.div_ceil(2) * 16;
+        let wide_ty = format!("i{wide}");
+        let extended = tmp(format!("zext {ty} {e} to {wide_ty}"));
+        let swapped = tmp(self.intrinsic("bswap", &wide_ty, &[extended]));
+        let shifted = tmp(format!("lshr {wide_ty} {swapped}, {}", wide - bytes * 8));
+
+        format!("trunc {wide_ty} {shifted} to {ty}")
+    }
+}
+
+impl Target for Llvm {
+    fn temp(&self, idx: usize) -> String {
+        format!("%t{idx}")
+    }
+
+    /// Prefixed, so that the arguments can't clash with the temporaries
+    fn arg(&self, name: &str) -> String {
+        format!("%a.{name}")
+    }
+
+    fn constant(&self, val: ExprVal) -> String {
+        match self.width.width {
+            1 if self.width.bits(val) == 0 => "false".to_string(),
+            1 => "true".to_string(),
+            width => Signedness::Signed.truncate(val, width).to_string(),
+        }
+    }
+
+    fn bind(&self, name: &str, expr: &str) -> String {
+        format!("{name} = {expr}")
+    }
+
+    fn unop(&self, kind: UnopKind, e: &str, tmp: &dyn Fn(String) -> String) -> String {
+        let ty = self.ty();
+
+        match kind {
+            UnopKind::Not => format!("xor {ty} {e}, {}", self.constant(-1)),
+            UnopKind::Negate => format!("sub {ty} {}, {e}", self.constant(0)),
+            UnopKind::Popcnt => self.intrinsic("ctpop", &ty, &[e.to_string()]),
+            UnopKind::Clz => self.call_zero_defined("ctlz", e),
+            UnopKind::Ctz => self.call_zero_defined("cttz", e),
+            UnopKind::Bswap => self.bswap(e, tmp),
+        }
+    }
+
+    fn binop(
+        &self,
+        kind: BinopKind,
+        l: &str,
+        r: &str,
+        tmp: &dyn Fn(String) -> String,
+    ) -> String {
+        let (width, ty) = (self.width.width, self.ty());
+        let (zero, one, ones) = (self.constant(0), self.constant(1), self.constant(-1));
+        let fits = || tmp(format!("icmp ult {ty} {r}, {}", self.constant(width as ExprVal)));
+        let is_zero = || tmp(format!("icmp eq {ty} {r}, {zero}"));
+        // A divisor, for which neither `sdiv` nor `srem` are undefined.
+        // Both results get replaced for the zero anyway, and the overflowing
+        // `MIN / -1` gives the same result as `MIN / 1`.
+        let safe_divisor = |is_zero: &str| {
+            let min = self.constant(self.width.sign_bit() as ExprVal);
+            let overflow = [
+                tmp(format!("icmp eq {ty} {l}, {min}")),
+                tmp(format!("icmp eq {ty} {r}, {ones}")),
+            ];
+            let overflow = tmp(format!("and i1 {}, {}", overflow[0], overflow[1]));
+            let bad = tmp(format!("or i1 {is_zero}, {overflow}"));
+
+            tmp(format!("select i1 {bad}, {ty} {one}, {ty} {r}"))
+        };
+
+        match kind {
+            BinopKind::And => format!("and {ty} {l}, {r}"),
+            BinopKind::Or => format!("or {ty} {l}, {r}"),
+            BinopKind::Xor => format!("xor {ty} {l}, {r}"),
+            BinopKind::Plus => format!("add {ty} {l}, {r}"),
+            BinopKind::Minus => format!("sub {ty} {l}, {r}"),
+            BinopKind::Mul => format!("mul {ty} {l}, {r}"),
+            BinopKind::Shl | BinopKind::ShrL => {
+                let op = match kind {
+                    BinopKind::Shl => "shl",
+                    _ => "lshr",
+                };
+                let (fits, shifted) = (fits(), tmp(format!("{op} {ty} {l}, {r}")));
+
+                format!("select i1 {fits}, {ty} {shifted}, {ty} {zero}")
+            },
+            BinopKind::ShrA => {
+                let fits = fits();
+                let amount = tmp(format!(
+                    "select i1 {fits}, {ty} {r}, {ty} {}",
+                    self.constant(width as ExprVal - 1),
+                ));
+
+                format!("ashr {ty} {l}, {amount}")
+            },
+            BinopKind::UDiv | BinopKind::URem => {
+                let is_zero = is_zero();
+                let divisor = tmp(format!("select i1 {is_zero}, {ty} {one}, {ty} {r}"));
+                let (op, on_zero) = match kind {
+                    BinopKind::UDiv => ("udiv", ones.as_str()),
+                    _ => ("urem", l),
+                };
+                let res = tmp(format!("{op} {ty} {l}, {divisor}"));
+
+                format!("select i1 {is_zero}, {ty} {on_zero}, {ty} {res}")
+            },
+            // The only non-zero 1-bit divisor is -1, which would overflow
+            BinopKind::SDiv if width == 1 => format!("select i1 {}, {ty} true, {ty} {l}", is_zero()),
+            BinopKind::SRem if width == 1 => format!("and {ty} {l}, {}", is_zero()),
+            BinopKind::SDiv => {
+                let is_zero = is_zero();
+                let res = tmp(format!("sdiv {ty} {l}, {}", safe_divisor(&is_zero)));
+                let is_neg = tmp(format!("icmp slt {ty} {l}, {zero}"));
+                let on_zero = tmp(format!("select i1 {is_neg}, {ty} {one}, {ty} {ones}"));
+
+                format!("select i1 {is_zero}, {ty} {on_zero}, {ty} {res}")
+            },
+            BinopKind::SRem => {
+                let is_zero = is_zero();
+                let res = tmp(format!("srem {ty} {l}, {}", safe_divisor(&is_zero)));
+
+                format!("select i1 {is_zero}, {ty} {l}, {ty} {res}")
+            },
+            BinopKind::Rotl => self.intrinsic("fshl", &ty, &[l.to_string(), l.to_string(), r.to_string()]),
+            BinopKind::Rotr => self.intrinsic("fshr", &ty, &[l.to_string(), l.to_string(), r.to_string()]),
+        }
+    }
+
+    fn cmp(&self, kind: CmpKind, l: &str, r: &str, tmp: &dyn Fn(String) -> String) -> String {
+        let pred = match kind {
+            CmpKind::Eq => "eq",
+            CmpKind::Slt => "slt",
+            CmpKind::Ult => "ult",
+        };
+
+        tmp(format!("icmp {pred} {} {l}, {r}", self.ty()))
+    }
+
+    fn ite(&self, cond: &str, t: &str, e: &str) -> String {
+        let ty = self.ty();
+
+        format!("select i1 {cond}, {ty} {t}, {ty} {e}")
+    }
+}
+
+/// Emit `answer` as an LLVM IR function `@name`, taking `args`. All the
+/// values are `iN`, where `N` is `width`. The shared subexpressions are
+/// computed once, so the straight-line programs recovered from the
+/// circuit synthesizer keep their shape.
+pub fn emit_llvm(
+    answer: &AnswerExpr,
+    name: &str,
+    args: &[String],
+    width: u32,
+) -> String {
+    emit_llvm_module(&[(name, answer)], args, width)
+}
+
+/// Emit a module of functions, like [emit_llvm] does for one.
+/// The intrinsics they call are declared once.
+pub fn emit_llvm_module(
+    funcs: &[(&str, &AnswerExpr)],
+    args: &[String],
+    width: u32,
+) -> String {
+    let target = Llvm {
+        width: Width::new(width, Signedness::Unsigned, &[width]),
+        declarations: RefCell::new(BTreeSet::new()),
+    };
+    let ty = target.ty();
+
+    let params = args.iter()
+        .map(|x| format!("{ty} {}", target.arg(x)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut out = String::new();
+    for (name, answer) in funcs {
+        let (body, res) = lower(&target, answer);
+
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out += &format!("define {ty} @{name}({params}) {{\n");
+        for line in body {
+            out += &format!("  {line}\n");
+        }
+        out += &format!("  ret {ty} {res}\n}}\n");
+    }
+
+    for decl in target.declarations.into_inner() {
+        out += &format!("\n{decl}");
+    }
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+
+    out
+}
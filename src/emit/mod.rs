@@ -0,0 +1,164 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::expr::{AnswerExpr, BinopKind, CmpKind, ExprVal, Signedness, UnopKind, Value};
+
+pub mod c;
+pub mod llvm;
+pub mod python;
+pub mod rust;
+pub mod smt;
+
+/// A language the answers can be lowered into. The lowering is
+/// straight-line: every operator gets bound to a fresh temporary,
+/// so the operands a target receives are always either temporaries,
+/// arguments or literals and can be repeated freely. Identical
+/// computations are bound only once.
+trait Target {
+    /// The name of the `idx`-th temporary.
+    fn temp(&self, idx: usize) -> String {
+        format!("t{idx}")
+    }
+
+    /// The operand for the argument `name`.
+    fn arg(&self, name: &str) -> String;
+
+    fn constant(&self, val: ExprVal) -> String;
+
+    /// A statement binding `expr` to a new temporary `name`.
+    fn bind(&self, name: &str, expr: &str) -> String;
+
+    /// Lower a unary operator. Helper values can be bound to
+    /// temporaries with `tmp`, which returns their name.
+    fn unop(&self, kind: UnopKind, e: &str, tmp: &dyn Fn(String) -> String) -> String;
+
+    /// Lower a binary operator. See [Target::unop].
+    fn binop(
+        &self,
+        kind: BinopKind,
+        l: &str,
+        r: &str,
+        tmp: &dyn Fn(String) -> String,
+    ) -> String;
+
+    /// Lower a comparison into a boolean expression. See [Target::unop].
+    fn cmp(&self, kind: CmpKind, l: &str, r: &str, tmp: &dyn Fn(String) -> String) -> String;
+
+    fn ite(&self, cond: &str, t: &str, e: &str) -> String;
+}
+
+/// Lower `expr` into a list of statements. Returns them
+/// together with the operand holding the result.
+fn lower<T: Target>(target: &T, expr: &AnswerExpr) -> (Vec<String>, String) {
+    let body = RefCell::new(Vec::new());
+    let bound = RefCell::new(HashMap::new());
+    let tmp = |expr: String| {
+        let mut body = body.borrow_mut();
+        let name = bound.borrow_mut()
+            .entry(expr)
+            .or_insert_with_key(|expr| {
+                let name = target.temp(body.len());
+                body.push(target.bind(&name, expr));
+
+                name
+            })
+            .clone();
+
+        name
+    };
+
+    let res = expr.walk_expr(
+        &mut |v| match v {
+            Value::Arg(x) => target.arg(x),
+            Value::Const(x) => target.constant(*x),
+        },
+        &mut |unop_kind, e: String| tmp(target.unop(unop_kind, &e, &tmp)),
+        &mut |binop_kind, l: String, r: String| tmp(target.binop(binop_kind, &l, &r, &tmp)),
+        &mut |cmp_kind, l: String, r: String| target.cmp(cmp_kind, &l, &r, &tmp),
+        &mut |c: String, t: String, e: String| tmp(target.ite(&c, &t, &e)),
+        &mut |x| x,
+    );
+
+    (body.into_inner(), res)
+}
+
+/// The facts about the bit width all the targets need.
+struct Width {
+    width: u32,
+    signedness: Signedness,
+    /// Bits of the machine type the values are stored in
+    container: u32,
+}
+
+impl Width {
+    /// Pick the narrowest of `containers` that fits `width` bits.
+    fn new(width: u32, signedness: Signedness, containers: &[u32]) -> Self {
+        let container = containers.iter()
+            .copied()
+            .find(|x| *x >= width)
+            .unwrap();
+
+        Self { width, signedness, container }
+    }
+
+    /// The bits of `val` within the width.
+    fn bits(&self, val: ExprVal) -> u128 {
+        Signedness::Unsigned.truncate(val, self.width) as u128
+    }
+
+    fn mask(&self) -> u128 {
+        self.bits(-1)
+    }
+
+    fn sign_bit(&self) -> u128 {
+        1 << (self.width - 1)
+    }
+
+    /// The width of the values fits the container exactly,
+    /// so no masking is needed.
+    fn is_exact(&self) -> bool {
+        self.width == self.container
+    }
+
+    /// Byte count of `bswap`.
+    fn bytes(&self) -> u32 {
+        self.width.div_ceil(8)
+    }
+
+    // Flipping the sign bit maps the signed values onto the unsigned
+    // ones, preserving the order. This lets the targets, which keep the
+    // values unsigned, compare and shift them as signed ones. `literal`
+    // spells a constant of the target, `sub` a wrapping subtraction.
+
+    /// The signed `l < r` on the unsigned values.
+    fn slt(&self, l: &str, r: &str, literal: &dyn Fn(u128) -> String) -> String {
+        let sign_bit = literal(self.sign_bit());
+
+        format!("({l} ^ {sign_bit}) < ({r} ^ {sign_bit})")
+    }
+
+    /// The arithmetic `l >> amount` on the unsigned values. The `amount`
+    /// must be below the width. The result still has to be masked.
+    fn ashr(
+        &self,
+        l: &str,
+        amount: &str,
+        literal: &dyn Fn(u128) -> String,
+        sub: &dyn Fn(&str, &str) -> String,
+    ) -> String {
+        let sign_bit = literal(self.sign_bit());
+
+        sub(&format!("(({l} ^ {sign_bit}) >> {amount})"), &format!("{sign_bit} >> {amount}"))
+    }
+
+    /// Sign-extend the value `e` from the width to the whole container.
+    fn sign_extend(
+        &self,
+        e: &str,
+        literal: &dyn Fn(u128) -> String,
+        sub: &dyn Fn(&str, &str) -> String,
+    ) -> String {
+        let sign_bit = literal(self.sign_bit());
+
+        sub(&format!("({e} ^ {sign_bit})"), &sign_bit)
+    }
+}
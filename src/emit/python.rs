@@ -0,0 +1,160 @@
+use crate::expr::{AnswerExpr, BinopKind, CmpKind, ExprVal, Signedness, UnopKind};
+
+use super::{lower, Target, Width};
+
+/// Emits Python 3. The integers are unbounded, so every value is
+/// kept non-negative and masked down to the width explicitly.
+struct Python(Width);
+
+impl Python {
+    fn literal(bits: u128) -> String {
+        format!("{bits:#x}")
+    }
+
+    fn masked(&self, expr: String) -> String {
+        format!("({expr}) & {}", Self::literal(self.0.mask()))
+    }
+
+    /// Sign-extend `e` into a Python integer
+    fn signed(&self, e: &str) -> String {
+        self.0.sign_extend(e, &Self::literal, &|l, r| format!("{l} - {r}"))
+    }
+
+    /// Literals can't be followed by a method call
+    fn receiver(e: &str) -> String {
+        match e.starts_with(|c: char| c.is_ascii_digit()) {
+            true => format!("({e})"),
+            false => e.to_string(),
+        }
+    }
+}
+
+impl Target for Python {
+    /// Prefixed, so that the arguments can't clash with the
+    /// temporaries or the keywords
+    fn arg(&self, name: &str) -> String {
+        format!("u_{name}")
+    }
+
+    fn constant(&self, val: ExprVal) -> String {
+        Self::literal(self.0.bits(val))
+    }
+
+    fn bind(&self, name: &str, expr: &str) -> String {
+        format!("{name} = {expr}")
+    }
+
+    fn unop(&self, kind: UnopKind, e: &str, _tmp: &dyn Fn(String) -> String) -> String {
+        let width = self.0.width;
+
+        match kind {
+            UnopKind::Not => self.masked(format!("~{e}")),
+            UnopKind::Negate => self.masked(format!("-{e}")),
+            UnopKind::Popcnt => format!("bin({e}).count(\"1\")"),
+            UnopKind::Clz => format!("{width} - {}.bit_length()", Self::receiver(e)),
+            UnopKind::Ctz => format!("({e} & -{e}).bit_length() - 1 if {e} else {width}"),
+            UnopKind::Bswap => self.masked(format!(
+                "int.from_bytes({}.to_bytes({}, \"little\"), \"big\")",
+                Self::receiver(e),
+                self.0.bytes(),
+            )),
+        }
+    }
+
+    fn binop(
+        &self,
+        kind: BinopKind,
+        l: &str,
+        r: &str,
+        tmp: &dyn Fn(String) -> String,
+    ) -> String {
+        let width = self.0.width;
+        let mask = Self::literal(self.0.mask());
+
+        match kind {
+            BinopKind::And => format!("{l} & {r}"),
+            BinopKind::Or => format!("{l} | {r}"),
+            BinopKind::Xor => format!("{l} ^ {r}"),
+            BinopKind::Plus => self.masked(format!("{l} + {r}")),
+            BinopKind::Minus => self.masked(format!("{l} - {r}")),
+            BinopKind::Mul => self.masked(format!("{l} * {r}")),
+            BinopKind::Shl => format!("{} if {r} < {width} else 0", self.masked(format!("{l} << {r}"))),
+            BinopKind::ShrL => format!("{l} >> {r}"),
+            BinopKind::ShrA => self.masked(format!("({}) >> {r}", self.signed(l))),
+            BinopKind::UDiv => format!("{l} // {r} if {r} else {mask}"),
+            BinopKind::URem => format!("{l} % {r} if {r} else {l}"),
+            // Python rounds the division towards negative infinity,
+            // so the quotient is computed on the magnitudes.
+            BinopKind::SDiv => {
+                let (l_val, r_val) = (tmp(self.signed(l)), tmp(self.signed(r)));
+
+                format!(
+                    "{} if {r} else (1 if {l_val} < 0 else {mask})",
+                    self.masked(format!(
+                        "abs({l_val}) // abs({r_val}) * (-1 if ({l_val} < 0) != ({r_val} < 0) else 1)",
+                    )),
+                )
+            },
+            BinopKind::SRem => {
+                let (l_val, r_val) = (tmp(self.signed(l)), tmp(self.signed(r)));
+
+                format!(
+                    "{} if {r} else {l}",
+                    self.masked(format!("abs({l_val}) % abs({r_val}) * (-1 if {l_val} < 0 else 1)")),
+                )
+            },
+            BinopKind::Rotl => self.masked(format!(
+                "({l} << ({r} % {width})) | ({l} >> ({width} - {r} % {width}))",
+            )),
+            BinopKind::Rotr => self.masked(format!(
+                "({l} >> ({r} % {width})) | ({l} << ({width} - {r} % {width}))",
+            )),
+        }
+    }
+
+    fn cmp(&self, kind: CmpKind, l: &str, r: &str, _tmp: &dyn Fn(String) -> String) -> String {
+        match kind {
+            CmpKind::Eq => format!("{l} == {r}"),
+            CmpKind::Ult => format!("{l} < {r}"),
+            CmpKind::Slt => format!("{} < {}", self.signed(l), self.signed(r)),
+        }
+    }
+
+    fn ite(&self, cond: &str, t: &str, e: &str) -> String {
+        format!("{t} if {cond} else {e}")
+    }
+}
+
+/// Emit `answer` as a Python function `name`, taking `args`. The arguments
+/// can be any integers, only their lowest `width` bits are read. The result
+/// is in the range of `width`-bit integers of the given signedness. The
+/// parameters are the arguments prefixed with `u_`.
+pub fn emit_python(
+    answer: &AnswerExpr,
+    name: &str,
+    args: &[String],
+    width: u32,
+    signedness: Signedness,
+) -> String {
+    let target = Python(Width::new(width, signedness, &[width]));
+    let (body, res) = lower(&target, answer);
+
+    let mask = Python::literal(target.0.mask());
+    let params = args.iter()
+        .map(|x| target.arg(x))
+        .collect::<Vec<_>>();
+    let unpack = params.iter()
+        .map(|x| format!("{x} &= {mask}"));
+    let res = match signedness {
+        Signedness::Signed => target.signed(&res),
+        Signedness::Unsigned => res,
+    };
+
+    let mut out = format!("def {name}({}):\n", params.join(", "));
+    for line in unpack.chain(body) {
+        out += &format!("    {line}\n");
+    }
+    out += &format!("    return {res}\n");
+
+    out
+}
@@ -0,0 +1,197 @@
+use crate::expr::{AnswerExpr, BinopKind, CmpKind, ExprVal, Signedness, UnopKind};
+
+use super::{lower, Target, Width};
+
+/// Emits Rust. The values are kept in unsigned integers, the arithmetic
+/// uses the `wrapping_*` methods and the operations, which would panic
+/// (oversized shifts, division by zero) are guarded explicitly.
+struct Rust(Width);
+
+impl Rust {
+    fn ty(&self) -> String {
+        format!("u{}", self.0.container)
+    }
+
+    fn signed_ty(&self) -> String {
+        format!("i{}", self.0.container)
+    }
+
+    fn param_ty(&self) -> String {
+        match self.0.signedness {
+            Signedness::Signed => self.signed_ty(),
+            Signedness::Unsigned => self.ty(),
+        }
+    }
+
+    fn literal(&self, bits: u128) -> String {
+        format!("{bits:#x}{}", self.ty())
+    }
+
+    fn masked(&self, expr: String) -> String {
+        if self.0.is_exact() {
+            return expr;
+        }
+
+        format!("({expr}) & {}", self.literal(self.0.mask()))
+    }
+
+    fn sub(l: &str, r: &str) -> String {
+        format!("{l}.wrapping_sub({r})")
+    }
+
+    /// Sign-extend `e` into the signed type
+    fn signed(&self, e: &str) -> String {
+        if self.0.is_exact() {
+            return format!("{e} as {}", self.signed_ty());
+        }
+
+        format!("{} as {}", self.0.sign_extend(e, &|x| self.literal(x), &Self::sub), self.signed_ty())
+    }
+}
+
+impl Target for Rust {
+    /// Prefixed, so that the arguments can't clash with the
+    /// temporaries or the keywords
+    fn arg(&self, name: &str) -> String {
+        format!("u_{name}")
+    }
+
+    fn constant(&self, val: ExprVal) -> String {
+        self.literal(self.0.bits(val))
+    }
+
+    fn bind(&self, name: &str, expr: &str) -> String {
+        format!("let {name}: {} = {expr};", self.ty())
+    }
+
+    fn unop(&self, kind: UnopKind, e: &str, _tmp: &dyn Fn(String) -> String) -> String {
+        let (width, container) = (self.0.width, self.0.container);
+        let ty = self.ty();
+
+        match kind {
+            UnopKind::Not => self.masked(format!("!{e}")),
+            UnopKind::Negate => self.masked(format!("{e}.wrapping_neg()")),
+            UnopKind::Popcnt => format!("{e}.count_ones() as {ty}"),
+            UnopKind::Clz => match container - width {
+                0 => format!("{e}.leading_zeros() as {ty}"),
+                pad => format!("({e}.leading_zeros() - {pad}) as {ty}"),
+            },
+            UnopKind::Ctz => format!("{e}.trailing_zeros().min({width}) as {ty}"),
+            UnopKind::Bswap => match container - self.0.bytes() * 8 {
+                0 => self.masked(format!("{e}.swap_bytes()")),
+                pad => self.masked(format!("{e}.swap_bytes() >> {pad}")),
+            },
+        }
+    }
+
+    fn binop(
+        &self,
+        kind: BinopKind,
+        l: &str,
+        r: &str,
+        tmp: &dyn Fn(String) -> String,
+    ) -> String {
+        let width = self.0.width;
+        let mask = self.literal(self.0.mask());
+
+        match kind {
+            BinopKind::And => format!("{l} & {r}"),
+            BinopKind::Or => format!("{l} | {r}"),
+            BinopKind::Xor => format!("{l} ^ {r}"),
+            BinopKind::Plus => self.masked(format!("{l}.wrapping_add({r})")),
+            BinopKind::Minus => self.masked(format!("{l}.wrapping_sub({r})")),
+            BinopKind::Mul => self.masked(format!("{l}.wrapping_mul({r})")),
+            BinopKind::Shl => {
+                format!("if {r} < {width} {{ {} }} else {{ 0 }}", self.masked(format!("{l} << {r}")))
+            },
+            BinopKind::ShrL => format!("if {r} < {width} {{ {l} >> {r} }} else {{ 0 }}"),
+            BinopKind::ShrA => {
+                let amount = tmp(format!("{r}.min({})", width - 1));
+
+                self.masked(self.0.ashr(l, &amount, &|x| self.literal(x), &Self::sub))
+            },
+            BinopKind::UDiv => format!("{l}.checked_div({r}).unwrap_or({mask})"),
+            BinopKind::URem => format!("{l}.checked_rem({r}).unwrap_or({l})"),
+            BinopKind::SDiv => format!(
+                "if {r} == 0 {{ if {l} >> {} != 0 {{ 1 }} else {{ {mask} }} }} else {{ {} }}",
+                width - 1,
+                self.masked(format!("({}).wrapping_div({}) as {}", self.signed(l), self.signed(r), self.ty())),
+            ),
+            BinopKind::SRem => format!(
+                "if {r} == 0 {{ {l} }} else {{ {} }}",
+                self.masked(format!("({}).wrapping_rem({}) as {}", self.signed(l), self.signed(r), self.ty())),
+            ),
+            BinopKind::Rotl | BinopKind::Rotr if self.0.is_exact() => {
+                let method = match kind {
+                    BinopKind::Rotl => "rotate_left",
+                    _ => "rotate_right",
+                };
+
+                format!("{l}.{method}(({r} % {width}) as u32)")
+            },
+            BinopKind::Rotl | BinopKind::Rotr => {
+                let amount = tmp(format!("{r} % {width}"));
+                let (fwd, back) = match kind {
+                    BinopKind::Rotl => ("<<", ">>"),
+                    _ => (">>", "<<"),
+                };
+
+                format!(
+                    "if {amount} == 0 {{ {l} }} else {{ {} }}",
+                    self.masked(format!("({l} {fwd} {amount}) | ({l} {back} ({width} - {amount}))")),
+                )
+            },
+        }
+    }
+
+    fn cmp(&self, kind: CmpKind, l: &str, r: &str, _tmp: &dyn Fn(String) -> String) -> String {
+        match kind {
+            CmpKind::Eq => format!("{l} == {r}"),
+            CmpKind::Ult => format!("{l} < {r}"),
+            CmpKind::Slt => self.0.slt(l, r, &|x| self.literal(x)),
+        }
+    }
+
+    fn ite(&self, cond: &str, t: &str, e: &str) -> String {
+        format!("if {cond} {{ {t} }} else {{ {e} }}")
+    }
+}
+
+/// Emit `answer` as a Rust function `name`, taking `args`. The arguments
+/// and the result are `width`-bit integers of the given signedness. For
+/// the widths without a matching primitive type the next wider type is
+/// used and only the lowest `width` bits of the arguments are read. The
+/// parameters are the arguments prefixed with `u_`.
+pub fn emit_rust(
+    answer: &AnswerExpr,
+    name: &str,
+    args: &[String],
+    width: u32,
+    signedness: Signedness,
+) -> String {
+    let target = Rust(Width::new(width, signedness, &[8, 16, 32, 64, 128]));
+    let (ty, param_ty) = (target.ty(), target.param_ty());
+    let (body, res) = lower(&target, answer);
+
+    let params = args.iter()
+        .map(|x| format!("{}: {param_ty}", target.arg(x)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    // Unsigned arguments of an exact width are ready to use
+    let unpack = args.iter()
+        .filter(|_| signedness == Signedness::Signed || !target.0.is_exact())
+        .map(|x| target.arg(x))
+        .map(|x| format!("let {x} = {};", target.masked(format!("{x} as {ty}"))));
+    let res = match signedness {
+        Signedness::Signed => target.signed(&res),
+        Signedness::Unsigned => res,
+    };
+
+    let mut out = format!("pub fn {name}({params}) -> {param_ty} {{\n");
+    for line in unpack.chain(body) {
+        out += &format!("    {line}\n");
+    }
+    out += &format!("    {res}\n}}\n");
+
+    out
+}
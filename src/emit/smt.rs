@@ -0,0 +1,38 @@
+use crate::expr::AnswerExpr;
+
+fn sort(width: u32) -> String {
+    format!("(_ BitVec {width})")
+}
+
+/// Emit `answer` as an SMT-LIB2 `define-fun` of `name`, taking `args`.
+/// All the values are `width`-bit vectors. The body is the term the
+/// oracle checks, as printed by z3.
+pub fn emit_smt(
+    answer: &AnswerExpr,
+    name: &str,
+    args: &[String],
+    width: u32,
+) -> String {
+    let ctx = z3::Context::new(&z3::Config::new());
+    let body = answer.to_z3_ans(&ctx, width, |x| z3::ast::BV::new_const(&ctx, x, width))
+        .to_string()
+        .replace('\n', "\n  ");
+
+    let params = args.iter()
+        .map(|x| format!("({x} {})", sort(width)))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!("(define-fun {name} ({params}) {}\n  {body})\n", sort(width))
+}
+
+/// Emit `answer` as the solution to a SyGuS problem synthesizing `name`,
+/// in the format of the competition: the list of the definitions.
+pub fn emit_sygus(
+    answer: &AnswerExpr,
+    name: &str,
+    args: &[String],
+    width: u32,
+) -> String {
+    format!("(\n{})\n", emit_smt(answer, name, args, width))
+}
@@ -1,28 +1,136 @@
 use std::{fmt, rc::Rc};
+use std::hash::{Hash, Hasher};
 
-pub const BITS_PER_VAL: u32 = 32;
-pub type ExprVal = i32;
+use z3::ast::Ast;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// The widest bitvector bitsynth can operate on.
+pub const MAX_BITS_PER_VAL: u32 = 128;
+
+/// A concrete value. Values narrower than [MAX_BITS_PER_VAL] are kept
+/// extended according to the problem's [Signedness], so only the lowest
+/// `width` bits are significant.
+pub type ExprVal = i128;
+
+/// Controls how the bitvectors are interpreted when they are turned
+/// into concrete values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum Signedness {
+    #[default]
+    Signed,
+    Unsigned,
+}
+
+impl Signedness {
+    /// Truncate a value to `width` bits, extending the result back
+    /// with either the sign bit or zeroes.
+    pub fn truncate(self, val: ExprVal, width: u32) -> ExprVal {
+        debug_assert!(0 < width && width <= MAX_BITS_PER_VAL);
+
+        let shift = MAX_BITS_PER_VAL - width;
+
+        match self {
+            Signedness::Signed => (val << shift) >> shift,
+            Signedness::Unsigned => ((val << shift) as u128 >> shift) as ExprVal,
+        }
+    }
+}
+
+/// Build a z3 bitvector numeral of width `width`.
+pub fn val_to_z3(ctx: &z3::Context, val: ExprVal, width: u32) -> z3::ast::BV<'_> {
+    let bits = val as u128;
+
+    if width <= 64 {
+        return z3::ast::BV::from_u64(ctx, bits as u64 & (u64::MAX >> (64 - width)), width);
+    }
+
+    let hi = z3::ast::BV::from_u64(
+        ctx,
+        (bits >> 64) as u64 & (u64::MAX >> (MAX_BITS_PER_VAL - width)),
+        width - 64,
+    );
+    let lo = z3::ast::BV::from_u64(ctx, bits as u64, 64);
+
+    hi.concat(&lo)
+}
+
+/// Read a value back from a z3 bitvector numeral. Returns `None` if `bv`
+/// is not a numeral.
+pub fn val_from_z3(bv: &z3::ast::BV, signedness: Signedness) -> Option<ExprVal> {
+    let width = bv.get_size();
+
+    if width <= 64 {
+        return Some(signedness.truncate(bv.as_u64()? as ExprVal, width));
+    }
+
+    let hi = bv.extract(width - 1, 64).simplify().as_u64()?;
+    let lo = bv.extract(63, 0).simplify().as_u64()?;
+
+    Some(signedness.truncate(((hi as u128) << 64 | lo as u128) as ExprVal, width))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
 pub enum Value {
     Arg(String),
     Const(ExprVal),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
 pub enum Variable {
     UnknownConst,
     Const(ExprVal),
     Argument(usize),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
 pub enum UnopKind {
     Not,
     Negate,
+    /// Population count
+    Popcnt,
+    /// Count leading zeroes
+    Clz,
+    /// Count trailing zeroes
+    Ctz,
+    /// Byte swap. On widths, which are not a multiple of 8, the value is
+    /// zero-extended to full bytes first and truncated back afterwards.
+    Bswap,
+}
+
+impl UnopKind {
+    pub const ALL: [UnopKind; 6] = [
+        UnopKind::Negate,
+        UnopKind::Not,
+        UnopKind::Popcnt,
+        UnopKind::Clz,
+        UnopKind::Ctz,
+        UnopKind::Bswap,
+    ];
+
+    /// Whether the operator is a bit-counting or byte-shuffling intrinsic,
+    /// which not every ISA can do in one instruction.
+    pub fn is_intrinsic(self) -> bool {
+        matches!(self, UnopKind::Popcnt | UnopKind::Clz | UnopKind::Ctz | UnopKind::Bswap)
+    }
+
+    /// How the operator is spelled in the printed expressions.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnopKind::Not => "!",
+            UnopKind::Negate => "-",
+            UnopKind::Popcnt => "popcnt",
+            UnopKind::Clz => "clz",
+            UnopKind::Ctz => "ctz",
+            UnopKind::Bswap => "bswap",
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
 pub enum BinopKind {
     And,
     Or,
@@ -31,64 +139,376 @@ pub enum BinopKind {
     Minus,
     Shl,
     ShrA,
+    ShrL,
+    Mul,
+    SDiv,
+    UDiv,
+    SRem,
+    URem,
+    Rotl,
+    Rotr,
+}
+
+impl BinopKind {
+    pub const ALL: [BinopKind; 15] = [
+        BinopKind::And,
+        BinopKind::Or,
+        BinopKind::Xor,
+        BinopKind::Plus,
+        BinopKind::Minus,
+        BinopKind::Shl,
+        BinopKind::ShrA,
+        BinopKind::ShrL,
+        BinopKind::Mul,
+        BinopKind::SDiv,
+        BinopKind::UDiv,
+        BinopKind::SRem,
+        BinopKind::URem,
+        BinopKind::Rotl,
+        BinopKind::Rotr,
+    ];
+
+    /// Whether `l op r` is always equal to `r op l`.
+    pub fn is_commutative(self) -> bool {
+        matches!(
+            self,
+            BinopKind::And | BinopKind::Or | BinopKind::Xor | BinopKind::Plus | BinopKind::Mul,
+        )
+    }
+
+    /// Whether `(a op b) op c` is always equal to `a op (b op c)`.
+    pub fn is_associative(self) -> bool {
+        self.is_commutative()
+    }
+
+    /// How the operator is spelled in the printed expressions.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinopKind::And => "&",
+            BinopKind::Or => "|",
+            BinopKind::Xor => "^",
+            BinopKind::Plus => "+",
+            BinopKind::Minus => "-",
+            BinopKind::Shl => "<<",
+            BinopKind::ShrA => ">>",
+            BinopKind::ShrL => ">>>",
+            BinopKind::Mul => "*",
+            BinopKind::SDiv => "/",
+            BinopKind::UDiv => "/u",
+            BinopKind::SRem => "%",
+            BinopKind::URem => "%u",
+            BinopKind::Rotl => "rotl",
+            BinopKind::Rotr => "rotr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum CmpKind {
+    Eq,
+    Slt,
+    Ult,
+}
+
+impl CmpKind {
+    pub const ALL: [CmpKind; 3] = [
+        CmpKind::Eq,
+        CmpKind::Slt,
+        CmpKind::Ult,
+    ];
+
+    /// How the comparison is spelled in the printed expressions.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CmpKind::Eq => "==",
+            CmpKind::Slt => "<",
+            CmpKind::Ult => "<u",
+        }
+    }
+}
+
+/// The boolean sublanguage. Booleans are not values, so they
+/// can only appear as the condition of [Expr::Ite].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum BoolExpr<V = Variable> {
+    Cmp(CmpKind, Rc<Expr<V>>, Rc<Expr<V>>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// With the `serde` feature, an expression is stored as externally
+/// tagged JSON, e.g. `x + 1` is
+/// `{"binop":["plus",{"variable":{"arg":"x"}},{"variable":{"const":1}}]}`.
+///
+/// The comparisons, hashing, debug printing and dropping keep their
+/// own stacks, so they work on the expressions of any depth. They
+/// behave like the derived ones would.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
 pub enum Expr<V = Variable> {
     Variable(V),
     Unop(UnopKind, Rc<Expr<V>>),
     Binop(BinopKind, Rc<Expr<V>>, Rc<Expr<V>>),
+    /// If-then-else
+    Ite(BoolExpr<V>, Rc<Expr<V>>, Rc<Expr<V>>),
 }
 
 pub type ExprSkeleton = Expr<()>;
 pub type AnswerExpr = Expr<Value>;
 
+/// The operators a search is allowed to use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpSet {
+    pub unops: Vec<UnopKind>,
+    pub binops: Vec<BinopKind>,
+    /// Comparisons available to the if-then-else conditions. If empty,
+    /// no if-then-else gets generated.
+    pub cmps: Vec<CmpKind>,
+}
+
+impl OpSet {
+    /// Every operator bitsynth supports.
+    pub fn all() -> Self {
+        Self {
+            unops: UnopKind::ALL.to_vec(),
+            binops: BinopKind::ALL.to_vec(),
+            cmps: CmpKind::ALL.to_vec(),
+        }
+    }
+
+    /// Every operator, except for the intrinsics.
+    pub fn portable() -> Self {
+        let mut res = Self::all();
+        res.unops.retain(|x| !x.is_intrinsic());
+
+        res
+    }
+
+    /// Checks that `expr` uses only the operators from this set.
+    pub fn allows<V>(&self, expr: &Expr<V>) -> bool {
+        expr.walk_expr(
+            &mut |_| true,
+            &mut |unop_kind, e| e && self.unops.contains(&unop_kind),
+            &mut |binop_kind, l, r| l && r && self.binops.contains(&binop_kind),
+            &mut |cmp_kind, l, r| l && r && self.cmps.contains(&cmp_kind),
+            &mut |c, t, e| c && t && e,
+            &mut |x| x,
+        )
+    }
+}
+
+impl Default for OpSet {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// A node of an expression without its operands, see [Expr::preorder].
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Head<'a, VarT> {
+    Variable(&'a VarT),
+    Unop(UnopKind),
+    Binop(BinopKind),
+    Ite(CmpKind),
+}
+
 impl<VarT> Expr<VarT> {
-    // NOTE: if stack starts overfilling -- that should be the first
-    // function we turn non-recursive.
-    pub fn walk_expr<T, V, U, B, Var, Prom>(
+    /// The nodes of the expression in pre-order. As the number of the
+    /// operands follows from the node, this is enough to compare
+    /// the expressions. The order is the one of the fields.
+    fn preorder(&self) -> impl Iterator<Item = Head<'_, VarT>> {
+        let mut stack = vec![self];
+
+        std::iter::from_fn(move || {
+            let head = match stack.pop()? {
+                Expr::Variable(v) => Head::Variable(v),
+                Expr::Unop(unop_kind, e) => {
+                    stack.push(e);
+                    Head::Unop(*unop_kind)
+                },
+                Expr::Binop(binop_kind, l, r) => {
+                    stack.extend([&**r, &**l]);
+                    Head::Binop(*binop_kind)
+                },
+                Expr::Ite(BoolExpr::Cmp(cmp_kind, l, r), t, e) => {
+                    stack.extend([&**e, &**t, &**r, &**l]);
+                    Head::Ite(*cmp_kind)
+                },
+            };
+
+            Some(head)
+        })
+    }
+
+    /// The operands, the ones of the condition first.
+    fn operands(&self) -> [Option<&Rc<Expr<VarT>>>; 4] {
+        match self {
+            Expr::Variable(_) => [None, None, None, None],
+            Expr::Unop(_, e) => [Some(e), None, None, None],
+            Expr::Binop(_, l, r) => [Some(l), Some(r), None, None],
+            Expr::Ite(BoolExpr::Cmp(_, l, r), t, e) => [Some(l), Some(r), Some(t), Some(e)],
+        }
+    }
+
+    /// Move the operands of the expression, which aren't variables, to
+    /// `stack`, leaving `leaf` in their place. See the [Drop] implementation.
+    fn detach(&mut self, leaf: &Rc<Expr<VarT>>, stack: &mut Vec<Rc<Expr<VarT>>>) {
+        let operands = match self {
+            Expr::Variable(_) => [None, None, None, None],
+            Expr::Unop(_, e) => [Some(e), None, None, None],
+            Expr::Binop(_, l, r) => [Some(l), Some(r), None, None],
+            Expr::Ite(BoolExpr::Cmp(_, l, r), t, e) => [Some(l), Some(r), Some(t), Some(e)],
+        };
+
+        for x in operands.into_iter().flatten() {
+            if !matches!(**x, Expr::Variable(_)) {
+                stack.push(std::mem::replace(x, Rc::clone(leaf)));
+            }
+        }
+    }
+}
+
+impl<VarT: PartialEq> PartialEq for Expr<VarT> {
+    fn eq(&self, other: &Self) -> bool {
+        self.preorder().eq(other.preorder())
+    }
+}
+
+impl<VarT: Eq> Eq for Expr<VarT> {}
+
+impl<VarT: PartialOrd> PartialOrd for Expr<VarT> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.preorder().partial_cmp(other.preorder())
+    }
+}
+
+impl<VarT: Ord> Ord for Expr<VarT> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.preorder().cmp(other.preorder())
+    }
+}
+
+impl<VarT: Hash> Hash for Expr<VarT> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.preorder().for_each(|x| x.hash(state));
+    }
+}
+
+impl<VarT: fmt::Debug> fmt::Debug for Expr<VarT> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        debug_expr(f, self)
+    }
+}
+
+impl<VarT> Drop for Expr<VarT> {
+    /// The nested operands are detached before they are dropped,
+    /// so the dropping doesn't recurse. A variable of the expression
+    /// fills their places in the meantime.
+    fn drop(&mut self) {
+        let operands = self.operands();
+        if operands.into_iter().flatten().all(|x| matches!(**x, Expr::Variable(_))) {
+            return;
+        }
+
+        let mut leaf = operands[0].unwrap();
+        while let Some(x) = leaf.operands()[0] {
+            leaf = x;
+        }
+        let leaf = Rc::clone(leaf);
+
+        let mut stack = Vec::new();
+        self.detach(&leaf, &mut stack);
+        while let Some(x) = stack.pop() {
+            if let Ok(mut x) = Rc::try_unwrap(x) {
+                x.detach(&leaf, &mut stack);
+            }
+        }
+    }
+}
+
+/// The pending work of [Expr::walk_expr].
+enum WalkTask<'a, VarT> {
+    Visit(&'a Expr<VarT>),
+    Unop(UnopKind),
+    Binop(BinopKind),
+    /// The condition of an if-then-else, its branches follow
+    Cmp(CmpKind),
+    Ite,
+}
+
+impl<VarT> Expr<VarT> {
+    /// Fold the expression bottom-up. The nodes are visited left to right,
+    /// the condition of an if-then-else is folded before its branches.
+    ///
+    /// The traversal keeps its own stack, so it works on the expressions
+    /// of any depth.
+    pub fn walk_expr<T, Cnd, V, U, B, Cm, I, Var, Prom>(
         &self,
         var_action: &mut V,
         unop_action: &mut U,
         binop_action: &mut B,
+        cmp_action: &mut Cm,
+        ite_action: &mut I,
         var_promote: &mut Prom,
     ) -> T
     where
         V: FnMut(&VarT) -> Var,
         U: FnMut(UnopKind, T) -> T,
         B: FnMut(BinopKind, T, T) -> T,
+        Cm: FnMut(CmpKind, T, T) -> Cnd,
+        I: FnMut(Cnd, T, T) -> T,
         Prom: FnMut(Var) -> T,
     {
-        match self {
-            Expr::Variable(variable) => var_promote(var_action(variable)),
-            Expr::Unop(unop_kind, expr) => {
-                let expr = expr.walk_expr(
-                    var_action,
-                    unop_action,
-                    binop_action,
-                    var_promote,
-                );
-
-                unop_action(*unop_kind, expr)
-            },
-            Expr::Binop(binop_kind, l, r) => {
-                let l = l.walk_expr(
-                    var_action,
-                    unop_action,
-                    binop_action,
-                    var_promote,
-                );
-
-                let r = r.walk_expr(
-                    var_action,
-                    unop_action,
-                    binop_action,
-                    var_promote,
-                );
-
-                binop_action(*binop_kind, l, r)
-            },
+        let mut tasks = vec![WalkTask::Visit(self)];
+        let mut vals = Vec::new();
+        let mut conds = Vec::new();
+        // The operands of a task are on top of `vals`, the last one topmost
+        let pop = |vals: &mut Vec<T>| vals.pop().unwrap();
+
+        while let Some(task) = tasks.pop() {
+            match task {
+                WalkTask::Visit(Expr::Variable(variable)) => {
+                    vals.push(var_promote(var_action(variable)));
+                },
+                WalkTask::Visit(Expr::Unop(unop_kind, expr)) => {
+                    tasks.push(WalkTask::Unop(*unop_kind));
+                    tasks.push(WalkTask::Visit(expr));
+                },
+                WalkTask::Visit(Expr::Binop(binop_kind, l, r)) => {
+                    tasks.push(WalkTask::Binop(*binop_kind));
+                    tasks.push(WalkTask::Visit(r));
+                    tasks.push(WalkTask::Visit(l));
+                },
+                WalkTask::Visit(Expr::Ite(BoolExpr::Cmp(cmp_kind, l, r), t, e)) => {
+                    tasks.push(WalkTask::Ite);
+                    tasks.push(WalkTask::Visit(e));
+                    tasks.push(WalkTask::Visit(t));
+                    tasks.push(WalkTask::Cmp(*cmp_kind));
+                    tasks.push(WalkTask::Visit(r));
+                    tasks.push(WalkTask::Visit(l));
+                },
+                WalkTask::Unop(unop_kind) => {
+                    let expr = pop(&mut vals);
+                    vals.push(unop_action(unop_kind, expr));
+                },
+                WalkTask::Binop(binop_kind) => {
+                    let (r, l) = (pop(&mut vals), pop(&mut vals));
+                    vals.push(binop_action(binop_kind, l, r));
+                },
+                WalkTask::Cmp(cmp_kind) => {
+                    let (r, l) = (pop(&mut vals), pop(&mut vals));
+                    conds.push(cmp_action(cmp_kind, l, r));
+                },
+                WalkTask::Ite => {
+                    let (e, t) = (pop(&mut vals), pop(&mut vals));
+                    let cond = conds.pop().unwrap();
+                    vals.push(ite_action(cond, t, e));
+                },
+            }
         }
+
+        pop(&mut vals)
     }
 
     pub fn expr_depth(&self) -> usize {
@@ -96,41 +516,248 @@ impl<VarT> Expr<VarT> {
             &mut |_| 0,
             &mut |_, x| x + 1,
             &mut |_, l, r| 1 + std::cmp::max(l, r),
+            &mut |_, l, r| std::cmp::max(l, r),
+            &mut |c: usize, t, e| 1 + c.max(t).max(e),
             &mut |x| x,
         )
     }
 }
 
-impl Expr {
-    pub fn compute<F>(
+/// Swap the bytes of a zero-extended `width`-bit value.
+fn byte_swap(bits: u128, width: u32) -> ExprVal {
+    let bytes = width.div_ceil(8);
+
+    (bits.swap_bytes() >> (MAX_BITS_PER_VAL - bytes * 8)) as ExprVal
+}
+
+fn z3_popcnt<'ctx>(e: &z3::ast::BV<'ctx>, width: u32) -> z3::ast::BV<'ctx> {
+    (1..width)
+        .map(|i| e.extract(i, i).zero_ext(width - 1))
+        .fold(e.extract(0, 0).zero_ext(width - 1), |acc, bit| acc + bit)
+}
+
+fn z3_clz<'ctx>(e: &z3::ast::BV<'ctx>, width: u32) -> z3::ast::BV<'ctx> {
+    let ctx = e.get_ctx();
+    let one = z3::ast::BV::from_u64(ctx, 1, 1);
+
+    // The highest set bit gets the final say
+    (0..width).fold(val_to_z3(ctx, width as ExprVal, width), |acc, i| {
+        e.extract(i, i)._eq(&one)
+            .ite(&val_to_z3(ctx, (width - 1 - i) as ExprVal, width), &acc)
+    })
+}
+
+fn z3_ctz<'ctx>(e: &z3::ast::BV<'ctx>, width: u32) -> z3::ast::BV<'ctx> {
+    let ctx = e.get_ctx();
+    let one = z3::ast::BV::from_u64(ctx, 1, 1);
+
+    // The lowest set bit gets the final say
+    (0..width).rev().fold(val_to_z3(ctx, width as ExprVal, width), |acc, i| {
+        e.extract(i, i)._eq(&one)
+            .ite(&val_to_z3(ctx, i as ExprVal, width), &acc)
+    })
+}
+
+fn z3_bswap<'ctx>(e: &z3::ast::BV<'ctx>, width: u32) -> z3::ast::BV<'ctx> {
+    let bytes = width.div_ceil(8);
+    let e = e.zero_ext(bytes * 8 - width);
+
+    (1..bytes)
+        .map(|i| e.extract(i * 8 + 7, i * 8))
+        .fold(e.extract(7, 0), |acc, byte| acc.concat(&byte))
+        .extract(width - 1, 0)
+}
+
+/// Rotations by a non-constant amount are spelled with shifts, since
+/// z3's own `ext_rotate_left` is not a part of SMT-LIB.
+fn z3_rotl<'ctx>(l: &z3::ast::BV<'ctx>, r: &z3::ast::BV<'ctx>, width: u32) -> z3::ast::BV<'ctx> {
+    let width = val_to_z3(l.get_ctx(), width as ExprVal, width);
+    let amount = r.bvurem(&width);
+
+    // Shifting by the full width gives zero, which covers `amount == 0`
+    l.bvshl(&amount) | l.bvlshr(&(width - &amount))
+}
+
+fn z3_rotr<'ctx>(l: &z3::ast::BV<'ctx>, r: &z3::ast::BV<'ctx>, width: u32) -> z3::ast::BV<'ctx> {
+    let width = val_to_z3(l.get_ctx(), width as ExprVal, width);
+    let amount = r.bvurem(&width);
+
+    l.bvlshr(&amount) | l.bvshl(&(width - &amount))
+}
+
+/// The z3 encoding of a unary operator. Matches [eval_unop] bit for bit.
+pub(crate) fn z3_unop<'ctx>(kind: UnopKind, e: &z3::ast::BV<'ctx>, width: u32) -> z3::ast::BV<'ctx> {
+    match kind {
+        UnopKind::Not => e.bvnot(),
+        UnopKind::Negate => e.bvneg(),
+        UnopKind::Popcnt => z3_popcnt(e, width),
+        UnopKind::Clz => z3_clz(e, width),
+        UnopKind::Ctz => z3_ctz(e, width),
+        UnopKind::Bswap => z3_bswap(e, width),
+    }
+}
+
+/// The z3 encoding of a binary operator. See [z3_unop].
+pub(crate) fn z3_binop<'ctx>(
+    kind: BinopKind,
+    l: &z3::ast::BV<'ctx>,
+    r: &z3::ast::BV<'ctx>,
+    width: u32,
+) -> z3::ast::BV<'ctx> {
+    match kind {
+        BinopKind::And => l.bvand(r),
+        BinopKind::Or => l.bvor(r),
+        BinopKind::Xor => l.bvxor(r),
+        BinopKind::Plus => l.bvadd(r),
+        BinopKind::Minus => l.bvsub(r),
+        BinopKind::Shl => l.bvshl(r),
+        BinopKind::ShrA => l.bvashr(r),
+        BinopKind::ShrL => l.bvlshr(r),
+        BinopKind::Mul => l.bvmul(r),
+        BinopKind::SDiv => l.bvsdiv(r),
+        BinopKind::UDiv => l.bvudiv(r),
+        BinopKind::SRem => l.bvsrem(r),
+        BinopKind::URem => l.bvurem(r),
+        BinopKind::Rotl => z3_rotl(l, r, width),
+        BinopKind::Rotr => z3_rotr(l, r, width),
+    }
+}
+
+pub(crate) fn z3_cmp<'ctx>(
+    kind: CmpKind,
+    l: &z3::ast::BV<'ctx>,
+    r: &z3::ast::BV<'ctx>,
+) -> z3::ast::Bool<'ctx> {
+    match kind {
+        CmpKind::Eq => l._eq(r),
+        CmpKind::Slt => l.bvslt(r),
+        CmpKind::Ult => l.bvult(r),
+    }
+}
+
+/// Rotate a zero-extended `width`-bit value to the left by `amount` bits.
+fn rotate_left(bits: u128, amount: u32, width: u32) -> ExprVal {
+    let amount = amount % width;
+
+    if amount == 0 {
+        return bits as ExprVal;
+    }
+
+    ((bits << amount) | (bits >> (width - amount))) as ExprVal
+}
+
+/// Concrete semantics of a unary operator. The result is only
+/// meaningful in its lower `width` bits.
+pub(crate) fn eval_unop(kind: UnopKind, e: ExprVal, width: u32) -> ExprVal {
+    let zext = Signedness::Unsigned.truncate(e, width) as u128;
+
+    match kind {
+        UnopKind::Not => !e,
+        UnopKind::Negate => e.wrapping_neg(),
+        UnopKind::Popcnt => zext.count_ones() as ExprVal,
+        UnopKind::Clz => (zext.leading_zeros() - (MAX_BITS_PER_VAL - width)) as ExprVal,
+        UnopKind::Ctz => zext.trailing_zeros().min(width) as ExprVal,
+        UnopKind::Bswap => byte_swap(zext, width),
+    }
+}
+
+/// Concrete semantics of a binary operator. The result is only
+/// meaningful in its lower `width` bits. Shifts, rotations and
+/// division by zero follow SMT-LIB, so this agrees with [`Expr::to_z3`].
+pub(crate) fn eval_binop(kind: BinopKind, l: ExprVal, r: ExprVal, width: u32) -> ExprVal {
+    let sext = |x| Signedness::Signed.truncate(x, width);
+    let zext = |x| Signedness::Unsigned.truncate(x, width) as u128;
+    // Shifting by the width or more pushes out every bit
+    let shift = |x| match zext(x) {
+        amount if amount < width as u128 => Some(amount as u32),
+        _ => None,
+    };
+
+    match kind {
+        BinopKind::And => l & r,
+        BinopKind::Or => l | r,
+        BinopKind::Xor => l ^ r,
+        BinopKind::Plus => l.wrapping_add(r),
+        BinopKind::Minus => l.wrapping_sub(r),
+        BinopKind::Shl => shift(r).map_or(0, |r| l << r),
+        BinopKind::ShrA => shift(r).map_or(sext(l) >> (width - 1), |r| sext(l) >> r),
+        BinopKind::ShrL => shift(r).map_or(0, |r| (zext(l) >> r) as ExprVal),
+        BinopKind::Mul => l.wrapping_mul(r),
+        BinopKind::SDiv => match (sext(l), sext(r)) {
+            (l, 0) if l < 0 => 1,
+            (_, 0) => -1,
+            (l, r) => l.wrapping_div(r),
+        },
+        BinopKind::UDiv => match (zext(l), zext(r)) {
+            (_, 0) => -1,
+            (l, r) => (l / r) as ExprVal,
+        },
+        BinopKind::SRem => match (sext(l), sext(r)) {
+            (l, 0) => l,
+            (l, r) => l.wrapping_rem(r),
+        },
+        BinopKind::URem => match (zext(l), zext(r)) {
+            (l, 0) => l as ExprVal,
+            (l, r) => (l % r) as ExprVal,
+        },
+        BinopKind::Rotl => rotate_left(zext(l), (zext(r) % width as u128) as u32, width),
+        BinopKind::Rotr => rotate_left(zext(l), width - (zext(r) % width as u128) as u32, width),
+    }
+}
+
+/// Concrete semantics of a comparison.
+pub(crate) fn eval_cmp(kind: CmpKind, l: ExprVal, r: ExprVal, width: u32) -> bool {
+    let sext = |x| Signedness::Signed.truncate(x, width);
+    let zext = |x| Signedness::Unsigned.truncate(x, width);
+
+    match kind {
+        CmpKind::Eq => zext(l) == zext(r),
+        CmpKind::Slt => sext(l) < sext(r),
+        CmpKind::Ult => (zext(l) as u128) < (zext(r) as u128),
+    }
+}
+
+impl<VarT> Expr<VarT> {
+    fn eval<F>(
         &self,
+        width: u32,
+        signedness: Signedness,
         mut var_map: F,
     ) -> ExprVal
     where
-        F: FnMut(Variable) -> ExprVal,
+        F: FnMut(&VarT) -> ExprVal,
     {
         self.walk_expr(
-            &mut |x| var_map(*x),
-            &mut |unop_kind, e: i32| match unop_kind {
-                UnopKind::Not => -e,
-                UnopKind::Negate => !e,
-            },
-            &mut |binop_kind, l, r| match binop_kind {
-                BinopKind::And => l & r,
-                BinopKind::Or => l | r,
-                BinopKind::Xor => l ^ r,
-                BinopKind::Plus => l + r,
-                BinopKind::Minus => l - r,
-                BinopKind::Shl => l << r,
-                BinopKind::ShrA => l >> r,
-            },
+            &mut |x| signedness.truncate(var_map(x), width),
+            &mut |unop_kind, e| signedness.truncate(eval_unop(unop_kind, e, width), width),
+            &mut |binop_kind, l, r| signedness.truncate(eval_binop(binop_kind, l, r, width), width),
+            &mut |cmp_kind, l, r| eval_cmp(cmp_kind, l, r, width),
+            &mut |c, t, e| if c { t } else { e },
             &mut |x| x,
         )
     }
+}
+
+impl Expr {
+    /// Evaluate the expression on concrete values. The result
+    /// is bit-for-bit what z3 computes for [`Expr::to_z3`],
+    /// extended to an [`ExprVal`] according to `signedness`.
+    pub fn compute<F>(
+        &self,
+        width: u32,
+        signedness: Signedness,
+        mut var_map: F,
+    ) -> ExprVal
+    where
+        F: FnMut(Variable) -> ExprVal,
+    {
+        self.eval(width, signedness, |x| var_map(*x))
+    }
 
     pub fn to_z3<'ctx, A, C>(
         &self,
         ctx: &'ctx z3::Context,
+        width: u32,
         mut const_map: C,
         mut arg_map: A,
     ) -> z3::ast::BV<'ctx>
@@ -148,22 +775,13 @@ impl Expr {
 
                     res
                 },
-                Variable::Const(x) => z3::ast::BV::from_i64(ctx, *x as i64, BITS_PER_VAL),
+                Variable::Const(x) => val_to_z3(ctx, *x, width),
                 Variable::Argument(x) => arg_map(ctx, *x),
             },
-            &mut |unop_kind, e: z3::ast::BV<'ctx>| match unop_kind {
-                UnopKind::Not => !e,
-                UnopKind::Negate => -e,
-            },
-            &mut |binop_kind, l, r| match binop_kind {
-                BinopKind::And => l & r,
-                BinopKind::Or => l | r,
-                BinopKind::Xor => l ^ r,
-                BinopKind::Plus => l + r,
-                BinopKind::Minus => l - r,
-                BinopKind::Shl => l << r,
-                BinopKind::ShrA => l.bvashr(&r),
-            },
+            &mut |unop_kind, e: z3::ast::BV<'ctx>| z3_unop(unop_kind, &e, width),
+            &mut |binop_kind, l, r| z3_binop(binop_kind, &l, &r, width),
+            &mut |cmp_kind, l, r| z3_cmp(cmp_kind, &l, &r),
+            &mut |c: z3::ast::Bool<'ctx>, t, e| c.ite(&t, &e),
             &mut |x| x,
         )
     }
@@ -183,15 +801,40 @@ impl Expr {
             &mut |binop_kind, l, r| {
                 Expr::Binop(binop_kind, Rc::new(l), Rc::new(r))
             },
+            &mut |cmp_kind, l, r| {
+                BoolExpr::Cmp(cmp_kind, Rc::new(l), Rc::new(r))
+            },
+            &mut |c, t, e| {
+                Expr::Ite(c, Rc::new(t), Rc::new(e))
+            },
             &mut |x| Expr::Variable(x),
         )
     }
 }
 
 impl AnswerExpr {
+    /// Evaluate the answer on concrete argument values. Agrees
+    /// with [`AnswerExpr::to_z3_ans`] the same way [`Expr::compute`]
+    /// agrees with [`Expr::to_z3`].
+    pub fn compute_ans<F>(
+        &self,
+        width: u32,
+        signedness: Signedness,
+        mut arg_map: F,
+    ) -> ExprVal
+    where
+        F: FnMut(&str) -> ExprVal,
+    {
+        self.eval(width, signedness, |v| match v {
+            Value::Arg(x) => arg_map(x.as_str()),
+            Value::Const(x) => *x,
+        })
+    }
+
     pub fn to_z3_ans<'ctx, V>(
         &self,
         ctx: &'ctx z3::Context,
+        width: u32,
         mut var_map: V,
     ) -> z3::ast::BV<'ctx>
     where
@@ -200,47 +843,138 @@ impl AnswerExpr {
         self.walk_expr(
             &mut move |v| match v {
                 Value::Arg(x) => var_map(x.as_str()),
-                Value::Const(x) => z3::ast::BV::from_i64(ctx, *x as i64, BITS_PER_VAL),
-            },
-            &mut |unop_kind, e: z3::ast::BV<'ctx>| match unop_kind {
-                UnopKind::Not => !e,
-                UnopKind::Negate => -e,
-            },
-            &mut |binop_kind, l, r| match binop_kind {
-                BinopKind::And => l & r,
-                BinopKind::Or => l | r,
-                BinopKind::Xor => l ^ r,
-                BinopKind::Plus => l + r,
-                BinopKind::Minus => l - r,
-                BinopKind::Shl => l << r,
-                BinopKind::ShrA => l.bvashr(&r),
+                Value::Const(x) => val_to_z3(ctx, *x, width),
             },
+            &mut |unop_kind, e: z3::ast::BV<'ctx>| z3_unop(unop_kind, &e, width),
+            &mut |binop_kind, l, r| z3_binop(binop_kind, &l, &r, width),
+            &mut |cmp_kind, l, r| z3_cmp(cmp_kind, &l, &r),
+            &mut |c: z3::ast::Bool<'ctx>, t, e| c.ite(&t, &e),
             &mut |x| x,
         )
     }
 }
 
-impl fmt::Display for AnswerExpr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::Variable(x) => match x {
-                Value::Arg(arg) => write!(f, "{arg}"),
-                Value::Const(c) => write!(f, "{c}"),
+/// A piece of a printed expression.
+enum Piece<'a, V = Value> {
+    Expr(&'a Expr<V>),
+    Cond(&'a BoolExpr<V>),
+    Text(&'static str),
+}
+
+/// Print an expression. Like [Expr::walk_expr], this keeps
+/// its own stack instead of recursing.
+fn print_expr(f: &mut fmt::Formatter<'_>, root: Piece) -> fmt::Result {
+    let mut stack = vec![root];
+
+    while let Some(piece) = stack.pop() {
+        let pieces = match piece {
+            Piece::Text(text) => {
+                f.write_str(text)?;
+                continue;
             },
-            Expr::Unop(unop_kind, expr) => match unop_kind {
-                UnopKind::Not => write!(f, "!({expr})"),
-                UnopKind::Negate => write!(f, "-({expr})"),
+            Piece::Expr(Expr::Variable(x)) => {
+                match x {
+                    Value::Arg(arg) => write!(f, "{arg}")?,
+                    Value::Const(c) => write!(f, "{c}")?,
+                }
+                continue;
             },
-            Expr::Binop(binop_kind, l, r) => match binop_kind {
-                BinopKind::And => write!(f, "({l} & {r})"),
-                BinopKind::Or => write!(f, "({l} | {r})"),
-                BinopKind::Xor => write!(f, "({l} ^ {r})"),
-                BinopKind::Plus => write!(f, "({l} + {r})"),
-                BinopKind::Minus => write!(f, "({l} - {r})"),
-                BinopKind::Shl => write!(f, "({l} << {r})"),
-                BinopKind::ShrA => write!(f, "({l} >> {r})"),
+            Piece::Expr(Expr::Unop(unop_kind, expr)) => vec![
+                Piece::Text(unop_kind.symbol()),
+                Piece::Text("("),
+                Piece::Expr(expr),
+                Piece::Text(")"),
+            ],
+            Piece::Expr(Expr::Binop(binop_kind, l, r)) => vec![
+                Piece::Text("("),
+                Piece::Expr(l),
+                Piece::Text(" "),
+                Piece::Text(binop_kind.symbol()),
+                Piece::Text(" "),
+                Piece::Expr(r),
+                Piece::Text(")"),
+            ],
+            Piece::Expr(Expr::Ite(cond, t, e)) => vec![
+                Piece::Text("("),
+                Piece::Cond(cond),
+                Piece::Text(" ? "),
+                Piece::Expr(t),
+                Piece::Text(" : "),
+                Piece::Expr(e),
+                Piece::Text(")"),
+            ],
+            Piece::Cond(BoolExpr::Cmp(cmp_kind, l, r)) => vec![
+                Piece::Text("("),
+                Piece::Expr(l),
+                Piece::Text(" "),
+                Piece::Text(cmp_kind.symbol()),
+                Piece::Text(" "),
+                Piece::Expr(r),
+                Piece::Text(")"),
+            ],
+        };
+
+        stack.extend(pieces.into_iter().rev());
+    }
+
+    Ok(())
+}
+
+/// Print an expression like the derived [fmt::Debug] would,
+/// keeping a stack like [print_expr].
+fn debug_expr<V: fmt::Debug>(f: &mut fmt::Formatter<'_>, root: &Expr<V>) -> fmt::Result {
+    let mut stack = vec![Piece::Expr(root)];
+
+    while let Some(piece) = stack.pop() {
+        let pieces = match piece {
+            Piece::Text(text) => {
+                f.write_str(text)?;
+                continue;
             },
-        }
+            Piece::Expr(Expr::Variable(x)) => {
+                write!(f, "Variable({x:?})")?;
+                continue;
+            },
+            Piece::Expr(Expr::Unop(unop_kind, e)) => {
+                write!(f, "Unop({unop_kind:?}, ")?;
+                vec![Piece::Expr(e), Piece::Text(")")]
+            },
+            Piece::Expr(Expr::Binop(binop_kind, l, r)) => {
+                write!(f, "Binop({binop_kind:?}, ")?;
+                vec![Piece::Expr(l), Piece::Text(", "), Piece::Expr(r), Piece::Text(")")]
+            },
+            Piece::Expr(Expr::Ite(cond, t, e)) => {
+                f.write_str("Ite(")?;
+                vec![
+                    Piece::Cond(cond),
+                    Piece::Text(", "),
+                    Piece::Expr(t),
+                    Piece::Text(", "),
+                    Piece::Expr(e),
+                    Piece::Text(")"),
+                ]
+            },
+            Piece::Cond(BoolExpr::Cmp(cmp_kind, l, r)) => {
+                write!(f, "Cmp({cmp_kind:?}, ")?;
+                vec![Piece::Expr(l), Piece::Text(", "), Piece::Expr(r), Piece::Text(")")]
+            },
+        };
+
+        stack.extend(pieces.into_iter().rev());
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for AnswerExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        print_expr(f, Piece::Expr(self))
+    }
+}
+
+impl fmt::Display for BoolExpr<Value> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        print_expr(f, Piece::Cond(self))
     }
 }
 
@@ -270,6 +1004,12 @@ impl ExprSkeleton {
             &mut |binop_kind, l, r| {
                 Expr::Binop(binop_kind, Rc::new(l), Rc::new(r))
             },
+            &mut |cmp_kind, l, r| {
+                BoolExpr::Cmp(cmp_kind, Rc::new(l), Rc::new(r))
+            },
+            &mut |c, t, e| {
+                Expr::Ite(c, Rc::new(t), Rc::new(e))
+            },
             &mut promote
         )
     }
@@ -298,6 +1038,8 @@ impl ExprSkeleton {
             &mut |_| 1,
             &mut |_, e| e,
             &mut |_, l, r| l + r,
+            &mut |_, l, r| l + r,
+            &mut |c, t, e| c + t + e,
             &mut |x| x
         )
     }
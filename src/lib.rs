@@ -2,4 +2,13 @@ pub mod expr;
 pub mod search;
 pub mod synth;
 pub mod conv;
-pub mod oracle;
\ No newline at end of file
+pub mod oracle;
+pub mod parse;
+pub mod sygus;
+pub mod simplify;
+pub mod canon;
+pub mod emit;
+pub mod dag;
+pub mod cost;
+pub mod dot;
+pub mod random;
\ No newline at end of file
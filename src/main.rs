@@ -1,18 +1,23 @@
-use expr::AnswerExpr;
-use log::info;
-use search::BithackSearch;
-use synth::{
+use bitsynth::emit::{
+    c::emit_c,
+    llvm::{emit_llvm, emit_llvm_module},
+    python::emit_python,
+    rust::emit_rust,
+    smt::{emit_smt, emit_sygus},
+};
+use bitsynth::cost::{total_cost, Profile};
+use bitsynth::expr::{AnswerExpr, BinopKind, CmpKind, OpSet, Signedness, UnopKind, MAX_BITS_PER_VAL};
+use bitsynth::oracle::{Outcome, SpecError};
+use bitsynth::parse::parse_answer;
+use bitsynth::search::{self, BithackSearch, Reference, UnknownPolicy};
+use bitsynth::sygus::parse_sygus;
+use bitsynth::synth::{
     Synthesizer,
     brute_enum::BruteEnum,
     circuit_enum::CircuitEnum,
     simple_search::SimpleSearch,
 };
-
-mod search;
-mod synth;
-mod conv;
-mod expr;
-mod oracle;
+use log::info;
 
 use clap::{Parser, ValueEnum};
 
@@ -25,6 +30,48 @@ enum Synth {
     Circuit,
 }
 
+#[derive(Clone, Copy, ValueEnum, PartialEq, Eq)]
+enum Emit {
+    Text,
+    C,
+    Rust,
+    Python,
+    Llvm,
+    Smt,
+    Sygus,
+    Dot,
+}
+
+#[derive(Clone, Copy, ValueEnum, PartialEq, Eq)]
+enum Cost {
+    Nodes,
+    X86Latency,
+    X86Throughput,
+    Aarch64,
+    Riscv,
+}
+
+/// The optional components of the circuit library
+#[derive(Clone, Copy, ValueEnum, PartialEq, Eq)]
+enum Component {
+    Mul,
+    Div,
+    Rem,
+    Rotate,
+    Select,
+    Popcnt,
+    Clz,
+    Ctz,
+    Bswap,
+}
+
+#[derive(Clone, Copy, ValueEnum, PartialEq, Eq)]
+enum OnUnknown {
+    Skip,
+    Retry,
+    Abort,
+}
+
 #[derive(Parser)]
 struct Cli {
     #[arg(short, long)]
@@ -33,33 +80,333 @@ struct Cli {
     verbose: bool,
     #[arg(long)]
     timeout: Option<u64>,
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..=MAX_BITS_PER_VAL as i64))]
+    width: u32,
+    #[arg(short, long)]
+    unsigned: bool,
+    #[arg(long)]
+    portable: bool,
+    #[arg(long)]
+    no_simplify: bool,
     #[arg(short, long)]
     constraint: Vec<String>,
+    #[arg(long)]
+    assume: Vec<String>,
+    #[arg(long, conflicts_with_all = ["constraint", "reference_smt"])]
+    reference: Vec<String>,
+    #[arg(long, conflicts_with = "constraint")]
+    reference_smt: Vec<String>,
     #[arg(short, long)]
     arg: Vec<String>,
+    #[arg(short, long)]
+    result: Vec<String>,
     #[arg(value_enum, long, default_value = "circuit")]
     solver: Synth,
+    #[arg(value_enum, long)]
+    component: Vec<Component>,
+    #[arg(long)]
+    check: Vec<String>,
+    #[arg(value_enum, long, default_value = "text")]
+    emit: Emit,
+    #[arg(value_enum, long)]
+    cost: Option<Cost>,
+    #[arg(long, default_value_t = 0)]
+    improve: u64,
+    #[arg(value_enum, long, default_value = "skip")]
+    on_unknown: OnUnknown,
+    #[arg(long, default_value_t = 2)]
+    retries: u32,
+    #[arg(long, conflicts_with_all = ["constraint", "assume", "reference", "reference_smt", "arg", "result", "width"])]
+    sygus: Option<String>,
+    /// The operators of the grammars of the SyGuS problem
+    #[arg(skip)]
+    grammar: Option<OpSet>,
 }
 
+impl Cli {
+    fn signedness(&self) -> Signedness {
+        if self.unsigned {
+            Signedness::Unsigned
+        } else {
+            Signedness::Signed
+        }
+    }
+
+    /// The result variables of the specification, `res` by default
+    fn results(&self) -> Vec<String> {
+        if self.result.is_empty() {
+            vec!["res".to_string()]
+        } else {
+            self.result.clone()
+        }
+    }
+
+    fn ops(&self) -> OpSet {
+        let mut ops = if self.portable {
+            OpSet::portable()
+        } else {
+            OpSet::all()
+        };
+
+        if let Some(grammar) = &self.grammar {
+            ops.unops.retain(|x| grammar.unops.contains(x));
+            ops.binops.retain(|x| grammar.binops.contains(x));
+            ops.cmps.retain(|x| grammar.cmps.contains(x));
+        }
+
+        ops
+    }
+
+    /// The operators of the extra components of the circuit library
+    fn components(&self) -> OpSet {
+        let mut ops = OpSet { unops: Vec::new(), binops: Vec::new(), cmps: Vec::new() };
+
+        for component in &self.component {
+            match component {
+                Component::Mul => ops.binops.push(BinopKind::Mul),
+                Component::Div => ops.binops.extend([BinopKind::SDiv, BinopKind::UDiv]),
+                Component::Rem => ops.binops.extend([BinopKind::SRem, BinopKind::URem]),
+                Component::Rotate => ops.binops.extend([BinopKind::Rotl, BinopKind::Rotr]),
+                Component::Select => ops.cmps.extend([CmpKind::Slt, CmpKind::Ult]),
+                Component::Popcnt => ops.unops.push(UnopKind::Popcnt),
+                Component::Clz => ops.unops.push(UnopKind::Clz),
+                Component::Ctz => ops.unops.push(UnopKind::Ctz),
+                Component::Bswap => ops.unops.push(UnopKind::Bswap),
+            }
+        }
+
+        ops
+    }
+
+    /// Take the arguments, the results, the width, the constraints and
+    /// the operators from the SyGuS problem, if there is one. Exits,
+    /// if it can't be read.
+    fn load_sygus(&mut self) {
+        let Some(path) = &self.sygus else {
+            return;
+        };
+
+        let src = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {path}: {e}");
+            std::process::exit(2);
+        });
+        let problem = parse_sygus(&src).unwrap_or_else(|e| {
+            if let Some(line) = e.line.and_then(|line| src.lines().nth(line - 1)) {
+                eprintln!("{line}");
+            }
+            eprintln!("Failed to parse the SyGuS problem: {e}");
+            std::process::exit(2);
+        });
+
+        self.width = problem.width;
+        self.arg = problem.args.clone();
+        self.result = problem.results();
+        self.constraint = vec![problem.prompt.clone()];
+        self.assume = vec![problem.assumptions.clone()];
+        self.grammar = Some(problem.ops());
+    }
+
+    /// The names of the emitted functions, one for every result. The
+    /// functions of a SyGuS problem keep their names.
+    fn fun_names(&self) -> Vec<String> {
+        let results = self.results();
+
+        match results.as_slice() {
+            _ if self.sygus.is_some() => results,
+            [_] => vec!["f".to_string()],
+            _ => results.iter().map(|x| format!("f_{x}")).collect(),
+        }
+    }
+
+    /// The cost model to report and to rank the answers with. Looking
+    /// for cheaper answers without a model counts the nodes.
+    fn cost_profile(&self) -> Option<Profile> {
+        let profile = match self.cost {
+            None if self.improve > 0 => Profile::Nodes,
+            None => return None,
+            Some(Cost::Nodes) => Profile::Nodes,
+            Some(Cost::X86Latency) => Profile::X86Latency,
+            Some(Cost::X86Throughput) => Profile::X86Throughput,
+            Some(Cost::Aarch64) => Profile::Aarch64,
+            Some(Cost::Riscv) => Profile::Riscv,
+        };
+
+        Some(profile)
+    }
+
+    /// What to do with the checks the timeout has cut short.
+    /// Retrying doubles the timeout `--retries` times.
+    fn unknown_policy(&self) -> UnknownPolicy {
+        match self.on_unknown {
+            OnUnknown::Skip => UnknownPolicy::Skip,
+            OnUnknown::Retry => UnknownPolicy::Retry(self.retries),
+            OnUnknown::Abort => UnknownPolicy::Abort,
+        }
+    }
+
+    fn oracle_timeout(&self) -> Option<u32> {
+        self.timeout.map(|x| x.min(u32::MAX as u64) as u32)
+    }
+
+    fn report_cost(&self, answer: &[AnswerExpr]) {
+        if let Some(profile) = self.cost_profile() {
+            println!("Cost: {} ({})", total_cost(&profile, answer), profile.name());
+        }
+    }
+
+    /// Emit the answer. Every result gets its own function,
+    /// named after [Cli::fun_names].
+    fn emit(&self, answer: &[AnswerExpr]) -> String {
+        let results = self.results();
+        let names = self.fun_names();
+
+        if let ([answer], [name]) = (answer, names.as_slice()) {
+            return self.emit_one(answer, name);
+        }
+
+        match self.emit {
+            Emit::Text => results.iter()
+                .zip(answer)
+                .map(|(name, x)| format!("Found: {name} = {x}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Emit::Llvm => {
+                let funcs = names.iter()
+                    .map(String::as_str)
+                    .zip(answer)
+                    .collect::<Vec<_>>();
+
+                emit_llvm_module(&funcs, &self.arg, self.width)
+            },
+            Emit::Sygus => {
+                let defs = names.iter()
+                    .zip(answer)
+                    .map(|(name, x)| emit_smt(x, name, &self.arg, self.width))
+                    .collect::<String>();
+
+                format!("(\n{defs})\n")
+            },
+            _ => names.iter()
+                .zip(answer)
+                .map(|(name, x)| self.emit_one(x, name))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    fn emit_one(&self, answer: &AnswerExpr, name: &str) -> String {
+        let (args, width, signedness) = (&self.arg, self.width, self.signedness());
+
+        match self.emit {
+            Emit::Text => format!("Found: {answer}"),
+            Emit::C => emit_c(answer, name, args, width, signedness),
+            Emit::Rust => emit_rust(answer, name, args, width, signedness),
+            Emit::Python => emit_python(answer, name, args, width, signedness),
+            Emit::Llvm => emit_llvm(answer, name, args, width),
+            Emit::Smt => emit_smt(answer, name, args, width),
+            Emit::Sygus => emit_sygus(answer, name, args, width),
+            Emit::Dot => answer.to_dot(),
+        }
+    }
+
+    fn z3_config(&self) -> z3::Config {
+        let mut cfg = z3::Config::default();
+
+        if let Some(timeout) = self.timeout {
+            cfg.set_timeout_msec(timeout);
+        }
+
+        cfg
+    }
+}
+
+/// Parse an expression over the arguments. Exits, if it's malformed.
+fn parse_expr(cli: &Cli, src: &str) -> AnswerExpr {
+    match parse_answer(src, &cli.arg, cli.width, cli.signedness()) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{src}");
+            eprintln!("{:>1$}", "^", e.pos + 1);
+            eprintln!("Failed to parse the expression {e}");
+            std::process::exit(2);
+        },
+    }
+}
+
+/// The reference implementations given with `--reference`
+/// or `--reference-smt`, one for every result, with their sources.
+fn references(cli: &Cli) -> Vec<(Reference, &str)> {
+    cli.reference.iter()
+        .map(|x| (Reference::Answer(parse_expr(cli, x)), x.as_str()))
+        .chain(cli.reference_smt.iter().map(|x| (Reference::smt(x), x.as_str())))
+        .collect()
+}
+
+/// Exits, if parsing a part of the specification has failed.
+/// `src` is the text of the part.
+fn check_spec(res: Result<(), SpecError>, src: &str) {
+    if let Err(e) = res {
+        if let Some(line) = e.pos.and_then(|(line, _)| src.lines().nth(line - 1)) {
+            eprintln!("{line}");
+        }
+        eprintln!("Failed to parse the specification: {e}");
+        std::process::exit(2);
+    }
+}
+
+/// Feed the constraints or the references and the assumptions to
+/// `search` and set up its oracle. Exits, if the specification is
+/// malformed.
+fn parse_spec<'ctx, S: Synthesizer<'ctx>>(search: &mut BithackSearch<'ctx, S>, cli: &Cli) {
+    search.set_timeout(cli.oracle_timeout());
+    search.set_unknown_policy(cli.unknown_policy());
+
+    if cli.reference.is_empty() && cli.reference_smt.is_empty() {
+        let prompt = cli.constraint.join("\n");
+        check_spec(search.parse_prompt(&prompt), &prompt);
+    } else {
+        let (references, sources): (Vec<_>, Vec<_>) = references(cli).into_iter().unzip();
+        let res = search.parse_reference(&references);
+        let src = res.as_ref().err().and_then(|e| e.reference).map_or("", |idx| sources[idx]);
+        check_spec(res, src);
+    }
+
+    let assumptions = cli.assume.join("\n");
+    check_spec(search.parse_assumptions(&assumptions), &assumptions);
+}
+
+/// Run the search. `setup` gets to configure the synthesizer.
 fn search_main<'ctx, S>(
     ctx: &'ctx z3::Context,
     should_learn: bool,
-    constraint: Vec<String>,
-    arg: Vec<String>,
-) -> Option<AnswerExpr>
+    cli: &Cli,
+    setup: impl FnOnce(&mut S),
+) -> Option<Vec<AnswerExpr>>
 where
     S: Synthesizer<'ctx>,
 {
-    let mut search = BithackSearch::<S>::new(
+    let mut search = BithackSearch::<S>::with_results(
         should_learn,
-        &ctx,
-        arg,
+        ctx,
+        cli.width,
+        cli.signedness(),
+        &cli.ops(),
+        cli.arg.clone(),
+        cli.results(),
         3,
     );
 
-    search.parse_prompt(&constraint.join("\n"));
+    setup(search.synth_mut());
+    search.set_simplify(!cli.no_simplify);
+    parse_spec(&mut search, cli);
+    if let Some(profile) = cli.cost_profile() {
+        search.set_cost_model(Box::new(profile));
+    }
 
     let mut total_explored = 0;
+    let mut answers = Vec::new();
+    // The steps left to look for a cheaper answer
+    let mut improve_budget = cli.improve;
     while let Some(step) = search.step() {
         total_explored += 1;
 
@@ -77,53 +424,101 @@ where
                 cand,
                 is_universally_wrong,
             } => info!("Explored: {cand:?} bad: {is_universally_wrong}"),
+            search::SearchStep::DuplicateSample {
+                cand,
+            } => info!("Skipped: {cand:?}"),
+            search::SearchStep::CostlySample {
+                cand,
+            } => info!("Too costly: {cand:?}"),
+            search::SearchStep::UnknownSample {
+                cand,
+                reason,
+            } => info!("Inconclusive: {cand:?} ({reason})"),
             search::SearchStep::CorrectSample {
                 cand,
                 answer,
             } => {
                 info!("Explored: {cand:?} answer: {answer:?}");
-                println!("Total explored: {total_explored}");
-                return Some(answer);
+                answers.push(answer);
             },
         }
+
+        if answers.is_empty() {
+            continue;
+        }
+
+        if improve_budget == 0 {
+            break;
+        }
+        improve_budget -= 1;
+    }
+
+    if let Some(profile) = cli.cost_profile() {
+        answers.sort_by_cached_key(|x| total_cost(&profile, x));
+    }
+    if !answers.is_empty() {
+        println!("Total explored: {total_explored}");
+    }
+    if search.inconclusive_count() > 0 {
+        println!("Inconclusive checks: {}", search.inconclusive_count());
     }
 
-    None
+    answers.into_iter().next()
 }
 
-fn search_cli(
-    solver: Synth,
-    timeout: Option<u64>,
-    constraint: Vec<String>,
-    arg: Vec<String>,
-) -> Option<AnswerExpr> {
-    info!("Arguments: {:?}", arg);
-    info!("Constraints: {:?}", constraint);
+fn search_cli(cli: &Cli) -> Option<Vec<AnswerExpr>> {
+    let (width, signedness, ops) = (cli.width, cli.signedness(), cli.ops());
+
+    info!("Width: {width} ({signedness:?})");
+    info!("Operators: {ops:?}");
+    info!("Arguments: {:?}", cli.arg);
+    info!("Results: {:?}", cli.results());
+    info!("Constraints: {:?}", cli.constraint);
+    info!("Assumptions: {:?}", cli.assume);
+    info!("References: {:?}", [cli.reference.as_slice(), &cli.reference_smt].concat());
 
-    let should_learn = solver == Synth::Circuit;
+    let should_learn = cli.solver == Synth::Circuit;
 
-    let mut cfg = z3::Config::default();
+    let ctx = z3::Context::new(&cli.z3_config());
 
-    if let Some(timeout) = timeout {
-        cfg.set_timeout_msec(timeout);
+    match cli.solver {
+        Synth::Brute => search_main::<BruteEnum>(&ctx, should_learn, cli, |_| ()),
+        Synth::Simple => search_main::<SimpleSearch>(&ctx, should_learn, cli, |_| ()),
+        Synth::Circuit => search_main::<CircuitEnum>(&ctx, should_learn, cli, |synth| {
+            synth.add_components(&cli.components())
+        }),
     }
-    let ctx = z3::Context::new(&cfg);
+}
 
-    match solver {
-        Synth::Brute => {
-            search_main::<BruteEnum>(&ctx, should_learn, constraint, arg)
-        },
-        Synth::Simple => {
-            search_main::<SimpleSearch>(&ctx, should_learn, constraint, arg)
-        },
-        Synth::Circuit => {
-            search_main::<CircuitEnum>(&ctx, should_learn, constraint, arg)
-        },
+/// Check the answers given with `--check`, one for every result.
+fn check_cli(cli: &Cli) -> Outcome<()> {
+    if cli.check.len() != cli.results().len() {
+        eprintln!("Expected an answer for each of the results {:?}", cli.results());
+        std::process::exit(2);
     }
+
+    let answer = cli.check.iter()
+        .map(|answer| parse_expr(cli, answer))
+        .collect::<Vec<_>>();
+
+    let ctx = z3::Context::new(&cli.z3_config());
+    let mut search = BithackSearch::<SimpleSearch>::with_results(
+        false,
+        &ctx,
+        cli.width,
+        cli.signedness(),
+        &cli.ops(),
+        cli.arg.clone(),
+        cli.results(),
+        0,
+    );
+    parse_spec(&mut search, cli);
+
+    search.verify_answer(&answer)
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
 
     if cli.verbose {
         colog::default_builder()
@@ -134,8 +529,31 @@ fn main() {
             .init();
     }
 
-    match search_cli(cli.solver, cli.timeout, cli.constraint, cli.arg) {
-        Some(ans) => println!("Found: {ans:}"),
+    cli.load_sygus();
+
+    if !cli.check.is_empty() {
+        let answer = cli.check.join(", ");
+
+        match check_cli(&cli) {
+            Outcome::Found(()) => println!("Correct: {answer}"),
+            Outcome::NotFound => {
+                println!("Incorrect: {answer}");
+                std::process::exit(1);
+            },
+            Outcome::Unknown(reason) => {
+                println!("Unknown: {answer} ({reason})");
+                std::process::exit(3);
+            },
+        }
+
+        return;
+    }
+
+    match search_cli(&cli) {
+        Some(ans) => {
+            cli.report_cost(&ans);
+            println!("{}", cli.emit(&ans));
+        },
         None => println!("No fitting expression found"),
     }
 }
\ No newline at end of file
@@ -1,62 +1,499 @@
-use log::{debug, info};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt;
+
+use log::{debug, info, warn};
 use z3::ast::Ast;
 
-use crate::expr::{ExprVal, BITS_PER_VAL};
+use crate::expr::{val_from_z3, val_to_z3, ExprVal, Signedness};
+
+/// The error produced by [Oracle::parse]. `pos` is the line and the
+/// column of the offending command or symbol, the lines are counted
+/// from 1 and the columns from 0, like z3 does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecError {
+    pub pos: Option<(usize, usize)>,
+    /// The index of the reference the error is in, `pos` is counted
+    /// from its start. See [crate::search::BithackSearch::parse_reference].
+    pub reference: Option<usize>,
+    pub kind: SpecErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecErrorKind {
+    /// Any other error, e.g. an unbalanced parenthesis
+    /// or a command z3 rejects
+    Syntax(String),
+    UnknownSymbol(String),
+    /// An assertion is not a formula, e.g. `(assert x)`
+    NotBoolean,
+    /// A term has the wrong sort, e.g. a variable is compared to a
+    /// constant of another width. Carries the offending command.
+    SortMismatch(String),
+    /// The specification asserts nothing
+    Empty,
+    /// The number of the references differs from the number of the results
+    ReferenceCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(idx) = self.reference {
+            write!(f, "reference {}, ", idx + 1)?;
+        }
+        if let Some((line, column)) = self.pos {
+            write!(f, "line {line}, column {column}: ")?;
+        }
+
+        match &self.kind {
+            SpecErrorKind::Syntax(msg) => write!(f, "{msg}"),
+            SpecErrorKind::UnknownSymbol(x) => write!(f, "unknown symbol {x:?}"),
+            SpecErrorKind::NotBoolean => write!(f, "the assertion is not a formula"),
+            SpecErrorKind::SortMismatch(msg) => write!(f, "sort mismatch: {msg}"),
+            SpecErrorKind::Empty => write!(f, "the specification asserts nothing"),
+            SpecErrorKind::ReferenceCount { expected, found } => {
+                write!(f, "expected {expected} references, found {found}")
+            },
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+/// The outcome of a solver query. Apart from finding the answer or proving
+/// there is none, the solver may give up, e.g. because of the timeout.
+#[derive(Debug)]
+pub enum Outcome<T> {
+    Found(T),
+    NotFound,
+    /// The query was inconclusive, with the reason given by z3
+    Unknown(String),
+}
+
+impl<T> Outcome<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Outcome<U> {
+        match self {
+            Outcome::Found(x) => Outcome::Found(f(x)),
+            Outcome::NotFound => Outcome::NotFound,
+            Outcome::Unknown(reason) => Outcome::Unknown(reason),
+        }
+    }
+
+    /// The answer, if it was found. The inconclusive
+    /// outcome is treated as not finding it.
+    pub fn found(self) -> Option<T> {
+        match self {
+            Outcome::Found(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// The reserved words of the SMTLIB syntax, see [script_error]
+const RESERVED_WORDS: [&str; 9] = ["!", "_", "as", "let", "forall", "exists", "match", "par", "lambda"];
+
+/// A parenthesis or an atom of an SMTLIB script: its byte range
+/// with the line and the column it starts at.
+struct Token {
+    start: usize,
+    end: usize,
+    pos: (usize, usize),
+}
+
+/// Split an SMTLIB script into the top-level commands. Catches the
+/// errors z3 would only report as a failure to read the whole script.
+fn read_commands(src: &str) -> Result<Vec<Vec<Token>>, SpecError> {
+    let mut commands = Vec::new();
+    let mut command: Vec<Token> = Vec::new();
+    let mut depth = 0;
+    let (mut line, mut line_start) = (1, 0);
+    let mut chars = src.char_indices().peekable();
+
+    let err = |pos, msg: &str| SpecError {
+        pos: Some(pos),
+        reference: None,
+        kind: SpecErrorKind::Syntax(msg.to_string()),
+    };
+    while let Some((start, c)) = chars.next() {
+        let pos = (line, src[line_start..start].chars().count());
+        match c {
+            '\n' => {
+                line += 1;
+                line_start = start + 1;
+                continue;
+            },
+            ';' => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                continue;
+            },
+            '\0' => return Err(err(pos, "unexpected nul character")),
+            c if c.is_whitespace() => continue,
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(err(pos, "unexpected \")\"")),
+            ')' => depth -= 1,
+            _ if depth == 0 => return Err(err(pos, "expected a command")),
+            '|' | '"' => {
+                let mut closed = false;
+                while let Some((idx, x)) = chars.next() {
+                    if x == '\n' {
+                        line += 1;
+                        line_start = idx + 1;
+                    }
+                    // A quote inside a string is doubled
+                    if x == c && !(c == '"' && chars.next_if(|(_, x)| *x == '"').is_some()) {
+                        closed = true;
+                        break;
+                    }
+                }
+
+                if !closed {
+                    return Err(err(pos, "unexpected end of input"));
+                }
+            },
+            _ => {
+                while chars.next_if(|(_, x)| !x.is_whitespace() && !"()|\";".contains(*x)).is_some() {}
+            },
+        }
+
+        let end = chars.peek().map_or(src.len(), |(idx, _)| *idx);
+        command.push(Token { start, end, pos });
+        if depth == 0 {
+            commands.push(std::mem::take(&mut command));
+        }
+    }
+
+    match command.first() {
+        Some(token) => Err(err(token.pos, "unexpected end of input")),
+        None => Ok(commands),
+    }
+}
+
+/// Whether z3 knows `symbol` as a sort, a constant or a function of up
+/// to three arguments of one of the sorts a specification at `width`
+/// bits may use. `parse` runs z3.
+fn is_builtin(symbol: &str, width: u32, parse: &impl Fn(&str) -> bool) -> bool {
+    let sorts = ["Bool".to_string(), "Int".to_string(), "Real".to_string(), format!("(_ BitVec {width})")];
+    let app = |args: &[&str]| format!("(assert (let ((|r| ({symbol} {}))) true))", args.join(" "));
+
+    let mut scripts = vec![
+        format!("(declare-const |r| {symbol})"),
+        format!("(assert (let ((|r| {symbol})) true))"),
+    ];
+    for sort in &sorts {
+        let decls = format!("(declare-const |b| Bool) (declare-const |a| {sort})");
+        scripts.extend((1..=3).map(|n| format!("{decls} {}", app(&vec!["|a|"; n]))));
+        scripts.push(format!("{decls} {}", app(&["|b|", "|a|", "|a|"])));
+    }
+
+    scripts.iter().any(|x| parse(x))
+}
+
+/// Tell why z3 rejects the command `commands[idx]` of `src`,
+/// while it accepts the commands before it. `parse` runs z3.
+fn script_error(
+    src: &str,
+    commands: &[Vec<Token>],
+    idx: usize,
+    width: u32,
+    parse: impl Fn(&str) -> bool,
+) -> SpecError {
+    let text = |token: &Token| &src[token.start..token.end];
+    let command = &commands[idx];
+    let head = command.get(1).map_or("", text);
+    let err = |pos, kind| SpecError { pos: Some(pos), reference: None, kind };
+
+    // The declared names and the bound variables, i.e. the ones
+    // opening a list inside a list, like `((x (_ BitVec 8)))`
+    let mut known = RESERVED_WORDS.into_iter().collect::<HashSet<_>>();
+    for tokens in &commands[..=idx] {
+        if let [_, head, name, ..] = &tokens[..] {
+            if ["declare-const", "declare-fun", "define-fun", "define-const"].contains(&text(head)) {
+                known.insert(text(name));
+            }
+        }
+    }
+    for window in command.windows(3) {
+        if text(&window[0]) == "(" && text(&window[1]) == "(" {
+            known.insert(text(&window[2]));
+        }
+    }
+
+    let unknown = command.windows(2).skip(1).map(|x| (text(&x[0]), &x[1])).find(|(prev, token)| {
+        let x = text(token);
+        let is_literal = x.starts_with(|c: char| c.is_ascii_digit() || "#\":()".contains(c));
+
+        !is_literal && !known.contains(x) && *prev != "_" && !prev.starts_with(':')
+            && !is_builtin(x, width, &parse)
+    });
+    if let Some((_, token)) = unknown {
+        return err(token.pos, SpecErrorKind::UnknownSymbol(text(token).to_string()));
+    }
+
+    let prefix = &src[..command[0].start];
+    let body = &src[command[0].end..command[command.len() - 1].start];
+    if head == "assert" {
+        let term = body.trim_start().strip_prefix("assert").unwrap_or(body);
+        if parse(&format!("{prefix}(assert (= {term} {term}))")) {
+            return err(command[0].pos, SpecErrorKind::NotBoolean);
+        }
+    }
+
+    if ["assert", "define-fun", "define-const"].contains(&head) {
+        let command = src[command[0].start..command[command.len() - 1].end].split_whitespace();
+        err(commands[idx][0].pos, SpecErrorKind::SortMismatch(command.collect::<Vec<_>>().join(" ")))
+    } else {
+        err(command[0].pos, SpecErrorKind::Syntax(format!("invalid {head}")))
+    }
+}
 
 /// The verification oracle. It is a thin wrapper around
 /// the Z3 smt solver.
 pub struct Oracle<'ctx> {
     z3: &'ctx z3::Context,
+    width: u32,
+    signedness: Signedness,
     solver: z3::Solver<'ctx>,
-    result_var: z3::ast::BV<'ctx>,
+    result_vars: Vec<z3::ast::BV<'ctx>>,
     constraints: Vec<z3::ast::Bool<'ctx>>,
+    /// The terms the results must equal, if the specification is a
+    /// function of the arguments. See [Oracle::set_reference].
+    reference: Option<Vec<z3::ast::BV<'ctx>>>,
+    /// The preconditions on the arguments. The specification
+    /// only has to hold, where they are met.
+    assumptions: Vec<z3::ast::Bool<'ctx>>,
+    timeout: Option<u32>,
+    retries: u32,
+    /// The number of inconclusive queries so far
+    inconclusive: Cell<usize>,
 }
 
 impl<'ctx> Oracle<'ctx> {
+    /// Constructs the oracle for a specification over the result
+    /// variables named `results`.
     pub fn new(
-        z3: &'ctx z3::Context
+        z3: &'ctx z3::Context,
+        width: u32,
+        signedness: Signedness,
+        results: &[String],
     ) -> Self {
         Self {
             z3,
+            width,
+            signedness,
             constraints: Vec::new(),
+            reference: None,
+            assumptions: Vec::new(),
             solver: z3::Solver::new(z3),
-            result_var: z3::ast::BV::new_const(
-                &z3,
-                "res",
-                BITS_PER_VAL,
-            ),
+            result_vars: results.iter()
+                .map(|x| z3::ast::BV::new_const(z3, x.as_str(), width))
+                .collect(),
+            timeout: None,
+            retries: 0,
+            inconclusive: Cell::new(0),
+        }
+    }
+
+    /// Limit every query to `timeout` milliseconds, overriding the
+    /// timeout of the context.
+    pub fn set_timeout(&mut self, timeout: Option<u32>) {
+        self.timeout = timeout;
+        self.apply_timeout(timeout);
+    }
+
+    /// Repeat an inconclusive query up to `retries` times, doubling the
+    /// timeout every time. Has no effect without [Oracle::set_timeout].
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// How many queries were inconclusive, the retried ones included.
+    pub fn inconclusive_count(&self) -> usize {
+        self.inconclusive.get()
+    }
+
+    fn apply_timeout(&self, timeout: Option<u32>) {
+        if let Some(timeout) = timeout {
+            let mut params = z3::Params::new(self.z3);
+            params.set_u32("timeout", timeout);
+            self.solver.set_params(&params);
+        }
+    }
+
+    /// Check the assertions of the solver, retrying
+    /// the inconclusive checks as configured.
+    fn check(&self) -> Outcome<z3::Model<'ctx>> {
+        let mut timeout = self.timeout;
+        let mut retries = 0;
+
+        let res = loop {
+            match self.solver.check() {
+                z3::SatResult::Sat => break Outcome::Found(
+                    self.solver.get_model().expect("Model must exist")
+                ),
+                z3::SatResult::Unsat => break Outcome::NotFound,
+                z3::SatResult::Unknown => {
+                    let reason = self.solver.get_reason_unknown().unwrap_or_default();
+                    self.inconclusive.set(self.inconclusive.get() + 1);
+
+                    match timeout {
+                        Some(x) if retries < self.retries => {
+                            timeout = Some(x.saturating_mul(2));
+                            retries += 1;
+                            info!("Inconclusive ({reason}), retrying with {}ms", x.saturating_mul(2));
+                            self.apply_timeout(timeout);
+                        },
+                        _ => break Outcome::Unknown(reason),
+                    }
+                },
+            }
+        };
+
+        if retries > 0 {
+            self.apply_timeout(self.timeout);
         }
+
+        res
     }
 
-    pub fn result_var(&self) -> &z3::ast::BV<'ctx> {
-        &self.result_var
+    pub fn result_vars(&self) -> &[z3::ast::BV<'ctx>] {
+        &self.result_vars
     }
 
     pub fn add_constraint(&mut self, constraint: z3::ast::Bool<'ctx>) {
         self.constraints.push(constraint);
+        self.reference = None;
     }
 
-    pub fn parse(&mut self, str: String) {
-        // This is SHIT. Don't do this kids!
-        info!("Going to parse: {str}");
-
-        self.solver.reset();
-        self.solver.from_string(str.as_bytes());
-        self.constraints = self.solver.get_assertions().into_iter()
-            .map(|x| unsafe {
-                z3::ast::Bool::wrap(
-                    &self.z3,
-                    x.get_z3_ast()
-                )
-            })
+    /// Make the specification a function of the arguments: every result
+    /// must equal its term of `reference`, the other constraints are
+    /// dropped. Knowing the function lets the oracle compute the expected
+    /// results directly and check the candidates without quantifying
+    /// over the results.
+    pub fn set_reference(&mut self, reference: Vec<z3::ast::BV<'ctx>>) {
+        assert_eq!(reference.len(), self.result_vars.len(), "Wrong number of results");
+
+        self.constraints = self.result_vars.iter()
+            .zip(&reference)
+            .map(|(x, y)| x._eq(y))
             .collect();
+        self.reference = Some(reference);
+
+        info!("Reference: {:?}", self.reference);
+    }
+
+    pub fn reference(&self) -> Option<&[z3::ast::BV<'ctx>]> {
+        self.reference.as_deref()
+    }
 
-        // This is even dirtier than the guy before it!
-        assert!(!self.constraints.is_empty(), "Syntax error");
+    /// Read the constraints from an SMTLIB script, which declares all
+    /// the variables it uses.
+    pub fn parse(&mut self, str: String) -> Result<(), SpecError> {
+        let constraints = self.read_assertions(str)?;
 
-        self.solver.reset();
+        if constraints.is_empty() {
+            return Err(SpecError { pos: None, reference: None, kind: SpecErrorKind::Empty });
+        }
+        self.constraints = constraints;
+        self.reference = None;
 
         info!("Input constraints: {:?}", self.constraints);
+
+        Ok(())
+    }
+
+    /// Restrict the inputs to the ones meeting `assumption`. The
+    /// counterexamples meet all the assumptions and the candidates
+    /// only have to be correct on such inputs. An assumption must
+    /// only mention the arguments.
+    pub fn add_assumption(&mut self, assumption: z3::ast::Bool<'ctx>) {
+        self.assumptions.push(assumption);
+    }
+
+    /// Read the assumptions from an SMTLIB script, which declares all
+    /// the variables it uses. Replaces the previous assumptions, a script
+    /// without assertions clears them. See [Oracle::add_assumption].
+    pub fn parse_assumptions(&mut self, str: String) -> Result<(), SpecError> {
+        self.assumptions = self.read_assertions(str)?;
+
+        info!("Assumptions: {:?}", self.assumptions);
+
+        Ok(())
+    }
+
+    pub fn assumptions(&self) -> &[z3::ast::Bool<'ctx>] {
+        &self.assumptions
+    }
+
+    /// Read a term from an SMTLIB script, which ends with an assertion
+    /// of the form `(= y term)`. The constraints stay intact.
+    pub fn parse_term(&self, str: String) -> Result<z3::ast::BV<'ctx>, SpecError> {
+        let not_term = || SpecError {
+            pos: None,
+            reference: None,
+            kind: SpecErrorKind::Syntax("expected an equality to a bitvector term".to_string()),
+        };
+        let assertion = self.read_assertions(str)?
+            .pop()
+            .ok_or(SpecError { pos: None, reference: None, kind: SpecErrorKind::Empty })?;
+
+        if !assertion.safe_decl().is_ok_and(|x| x.kind() == z3::DeclKind::EQ) {
+            return Err(not_term());
+        }
+
+        assertion.nth_child(1)
+            .and_then(|x| x.as_bv())
+            .ok_or_else(not_term)
+    }
+
+    fn read_assertions(&self, str: String) -> Result<Vec<z3::ast::Bool<'ctx>>, SpecError> {
+        info!("Going to parse: {str}");
+
+        let commands = read_commands(&str)?;
+        if let Some(assertions) = self.read_script(&str) {
+            return Ok(assertions);
+        }
+
+        // The scripts ending before the rejected command are accepted
+        let idx = commands.partition_point(|x| self.read_script(&str[..x[x.len() - 1].end]).is_some());
+        if idx == commands.len() {
+            return Err(SpecError {
+                pos: None,
+                reference: None,
+                kind: SpecErrorKind::Syntax("invalid script".to_string()),
+            });
+        }
+
+        Err(script_error(&str, &commands, idx, self.width, |x| self.read_script(x).is_some()))
+    }
+
+    /// The assertions of an SMTLIB script, if z3 accepts it. z3 reads
+    /// nothing from a malformed script, so a true assertion is added
+    /// to tell it from the one without assertions.
+    fn read_script(&self, src: &str) -> Option<Vec<z3::ast::Bool<'ctx>>> {
+        let parser = z3::Solver::new(self.z3);
+        parser.from_string(format!("{src}\n(assert true)"));
+        let mut assertions = parser.get_assertions();
+        assertions.pop()?;
+
+        // The solver only lends its assertions out, they are brought
+        // over to the context through a goal. It splits the conjunctions,
+        // so they are joined back to keep one term per assertion.
+        let assertions = assertions.iter()
+            .map(|x| {
+                let goal = z3::Goal::new(self.z3, false, false, false);
+                goal.assert(x);
+
+                match &goal.get_formulas::<z3::ast::Bool>()[..] {
+                    [x] => x.clone(),
+                    xs => z3::ast::Bool::and(self.z3, &xs.iter().collect::<Vec<_>>()),
+                }
+            })
+            .collect();
+
+        Some(assertions)
     }
 
     /// Attempt to construct a counter example.
@@ -65,28 +502,26 @@ impl<'ctx> Oracle<'ctx> {
     /// and verification constraint `phi(x, y)`, the checked statement is:
     ///
     /// `exists x, forall c y, y = f(c, x) => ~ phi (x, y)`
+    ///
+    /// With several results, `f` and `y` are tuples of the same length.
+    /// With the assumptions `pre(x)`, only `x` meeting them counts.
     pub fn counterexample<'a>(
         &'a self,
-        z3_cand: &'a z3::ast::BV<'ctx>,
+        z3_cand: &'a [z3::ast::BV<'ctx>],
         z3_consts: impl IntoIterator<Item = &'a z3::ast::BV<'ctx>>,
-    ) -> Option<z3::Model<'ctx>>
+    ) -> Outcome<z3::Model<'ctx>>
     where
         'ctx: 'a,
     {
-        let mut answer = None;
         debug!("Searching for universal counter-example");
 
-        let specif = self.counter_specif(&z3_cand, z3_consts);
+        let specif = self.counter_specif(z3_cand, z3_consts);
 
         self.solver.push();
         self.solver.assert(&specif);
-        let z3_verdict = self.solver.check();
-
-        debug!("Z3 counterexample search: {z3_verdict:?}");
+        let answer = self.check();
 
-        if z3_verdict == z3::SatResult::Sat {
-            answer = Some(self.solver.get_model().expect("Model must exist"));
-        }
+        debug!("Z3 counterexample search: {answer:?}");
 
         self.solver.pop(1);
 
@@ -100,35 +535,47 @@ impl<'ctx> Oracle<'ctx> {
     /// Given a fixed input X verification constraint `phi(x, y)`, the checked statement is:
     ///
     /// exists y, phi(X, y)
+    ///
+    /// The input is expected to meet the assumptions, if there are any.
+    /// Returns a value for every result. Not finding them means, that
+    /// no result is valid for the input. With a reference, the values
+    /// are computed without querying the solver.
     pub fn suitable_value<'a>(
         &self,
         z3_args: impl IntoIterator<Item = &'a z3::ast::BV<'ctx>>,
         z3_arg_values: impl IntoIterator<Item = ExprVal>,
-    ) -> ExprVal
+    ) -> Outcome<Vec<ExprVal>>
     where
         'ctx: 'a,
     {
         debug!("Generating a valid value");
 
+        let z3_args = z3_args.into_iter().collect::<Vec<_>>();
+        let z3_arg_values = z3_arg_values.into_iter().collect::<Vec<_>>();
+        if let Some(values) = self.evaluate_reference(&z3_args, &z3_arg_values) {
+            return Outcome::Found(values);
+        }
+
         self.solver.push();
 
-        self.solver.assert(&z3::ast::Bool::and(&self.z3,
-            self.constraints.iter().collect::<Vec<_>>().as_slice()
+        self.solver.assert(&z3::ast::Bool::and(self.z3,
+            self.constraints.iter().chain(&self.assumptions).collect::<Vec<_>>().as_slice()
         ));
-        for (arg, val) in z3_args.into_iter().zip(z3_arg_values.into_iter()) {
+        for (arg, val) in z3_args.into_iter().zip(z3_arg_values) {
             self.solver.assert(&arg._eq(
-                &z3::ast::BV::from_i64(&self.z3, val as i64, BITS_PER_VAL)
+                &val_to_z3(self.z3, val, self.width)
             ));
         }
 
-        assert!(self.solver.check() == z3::SatResult::Sat);
+        let ans = self.check().map(|model| self.result_vars.iter()
+            .map(|x| model.eval(x, true)
+                .and_then(|x| val_from_z3(&x, self.signedness))
+                .unwrap())
+            .collect());
 
-        let ans = self.solver.get_model()
-            .unwrap()
-            .get_const_interp(&self.result_var)
-            .unwrap()
-            .as_i64()
-            .unwrap() as ExprVal;
+        if let Outcome::NotFound = ans {
+            warn!("The specification has no valid result for the input");
+        }
 
         self.solver.pop(1);
 
@@ -141,43 +588,63 @@ impl<'ctx> Oracle<'ctx> {
     /// and verification constraint `phi(x, y)`, the checked statement is:
     ///
     /// `exists c, forall x y, y = f(c, x) => phi (x, y)`
+    ///
+    /// With several results, `f` and `y` are tuples of the same length.
+    /// With a reference `g(x)`, it becomes `exists c, forall x, f(c, x) = g(x)`.
+    /// With the assumptions `pre(x)`, the statement only
+    /// has to hold for `x` meeting them.
     pub fn check_candidate<'a>(
         &'a self,
-        z3_cand: &'a z3::ast::BV<'ctx>,
+        z3_cand: &'a [z3::ast::BV<'ctx>],
         z3_args: impl IntoIterator<Item = &'a z3::ast::BV<'ctx>>,
-    ) -> Option<z3::Model<'ctx>>
+    ) -> Outcome<z3::Model<'ctx>>
     where
         'ctx: 'a,
     {
         debug!("Checking the candidate");
 
-        let mut answer = None;
-
         self.solver.push();
-        let specif = self.candidate_specif(&z3_cand, z3_args);
+        let specif = self.candidate_specif(z3_cand, z3_args);
         self.solver.assert(&specif);
-        let z3_verdict = self.solver.check();
+        let answer = self.check();
 
-        debug!("Z3 verdict: {z3_verdict:?}");
-
-        if z3_verdict == z3::SatResult::Sat {
-            answer = Some(self.solver.get_model().expect("Model must exist"));
-        }
+        debug!("Z3 verdict: {answer:?}");
 
         self.solver.pop(1);
 
         answer
     }
 
+    /// The reference applied to the input, unless
+    /// there's no reference or z3 fails to fold it.
+    fn evaluate_reference(
+        &self,
+        z3_args: &[&z3::ast::BV<'ctx>],
+        z3_arg_values: &[ExprVal],
+    ) -> Option<Vec<ExprVal>> {
+        let z3_arg_values = z3_arg_values.iter()
+            .map(|x| val_to_z3(self.z3, *x, self.width))
+            .collect::<Vec<_>>();
+        let substitutions = z3_args.iter()
+            .copied()
+            .zip(&z3_arg_values)
+            .collect::<Vec<_>>();
+
+        self.reference.as_ref()?
+            .iter()
+            .map(|x| val_from_z3(&x.substitute(&substitutions).simplify(), self.signedness))
+            .collect()
+    }
+
     fn counter_specif<'a>(
         &'a self,
-        cand: &z3::ast::BV<'ctx>,
+        cand: &[z3::ast::BV<'ctx>],
         z3_consts: impl IntoIterator<Item = &'a z3::ast::BV<'ctx>>,
     ) -> z3::ast::Bool<'ctx> {
         let cand_constr = self.counter_constraint(cand);
 
         z3::ast::forall_const(
-            &self.z3,
+            self.z3,
             &z3_consts.into_iter()
                 .map(|x| x as &dyn z3::ast::Ast)
                 .collect::<Vec<_>>()
@@ -189,7 +656,7 @@ impl<'ctx> Oracle<'ctx> {
 
     fn candidate_specif<'a>(
         &'a self,
-        cand: &z3::ast::BV<'ctx>,
+        cand: &[z3::ast::BV<'ctx>],
         z3_args: impl IntoIterator<Item = &'a z3::ast::BV<'ctx>>,
     ) -> z3::ast::Bool<'ctx>
     where
@@ -198,9 +665,10 @@ impl<'ctx> Oracle<'ctx> {
         let cand_constr = self.cand_constraint(cand);
 
         z3::ast::forall_const(
-            &self.z3,
+            self.z3,
             &z3_args.into_iter()
-                .chain(std::iter::once(&self.result_var))
+                // A reference doesn't mention the results
+                .chain(self.reference.is_none().then_some(&self.result_vars).into_iter().flatten())
                 .map(|x| x as &dyn z3::ast::Ast)
                 .collect::<Vec<_>>()
             ,
@@ -209,21 +677,55 @@ impl<'ctx> Oracle<'ctx> {
         )
     }
 
-    fn counter_constraint(&self, cand: &z3::ast::BV<'ctx>) -> z3::ast::Bool<'ctx> {
-        let candeq = cand._eq(self.result_var());
-        let specif = z3::ast::Bool::and(&self.z3,
+    /// The results are the values of the candidate
+    fn cand_eq(&self, cand: &[z3::ast::BV<'ctx>]) -> z3::ast::Bool<'ctx> {
+        assert_eq!(cand.len(), self.result_vars.len(), "Wrong number of results");
+
+        let eqs = cand.iter()
+            .zip(&self.result_vars)
+            .map(|(x, y)| x._eq(y))
+            .collect::<Vec<_>>();
+
+        z3::ast::Bool::and(self.z3, &eqs.iter().collect::<Vec<_>>())
+    }
+
+    /// The candidate computes the reference
+    fn cand_eq_reference(&self, cand: &[z3::ast::BV<'ctx>]) -> Option<z3::ast::Bool<'ctx>> {
+        let reference = self.reference.as_ref()?;
+        assert_eq!(cand.len(), reference.len(), "Wrong number of results");
+
+        let eqs = cand.iter()
+            .zip(reference)
+            .map(|(x, y)| x._eq(y))
+            .collect::<Vec<_>>();
+
+        Some(z3::ast::Bool::and(self.z3, &eqs.iter().collect::<Vec<_>>()))
+    }
+
+    /// The input meets the assumptions
+    fn assumed(&self) -> z3::ast::Bool<'ctx> {
+        z3::ast::Bool::and(self.z3, &self.assumptions.iter().collect::<Vec<_>>())
+    }
+
+    fn counter_constraint(&self, cand: &[z3::ast::BV<'ctx>]) -> z3::ast::Bool<'ctx> {
+        let candeq = self.cand_eq(cand);
+        let specif = z3::ast::Bool::and(self.z3,
             self.constraints.iter().collect::<Vec<_>>().as_slice()
         );
 
-        z3::ast::Bool::and(&self.z3, &[&candeq.clone(), &!specif.clone()])
+        z3::ast::Bool::and(self.z3, &[&self.assumed(), &candeq.clone(), &!specif.clone()])
     }
 
-    fn cand_constraint(&self, cand: &z3::ast::BV<'ctx>) -> z3::ast::Bool<'ctx> {
-        let candeq = cand._eq(self.result_var());
-        let specif = z3::ast::Bool::and(&self.z3,
+    fn cand_constraint(&self, cand: &[z3::ast::BV<'ctx>]) -> z3::ast::Bool<'ctx> {
+        if let Some(eq) = self.cand_eq_reference(cand) {
+            return self.assumed().implies(&eq);
+        }
+
+        let candeq = self.cand_eq(cand);
+        let specif = z3::ast::Bool::and(self.z3,
             self.constraints.iter().collect::<Vec<_>>().as_slice()
         );
 
-        candeq.implies(&specif)
+        z3::ast::Bool::and(self.z3, &[&self.assumed(), &candeq]).implies(&specif)
     }
 }
\ No newline at end of file
@@ -0,0 +1,479 @@
+use std::{fmt, num::IntErrorKind, rc::Rc};
+
+use crate::expr::{
+    AnswerExpr, BinopKind, BoolExpr, CmpKind, Expr, ExprVal, Signedness, UnopKind, Value,
+    MAX_BITS_PER_VAL,
+};
+
+/// The error produced by [parse_answer]. `pos` is the byte offset into
+/// the parsed string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedEnd,
+    UnexpectedChar(char),
+    UnexpectedToken {
+        expected: &'static str,
+        found: String,
+    },
+    ConstantOutOfRange(String),
+    UnknownArgument(String),
+    /// A comparison was used where a value is expected
+    ExpectedValue,
+    /// A value was used as an if-then-else condition
+    ExpectedCondition,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at {}: ", self.pos)?;
+
+        match &self.kind {
+            ParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "unexpected character {c:?}"),
+            ParseErrorKind::UnexpectedToken { expected, found } =>
+                write!(f, "expected {expected}, found {found:?}"),
+            ParseErrorKind::ConstantOutOfRange(c) => write!(f, "constant {c} does not fit"),
+            ParseErrorKind::UnknownArgument(arg) => write!(f, "unknown argument {arg:?}"),
+            ParseErrorKind::ExpectedValue => write!(f, "expected a value, found a comparison"),
+            ParseErrorKind::ExpectedCondition => write!(f, "expected a comparison, found a value"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    Question,
+    Colon,
+    Op(&'static str),
+    Ident(String),
+    Num(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Question => write!(f, "?"),
+            Token::Colon => write!(f, ":"),
+            Token::Op(op) => write!(f, "{op}"),
+            Token::Ident(x) | Token::Num(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+// NOTE: the order matters, longer operators must come first
+const OPERATORS: [&str; 17] = [
+    ">>>", ">>", "<<", "==", "<u", "/u", "%u",
+    "<", "/", "%", "&", "|", "^", "+", "-", "*", "!",
+];
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut res = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = src[pos..].chars().next() {
+        let rest = &src[pos..];
+
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        }
+
+        let simple = match c {
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            '?' => Some(Token::Question),
+            ':' => Some(Token::Colon),
+            _ => None,
+        };
+        if let Some(tok) = simple {
+            res.push((pos, tok));
+            pos += 1;
+            continue;
+        }
+
+        if is_ident_char(c) {
+            let len = rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len());
+            let word = rest[..len].to_string();
+            let tok = if c.is_ascii_digit() {
+                Token::Num(word)
+            } else {
+                Token::Ident(word)
+            };
+
+            res.push((pos, tok));
+            pos += len;
+            continue;
+        }
+
+        // `<u` and friends must not swallow the start of an identifier
+        let op = OPERATORS.into_iter()
+            .filter(|op| rest.starts_with(op))
+            .find(|op| !op.ends_with('u') || !rest[op.len()..].starts_with(is_ident_char));
+        let Some(op) = op else {
+            return Err(ParseError {
+                pos,
+                kind: ParseErrorKind::UnexpectedChar(c),
+            });
+        };
+
+        res.push((pos, Token::Op(op)));
+        pos += op.len();
+    }
+
+    Ok(res)
+}
+
+/// Result of parsing a parenthesised group, which may be either a value
+/// or the condition of an if-then-else.
+enum Term {
+    Value(AnswerExpr),
+    Cond(BoolExpr<Value>),
+}
+
+/// The work left to do on a term, once its current operand is parsed.
+/// See [Parser::term].
+enum Frame {
+    /// The operand must be a value, not a comparison. `pos` is where it starts.
+    Value(usize),
+    Unop(UnopKind),
+    /// The first term of a group, which starts at `pos`
+    Group(usize),
+    /// The right operand of a binary operation
+    Binop(BinopKind, AnswerExpr),
+    /// The right operand of a comparison
+    Cmp(CmpKind, AnswerExpr),
+    /// The then branch of an if-then-else
+    Then(BoolExpr<Value>),
+    /// The else branch of an if-then-else
+    Else(BoolExpr<Value>, AnswerExpr),
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+    args: &'a [String],
+    width: u32,
+    signedness: Signedness,
+}
+
+impl Parser<'_> {
+    fn pos(&self) -> usize {
+        self.tokens.get(self.next)
+            .map(|(pos, _)| *pos)
+            .unwrap_or(self.end)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, tok)| tok)
+    }
+
+    fn bump(&mut self) -> Result<Token, ParseError> {
+        let tok = self.peek().cloned().ok_or(ParseError {
+            pos: self.pos(),
+            kind: ParseErrorKind::UnexpectedEnd,
+        })?;
+
+        self.next += 1;
+
+        Ok(tok)
+    }
+
+    fn unexpected<T>(&self, expected: &'static str) -> Result<T, ParseError> {
+        Err(ParseError {
+            pos: self.pos(),
+            kind: match self.peek() {
+                None => ParseErrorKind::UnexpectedEnd,
+                Some(tok) => ParseErrorKind::UnexpectedToken {
+                    expected,
+                    found: tok.to_string(),
+                },
+            },
+        })
+    }
+
+    fn expect(&mut self, tok: Token, expected: &'static str) -> Result<(), ParseError> {
+        if self.peek() != Some(&tok) {
+            return self.unexpected(expected);
+        }
+
+        self.next += 1;
+
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<AnswerExpr, ParseError> {
+        let pos = self.pos();
+
+        match self.term()? {
+            Term::Value(x) => Ok(x),
+            Term::Cond(_) => Err(ParseError {
+                pos,
+                kind: ParseErrorKind::ExpectedValue,
+            }),
+        }
+    }
+
+    /// Parses a term. The nested terms are kept on a stack of
+    /// their own, so the depth of the term doesn't matter.
+    fn term(&mut self) -> Result<Term, ParseError> {
+        let mut frames = Vec::new();
+
+        'read: loop {
+            let Some(mut term) = self.open_term(&mut frames)? else {
+                continue;
+            };
+
+            // Complete the pending terms, until one needs another operand
+            while let Some(frame) = frames.pop() {
+                let value = |term| match term {
+                    Term::Value(x) => x,
+                    Term::Cond(_) => unreachable!("checked by Frame::Value"),
+                };
+
+                term = match frame {
+                    Frame::Value(pos) => match term {
+                        Term::Value(_) => term,
+                        Term::Cond(_) => return Err(ParseError {
+                            pos,
+                            kind: ParseErrorKind::ExpectedValue,
+                        }),
+                    },
+                    Frame::Unop(kind) => {
+                        self.expect(Token::RParen, ")")?;
+                        Term::Value(Expr::Unop(kind, Rc::new(value(term))))
+                    },
+                    Frame::Group(pos) => {
+                        self.group(pos, term, &mut frames)?;
+                        continue 'read;
+                    },
+                    Frame::Binop(kind, l) => {
+                        self.expect(Token::RParen, ")")?;
+                        Term::Value(Expr::Binop(kind, Rc::new(l), Rc::new(value(term))))
+                    },
+                    Frame::Cmp(kind, l) => {
+                        self.expect(Token::RParen, ")")?;
+                        Term::Cond(BoolExpr::Cmp(kind, Rc::new(l), Rc::new(value(term))))
+                    },
+                    Frame::Then(cond) => {
+                        self.expect(Token::Colon, ":")?;
+                        frames.push(Frame::Else(cond, value(term)));
+                        frames.push(Frame::Value(self.pos()));
+                        continue 'read;
+                    },
+                    Frame::Else(cond, t) => {
+                        self.expect(Token::RParen, ")")?;
+                        Term::Value(Expr::Ite(cond, Rc::new(t), Rc::new(value(term))))
+                    },
+                };
+            }
+
+            return Ok(term);
+        }
+    }
+
+    /// Reads the start of a term. Returns the term, if it is complete,
+    /// or pushes the work left to do otherwise.
+    fn open_term(&mut self, frames: &mut Vec<Frame>) -> Result<Option<Term>, ParseError> {
+        let pos = self.pos();
+
+        let kind = match self.bump()? {
+            Token::LParen => {
+                frames.push(Frame::Group(self.pos()));
+                return Ok(None);
+            },
+            Token::Num(num) => return self.constant(pos, false, &num).map(|x| Some(Term::Value(x))),
+            Token::Op("-") if matches!(self.peek(), Some(Token::Num(_))) => {
+                let Token::Num(num) = self.bump()? else { unreachable!() };
+
+                return self.constant(pos, true, &num).map(|x| Some(Term::Value(x)));
+            },
+            Token::Op("-") => UnopKind::Negate,
+            Token::Op("!") => UnopKind::Not,
+            Token::Ident(name) if self.peek() == Some(&Token::LParen) => {
+                match name.as_str() {
+                    "popcnt" => UnopKind::Popcnt,
+                    "clz" => UnopKind::Clz,
+                    "ctz" => UnopKind::Ctz,
+                    "bswap" => UnopKind::Bswap,
+                    _ => {
+                        self.next -= 1;
+                        return self.unexpected("an intrinsic");
+                    },
+                }
+            },
+            Token::Ident(name) => {
+                if !self.args.contains(&name) {
+                    return Err(ParseError {
+                        pos,
+                        kind: ParseErrorKind::UnknownArgument(name),
+                    });
+                }
+
+                return Ok(Some(Term::Value(Expr::Variable(Value::Arg(name)))));
+            },
+            _ => {
+                self.next -= 1;
+                return self.unexpected("an expression");
+            },
+        };
+
+        self.expect(Token::LParen, "(")?;
+        frames.push(Frame::Unop(kind));
+        frames.push(Frame::Value(self.pos()));
+
+        Ok(None)
+    }
+
+    /// Continues a group at `pos` after its first term. This is
+    /// either a binary operation, a comparison or an if-then-else.
+    fn group(&mut self, pos: usize, first: Term, frames: &mut Vec<Frame>) -> Result<(), ParseError> {
+        match first {
+            Term::Cond(cond) => {
+                self.expect(Token::Question, "?")?;
+                frames.push(Frame::Then(cond));
+            },
+            Term::Value(_) if self.peek() == Some(&Token::Question) => {
+                return Err(ParseError {
+                    pos,
+                    kind: ParseErrorKind::ExpectedCondition,
+                });
+            },
+            Term::Value(l) => {
+                let op = match self.peek() {
+                    Some(Token::Op(op)) => *op,
+                    Some(Token::Ident(op)) if op == "rotl" => "rotl",
+                    Some(Token::Ident(op)) if op == "rotr" => "rotr",
+                    _ => return self.unexpected("an operator"),
+                };
+                let frame = match (binop_of(op), cmp_of(op)) {
+                    (Some(binop), _) => Frame::Binop(binop, l),
+                    (_, Some(cmp)) => Frame::Cmp(cmp, l),
+                    (None, None) => return self.unexpected("an operator"),
+                };
+
+                self.next += 1;
+                frames.push(frame);
+            },
+        }
+
+        frames.push(Frame::Value(self.pos()));
+
+        Ok(())
+    }
+
+    fn constant(&self, pos: usize, negative: bool, num: &str) -> Result<AnswerExpr, ParseError> {
+        let out_of_range = || ParseError {
+            pos,
+            kind: ParseErrorKind::ConstantOutOfRange(
+                format!("{}{num}", if negative { "-" } else { "" })
+            ),
+        };
+        let magnitude = match num.strip_prefix("0x").or_else(|| num.strip_prefix("0X")) {
+            Some(hex) => u128::from_str_radix(hex, 16),
+            None => num.parse::<u128>(),
+        };
+        let magnitude = magnitude.map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow => out_of_range(),
+            _ => ParseError {
+                pos,
+                kind: ParseErrorKind::UnexpectedToken {
+                    expected: "a number",
+                    found: num.to_string(),
+                },
+            },
+        })?;
+
+        // The constant may be written either as a signed or as an unsigned number
+        let fits = if negative {
+            magnitude <= 1 << (self.width - 1)
+        } else {
+            self.width == MAX_BITS_PER_VAL || magnitude < 1 << self.width
+        };
+        if !fits {
+            return Err(out_of_range());
+        }
+
+        let val = if negative {
+            (magnitude as ExprVal).wrapping_neg()
+        } else {
+            magnitude as ExprVal
+        };
+
+        Ok(Expr::Variable(Value::Const(self.signedness.truncate(val, self.width))))
+    }
+}
+
+fn cmp_of(op: &str) -> Option<CmpKind> {
+    Some(match op {
+        "==" => CmpKind::Eq,
+        "<" => CmpKind::Slt,
+        "<u" => CmpKind::Ult,
+        _ => return None,
+    })
+}
+
+fn binop_of(op: &str) -> Option<BinopKind> {
+    Some(match op {
+        "&" => BinopKind::And,
+        "|" => BinopKind::Or,
+        "^" => BinopKind::Xor,
+        "+" => BinopKind::Plus,
+        "-" => BinopKind::Minus,
+        "<<" => BinopKind::Shl,
+        ">>" => BinopKind::ShrA,
+        ">>>" => BinopKind::ShrL,
+        "*" => BinopKind::Mul,
+        "/" => BinopKind::SDiv,
+        "/u" => BinopKind::UDiv,
+        "%" => BinopKind::SRem,
+        "%u" => BinopKind::URem,
+        "rotl" => BinopKind::Rotl,
+        "rotr" => BinopKind::Rotr,
+        _ => return None,
+    })
+}
+
+/// Parse an expression written in the syntax of `Display for AnswerExpr`.
+///
+/// The variables must be present in `args`. The constants may be decimal or
+/// hexadecimal (`0x`), optionally negated, and must fit into `width` bits either
+/// as a signed or as an unsigned number. They get stored according to `signedness`.
+pub fn parse_answer(
+    src: &str,
+    args: &[String],
+    width: u32,
+    signedness: Signedness,
+) -> Result<AnswerExpr, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        next: 0,
+        end: src.len(),
+        args,
+        width,
+        signedness,
+    };
+
+    let res = parser.value()?;
+    if parser.peek().is_some() {
+        return parser.unexpected("end of input");
+    }
+
+    Ok(res)
+}
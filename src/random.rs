@@ -0,0 +1,324 @@
+use std::rc::Rc;
+
+use crate::expr::{
+    AnswerExpr, BinopKind, BoolExpr, CmpKind, Expr, ExprVal, OpSet, Signedness, UnopKind,
+    Value, Variable,
+};
+
+/// A linear congruential generator. It needs no dependencies and gives
+/// the same sequence for the same seed on every platform, so anything
+/// generated from a seed can be reproduced.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+
+        self.0
+    }
+
+    /// A number from `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() >> 33) as usize % n
+    }
+
+    /// An index into `weights`, picked with the probability
+    /// proportional to its weight.
+    pub fn pick(&mut self, weights: &[u32]) -> usize {
+        let total = weights.iter().map(|x| *x as u64).sum::<u64>();
+        assert!(total > 0, "All the weights are zero");
+
+        let mut roll = (self.next_u64() >> 1) % total;
+        for (idx, weight) in weights.iter().enumerate() {
+            match roll.checked_sub(*weight as u64) {
+                Some(rest) => roll = rest,
+                None => return idx,
+            }
+        }
+
+        unreachable!()
+    }
+
+    fn next_u128(&mut self) -> u128 {
+        (self.next_u64() as u128) << 64 | self.next_u64() as u128
+    }
+
+    /// Split `total` into `parts` random summands.
+    fn split(&mut self, total: usize, parts: usize) -> Vec<usize> {
+        let mut cuts = (1..parts)
+            .map(|_| self.below(total + 1))
+            .collect::<Vec<_>>();
+        cuts.push(0);
+        cuts.push(total);
+        cuts.sort_unstable();
+
+        cuts.windows(2).map(|x| x[1] - x[0]).collect()
+    }
+}
+
+/// How the constants of the generated expressions are distributed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConstDist {
+    /// Mostly the edge cases of the width: zero, one, all ones, the sign
+    /// bit, shift amounts around the width and small numbers
+    #[default]
+    Edges,
+    /// Any value of the width
+    Uniform,
+    /// A value from `lo..=hi`, truncated to the width
+    Range(ExprVal, ExprVal),
+}
+
+/// The relative weights of the node kinds in the generated expressions.
+/// A kind, which can't be generated, e.g. an argument when there are
+/// none or an operator missing from the [OpSet], is never picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Weights {
+    pub argument: u32,
+    pub constant: u32,
+    pub unop: u32,
+    pub binop: u32,
+    pub ite: u32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            argument: 2,
+            constant: 1,
+            unop: 2,
+            binop: 5,
+            ite: 2,
+        }
+    }
+}
+
+/// How much of the expression is left to generate.
+#[derive(Clone, Copy)]
+enum Budget {
+    /// The remaining depth. Reaching zero forces a leaf.
+    Depth(usize),
+    /// The exact number of operators.
+    Size(usize),
+}
+
+/// The pending work of the generator. The operators are
+/// assembled after all their operands are generated.
+enum GenTask {
+    Node(Budget),
+    Unop(UnopKind),
+    Binop(BinopKind),
+    /// An if-then-else over the comparison, the operands come in
+    /// the order of the comparison's sides, then the branches
+    Ite(CmpKind),
+}
+
+/// A seeded generator of random expressions over `arg_count` arguments,
+/// built from the operators of an [OpSet]. The expressions carry no
+/// unknown constants, so they can be evaluated, emitted or turned into
+/// specifications right away, see [spec].
+pub struct ExprGen {
+    rng: Rng,
+    width: u32,
+    signedness: Signedness,
+    arg_count: usize,
+    ops: OpSet,
+    max_depth: usize,
+    weights: Weights,
+    consts: ConstDist,
+}
+
+impl ExprGen {
+    /// Constructs a generator of `width`-bit expressions. The constants
+    /// are extended according to `signedness`. By default every operator
+    /// is used, the expressions are at most 4 levels deep and the
+    /// constants are [ConstDist::Edges].
+    pub fn new(seed: u64, width: u32, signedness: Signedness, arg_count: usize) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            width,
+            signedness,
+            arg_count,
+            ops: OpSet::all(),
+            max_depth: 4,
+            weights: Weights::default(),
+            consts: ConstDist::default(),
+        }
+    }
+
+    pub fn set_ops(&mut self, ops: &OpSet) {
+        self.ops = ops.clone();
+    }
+
+    /// Limit the depth of the expressions from [ExprGen::expr]. At the
+    /// depth of zero only the leaves get generated.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn set_weights(&mut self, weights: Weights) {
+        self.weights = weights;
+    }
+
+    pub fn set_consts(&mut self, consts: ConstDist) {
+        self.consts = consts;
+    }
+
+    /// The underlying source of randomness, e.g. to pick
+    /// the test parameters from the same seed.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// A random constant.
+    pub fn value(&mut self) -> ExprVal {
+        let width = self.width;
+        let raw = match self.consts {
+            ConstDist::Edges => match self.rng.below(8) {
+                0 => 0,
+                1 => -1,
+                2 => 1 << (width - 1),
+                3 => width as ExprVal + self.rng.below(3) as ExprVal - 1,
+                4 => self.rng.below(8) as ExprVal,
+                _ => self.rng.next_u128() as ExprVal,
+            },
+            ConstDist::Uniform => self.rng.next_u128() as ExprVal,
+            ConstDist::Range(lo, hi) => {
+                debug_assert!(lo <= hi);
+
+                let raw = self.rng.next_u128();
+                let offset = (hi.wrapping_sub(lo) as u128)
+                    .checked_add(1)
+                    .map_or(raw, |count| raw % count);
+
+                lo.wrapping_add(offset as ExprVal)
+            },
+        };
+
+        self.signedness.truncate(raw, width)
+    }
+
+    /// Random values for all the arguments.
+    pub fn args(&mut self) -> Vec<ExprVal> {
+        (0..self.arg_count).map(|_| self.value()).collect()
+    }
+
+    /// A random expression, at most as deep as the limit.
+    pub fn expr(&mut self) -> Expr {
+        self.generate(Budget::Depth(self.max_depth))
+    }
+
+    /// A random expression with exactly `size` operators, i.e. costing
+    /// `size` under [crate::cost::Profile::Nodes]. The depth limit is
+    /// ignored.
+    pub fn expr_of_size(&mut self, size: usize) -> Expr {
+        self.generate(Budget::Size(size))
+    }
+
+    /// A random expression from [ExprGen::expr] as an answer,
+    /// the arguments are called `names`.
+    pub fn answer(&mut self, names: &[String]) -> AnswerExpr {
+        assert_eq!(names.len(), self.arg_count);
+
+        self.expr().to_ans(|v| match v {
+            Variable::UnknownConst => unreachable!("No unknown constants are generated"),
+            Variable::Const(x) => Value::Const(x),
+            Variable::Argument(idx) => Value::Arg(names[idx].clone()),
+        })
+    }
+
+    fn generate(&mut self, budget: Budget) -> Expr {
+        let mut tasks = vec![GenTask::Node(budget)];
+        let mut vals = Vec::new();
+
+        while let Some(task) = tasks.pop() {
+            match task {
+                GenTask::Node(budget) => {
+                    if let Some(leaf) = self.node(budget, &mut tasks) {
+                        vals.push(Expr::Variable(leaf));
+                    }
+                },
+                GenTask::Unop(kind) => {
+                    let e = vals.pop().unwrap();
+                    vals.push(Expr::Unop(kind, Rc::new(e)));
+                },
+                GenTask::Binop(kind) => {
+                    let r = vals.pop().unwrap();
+                    let l = vals.pop().unwrap();
+                    vals.push(Expr::Binop(kind, Rc::new(l), Rc::new(r)));
+                },
+                GenTask::Ite(kind) => {
+                    let e = vals.pop().unwrap();
+                    let t = vals.pop().unwrap();
+                    let r = vals.pop().unwrap();
+                    let l = vals.pop().unwrap();
+                    let cond = BoolExpr::Cmp(kind, Rc::new(l), Rc::new(r));
+                    vals.push(Expr::Ite(cond, Rc::new(t), Rc::new(e)));
+                },
+            }
+        }
+
+        debug_assert_eq!(vals.len(), 1);
+        vals.pop().unwrap()
+    }
+
+    /// Pick the kind of a node. A leaf is returned right away,
+    /// an operator gets scheduled together with its operands.
+    fn node(&mut self, budget: Budget, tasks: &mut Vec<GenTask>) -> Option<Variable> {
+        let (leaf, operator) = match budget {
+            Budget::Depth(0) | Budget::Size(0) => (true, false),
+            Budget::Depth(_) => (true, true),
+            Budget::Size(_) => (false, true),
+        };
+        let weights = [
+            if leaf && self.arg_count > 0 { self.weights.argument } else { 0 },
+            if leaf { self.weights.constant } else { 0 },
+            if operator && !self.ops.unops.is_empty() { self.weights.unop } else { 0 },
+            if operator && !self.ops.binops.is_empty() { self.weights.binop } else { 0 },
+            if operator && !self.ops.cmps.is_empty() { self.weights.ite } else { 0 },
+        ];
+        let arity = [0, 0, 1, 2, 4];
+
+        let kind = self.rng.pick(&weights);
+        match kind {
+            0 => return Some(Variable::Argument(self.rng.below(self.arg_count))),
+            1 => return Some(Variable::Const(self.value())),
+            _ => (),
+        }
+
+        let operands = match budget {
+            Budget::Depth(depth) => vec![Budget::Depth(depth - 1); arity[kind]],
+            Budget::Size(size) => self.rng.split(size - 1, arity[kind])
+                .into_iter()
+                .map(Budget::Size)
+                .collect(),
+        };
+        match kind {
+            2 => tasks.push(GenTask::Unop(self.ops.unops[self.rng.below(self.ops.unops.len())])),
+            3 => tasks.push(GenTask::Binop(self.ops.binops[self.rng.below(self.ops.binops.len())])),
+            _ => tasks.push(GenTask::Ite(self.ops.cmps[self.rng.below(self.ops.cmps.len())])),
+        }
+        // The first operand goes on top, so that
+        // the operands are generated left to right
+        tasks.extend(operands.into_iter().rev().map(GenTask::Node));
+
+        None
+    }
+}
+
+/// A specification met by `answer`: the result variable `result` must
+/// equal it on all the arguments. E.g. a random answer turns into a
+/// benchmark to find a cheaper equivalent for.
+pub fn spec(answer: &AnswerExpr, result: &str, width: u32) -> String {
+    let ctx = z3::Context::new(&z3::Config::new());
+    let term = answer.to_z3_ans(&ctx, width, |x| z3::ast::BV::new_const(&ctx, x, width));
+
+    format!("(assert (= {result} {term}))")
+}
@@ -1,32 +1,111 @@
+use std::collections::HashMap;
 
-use log::{debug, info};
+use log::{debug, info, warn};
 
-use crate::{expr::{Expr, Value}, oracle::Oracle, synth::Synthesizer};
+use crate::{expr::{AnswerExpr, Expr, ExprVal, OpSet, Signedness, Value, Variable}, oracle::{Oracle, Outcome, SpecError, SpecErrorKind}, synth::Synthesizer};
+use crate::canon::Canonicalizer;
+use crate::cost::{total_cost, CostModel};
+use crate::simplify::{ConstVar, Simplifier};
 use crate::conv::*;
 
-/// The report of the search routine
+/// The report of the search routine. The samples and the answers have
+/// an expression for every result of the specification, in order.
+///
+/// With the `serde` feature, a step is stored as a JSON object with its
+/// kind in the `step` field, e.g. `{"step":"duplicate_sample","cand":[...]}`.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "step", rename_all = "snake_case"))]
 pub enum SearchStep {
     /// The synthesizer has provided a sample `cand`
     /// that doesn't meet the specification.
     IncorrectSample {
-        cand: Expr,
+        cand: Vec<Expr>,
         /// This flag is set to `true` if a counterexample was found
         is_universally_wrong: bool,
     },
     /// The synthesizer has provided a sample `cand`
     /// that met the specification.
     CorrectSample {
-        cand: Expr,
-        answer: Expr<Value>,
+        cand: Vec<Expr>,
+        answer: Vec<Expr<Value>>,
     },
+    /// The synthesizer has provided a sample `cand`, which is
+    /// equivalent to an already checked one, so it was skipped.
+    DuplicateSample {
+        cand: Vec<Expr>,
+    },
+    /// The synthesizer has provided a sample `cand`, whose answer
+    /// isn't cheaper than the best answer found so far, so it was
+    /// skipped. See [BithackSearch::set_cost_model].
+    CostlySample {
+        cand: Vec<Expr>,
+    },
+    /// The synthesizer has provided a sample `cand`, which the oracle
+    /// has failed to check, e.g. because of the timeout. See
+    /// [BithackSearch::set_unknown_policy].
+    UnknownSample {
+        cand: Vec<Expr>,
+        /// The reason given by z3
+        reason: String,
+    },
+}
+
+/// What the search does, when the oracle fails to check a sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownPolicy {
+    /// Move on to the next sample
+    #[default]
+    Skip,
+    /// Check the sample again up to this many times, doubling the
+    /// timeout every time, then skip it. Only works together with
+    /// [BithackSearch::set_timeout].
+    Retry(u32),
+    /// Stop the search after reporting the sample
+    Abort,
+}
+
+/// A reference implementation of a result, see [BithackSearch::parse_reference].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reference {
+    /// An SMTLIB term over the arguments, e.g. `(bvmul x #x03)`
+    Term(String),
+    /// An SMTLIB `define-fun`, applied to the arguments in order.
+    /// The parameter names don't matter.
+    DefineFun(String),
+    Answer(AnswerExpr),
 }
 
+impl Reference {
+    /// An SMTLIB reference, a `define-fun` or a term.
+    pub fn smt(src: &str) -> Self {
+        if src.trim_start().starts_with("(define-fun") {
+            Reference::DefineFun(src.to_string())
+        } else {
+            Reference::Term(src.to_string())
+        }
+    }
+}
+
+/// The arguments of a counterexample with the expected results
+type Counterexample = (Vec<ExprVal>, Vec<ExprVal>);
+
 pub struct BithackSearch<'ctx, S> {
     should_learn: bool,
+    should_simplify: bool,
     synth: S,
     oracle: Oracle<'ctx>,
     converter: Z3ToExpr<'ctx>,
+    simplifier: Simplifier,
+    canonicalizer: Canonicalizer,
+    /// Canonical forms of the checked candidates. The rejected ones
+    /// come with their counterexamples, if any were found.
+    checked: HashMap<Vec<Expr>, Option<Counterexample>>,
+    cost_model: Option<Box<dyn CostModel>>,
+    /// The cost of the cheapest answer found so far
+    best_cost: Option<u32>,
+    unknown_policy: UnknownPolicy,
+    /// Set once a sample is unknown under [UnknownPolicy::Abort]
+    aborted: bool,
 }
 
 impl<'ctx, S: Synthesizer<'ctx>> BithackSearch<'ctx, S> {
@@ -35,27 +114,267 @@ impl<'ctx, S: Synthesizer<'ctx>> BithackSearch<'ctx, S> {
     ///
     /// If `should_learn` is true -- the searcher will try to look for
     /// a counterexample for invalid candidates.
+    ///
+    /// All the values are treated as bitvectors of `width` bits. The
+    /// constants and counterexamples are read back according to `signedness`.
+    /// The synthesizer is restricted to the operators from `ops`.
+    ///
+    /// The found answers are simplified, see [BithackSearch::set_simplify].
+    ///
+    /// The specification has a single result `res`.
     pub fn new(
         should_learn: bool,
         z3: &'ctx z3::Context,
+        width: u32,
+        signedness: Signedness,
+        ops: &OpSet,
+        arguments: Vec<String>,
+        depth_limit: usize,
+    ) -> Self {
+        Self::with_results(
+            should_learn,
+            z3,
+            width,
+            signedness,
+            ops,
+            arguments,
+            vec!["res".to_string()],
+            depth_limit,
+        )
+    }
+
+    /// Like [BithackSearch::new], but the specification is over the
+    /// result variables `results`. The search looks for an expression
+    /// for every one of them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_results(
+        should_learn: bool,
+        z3: &'ctx z3::Context,
+        width: u32,
+        signedness: Signedness,
+        ops: &OpSet,
         arguments: Vec<String>,
+        results: Vec<String>,
         depth_limit: usize,
     ) -> Self {
         Self {
             should_learn,
-            synth: S::build(z3, arguments.len(), depth_limit),
-            converter: Z3ToExpr::new(z3, arguments),
-            oracle: Oracle::new(z3),
+            should_simplify: true,
+            synth: S::build(z3, width, signedness, ops, arguments.len(), results.len(), depth_limit),
+            oracle: Oracle::new(z3, width, signedness, &results),
+            converter: Z3ToExpr::new(z3, width, signedness, arguments, results),
+            simplifier: Simplifier::new(width, signedness),
+            canonicalizer: Canonicalizer::new(width, signedness),
+            checked: HashMap::new(),
+            cost_model: None,
+            best_cost: None,
+            unknown_policy: UnknownPolicy::default(),
+            aborted: false,
         }
     }
 
-    /// Forward an SMTLIB prompt to the verification oracle
+    /// Enable or disable the simplification of the found answers.
+    /// A simplified answer is only reported if it still passes
+    /// the verification.
+    pub fn set_simplify(&mut self, should_simplify: bool) {
+        self.should_simplify = should_simplify;
+    }
+
+    /// Make the search prefer cheaper answers. Once an answer is found,
+    /// only the cheaper ones are reported. The candidates, which can't
+    /// give a cheaper answer, aren't even checked.
+    pub fn set_cost_model(&mut self, model: Box<dyn CostModel>) {
+        self.cost_model = Some(model);
+    }
+
+    /// Limit every check of the oracle to `timeout` milliseconds.
+    pub fn set_timeout(&mut self, timeout: Option<u32>) {
+        self.oracle.set_timeout(timeout);
+    }
+
+    /// Choose what to do with the samples the oracle fails to check.
+    /// They are skipped by default.
+    pub fn set_unknown_policy(&mut self, policy: UnknownPolicy) {
+        self.unknown_policy = policy;
+        self.oracle.set_retries(match policy {
+            UnknownPolicy::Retry(retries) => retries,
+            _ => 0,
+        });
+    }
+
+    /// How many checks of the oracle were inconclusive so far,
+    /// the retried ones included.
+    pub fn inconclusive_count(&self) -> usize {
+        self.oracle.inconclusive_count()
+    }
+
+    pub fn cost_model(&self) -> Option<&dyn CostModel> {
+        self.cost_model.as_deref()
+    }
+
+    /// Forward an SMTLIB prompt to the verification oracle. The arguments
+    /// and the results are declared beforehand. The lines of the errors
+    /// are counted from the start of `prompt`.
     pub fn parse_prompt(
         &mut self,
         prompt: &str,
-    ) {
+    ) -> Result<(), SpecError> {
         let preamble = self.converter.declaration();
-        self.oracle.parse([preamble.as_str(), prompt].join("\n"));
+        let preamble_lines = preamble.lines().count();
+
+        self.oracle.parse([preamble.as_str(), prompt].join("\n"))
+            // An error in the preamble is about the names, not the prompt
+            .map_err(|e| relocate_error(e, preamble_lines, usize::MAX))
+    }
+
+    /// Restrict the inputs with an SMTLIB prompt, like the one of
+    /// [BithackSearch::parse_prompt], except that its assertions only
+    /// mention the arguments. The answers only have to be correct on
+    /// the inputs meeting them. An empty prompt lifts the restrictions.
+    pub fn parse_assumptions(
+        &mut self,
+        prompt: &str,
+    ) -> Result<(), SpecError> {
+        let preamble = self.converter.declaration();
+        let preamble_lines = preamble.lines().count();
+
+        self.oracle.parse_assumptions([preamble.as_str(), prompt].join("\n"))
+            .map_err(|e| relocate_error(e, preamble_lines, usize::MAX))
+    }
+
+    /// Make the specification functional: the results must equal
+    /// the reference implementations, one for every result. Replaces
+    /// the prompt. The errors name the reference they are in, their
+    /// lines are counted from its start.
+    pub fn parse_reference(
+        &mut self,
+        reference: &[Reference],
+    ) -> Result<(), SpecError> {
+        let expected = self.converter.results().len();
+        if reference.len() != expected {
+            return Err(SpecError {
+                pos: None,
+                reference: None,
+                kind: SpecErrorKind::ReferenceCount { expected, found: reference.len() },
+            });
+        }
+
+        let terms = reference.iter()
+            .zip(self.converter.results())
+            .enumerate()
+            .map(|(idx, (x, result))| self.reference_term(x, result)
+                .map_err(|e| SpecError { reference: Some(idx), ..e }))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.oracle.set_reference(terms);
+
+        Ok(())
+    }
+
+    fn reference_term(
+        &self,
+        reference: &Reference,
+        result: &str,
+    ) -> Result<z3::ast::BV<'ctx>, SpecError> {
+        let preamble = self.converter.declaration();
+        let preamble_lines = preamble.lines().count();
+
+        match reference {
+            Reference::Answer(answer) => Ok(self.converter.ans_expr_to_z3(answer)),
+            // The term gets lines of its own, so that
+            // the columns of the errors stay correct
+            Reference::Term(term) => self.oracle
+                .parse_term(format!("{preamble}\n(assert (= {result}\n{term}\n))"))
+                .map_err(|e| relocate_error(e, preamble_lines + 1, term.lines().count())),
+            Reference::DefineFun(definition) => {
+                let name = definition.trim_start()
+                    .strip_prefix("(define-fun")
+                    .and_then(|x| x.split(|c: char| c.is_whitespace() || c == '(').find(|x| !x.is_empty()))
+                    .ok_or_else(|| SpecError {
+                        pos: None,
+                        reference: None,
+                        kind: SpecErrorKind::Syntax("expected a define-fun".to_string()),
+                    })?;
+                let call = match self.converter.arguments() {
+                    [] => name.to_string(),
+                    args => format!("({name} {})", args.join(" ")),
+                };
+
+                self.oracle
+                    .parse_term(format!("{preamble}\n{definition}\n(assert (= {result} {call}))"))
+                    .map_err(|e| relocate_error(e, preamble_lines, definition.lines().count()))
+            },
+        }
+    }
+
+    /// Verify a ready answer, an expression for every result, against the prompt.
+    pub fn verify_answer(&self, answer: &[AnswerExpr]) -> Outcome<()> {
+        let z3_answer = answer.iter()
+            .map(|x| self.converter.ans_expr_to_z3(x))
+            .collect::<Vec<_>>();
+
+        self.oracle.check_candidate(&z3_answer, self.converter.z3_args()).map(|_| ())
+    }
+
+    /// Like [BithackSearch::verify_answer], but an inconclusive
+    /// check counts as a failure.
+    pub fn check_answer(&self, answer: &[AnswerExpr]) -> bool {
+        self.verify_answer(answer).found().is_some()
+    }
+
+    /// Whether `exprs` are no cheaper than the best answer found so far
+    fn is_costly<V: ConstVar>(&self, exprs: &[Expr<V>]) -> bool {
+        match (self.cost_model(), self.best_cost) {
+            (Some(model), Some(best)) => total_cost(model, exprs) >= best,
+            _ => false,
+        }
+    }
+
+    /// Whether the answer to `cand` can't be cheaper than the best one.
+    /// The answer is either the candidate with its unknown constants
+    /// found or the simplified version of that, so without unknown
+    /// constants both can be costed before the check.
+    fn is_costly_cand(&self, cand: &[Expr]) -> bool {
+        if !self.is_costly(cand) {
+            return false;
+        }
+        if !self.should_simplify {
+            return true;
+        }
+
+        let has_unknown = cand.iter().any(|x| x.walk_expr(
+            &mut |v| *v == Variable::UnknownConst,
+            &mut |_, e| e,
+            &mut |_, l, r| l || r,
+            &mut |_, l, r| l || r,
+            &mut |c, t, e| c || t || e,
+            &mut |x| x,
+        ));
+        let simplified = cand.iter()
+            .map(|x| self.simplifier.simplify(x))
+            .collect::<Vec<_>>();
+
+        !has_unknown && self.is_costly(&simplified)
+    }
+
+    fn simplify_answer(&self, answer: Vec<AnswerExpr>) -> Vec<AnswerExpr> {
+        if !self.should_simplify {
+            return answer;
+        }
+
+        let simplified = answer.iter()
+            .map(|x| self.simplifier.simplify(x))
+            .collect::<Vec<_>>();
+        if simplified == answer {
+            return answer;
+        }
+
+        if !self.check_answer(&simplified) {
+            warn!("Simplified answer {simplified:?} failed verification, keeping {answer:?}");
+            return answer;
+        }
+
+        debug!("Simplified {answer:?} to {simplified:?}");
+        simplified
     }
 
     pub fn converter(&self) -> &Z3ToExpr<'ctx> {
@@ -66,46 +385,134 @@ impl<'ctx, S: Synthesizer<'ctx>> BithackSearch<'ctx, S> {
         &mut self.oracle
     }
 
+    pub fn synth(&self) -> &S {
+        &self.synth
+    }
+
+    pub fn synth_mut(&mut self) -> &mut S {
+        &mut self.synth
+    }
+
     /// Take a search step. `None` means that the search has terminated.
     /// For more information see [SearchStep].
     pub fn step(&mut self) -> Option<SearchStep> {
+        if self.aborted {
+            return None;
+        }
+
         let cand = self.synth.next_expr()?;
-        let z3_cand = self.converter.expr_to_z3(&cand);
+        let key = cand.iter()
+            .map(|x| self.canonicalizer.canonicalize(x))
+            .collect::<Vec<_>>();
+
+        if let Some(counterexample) = self.checked.get(&key) {
+            debug!("Skip: {cand:?}");
+
+            // The synthesizer still has to learn, that the candidate is bad
+            match counterexample.clone() {
+                Some((args, val)) => self.synth.bad_cand(&cand, args, val),
+                None => self.synth.unknown_cand(&cand),
+            }
+
+            return Some(SearchStep::DuplicateSample { cand });
+        }
+
+        if self.is_costly_cand(&cand) {
+            debug!("Too costly: {cand:?}");
+            self.synth.unknown_cand(&cand);
+            self.checked.insert(key, None);
+
+            return Some(SearchStep::CostlySample { cand });
+        }
+
+        let z3_cand = self.converter.cand_to_z3(&cand);
 
         debug!("Try: {cand:?}");
 
         Some(match self.oracle.check_candidate(&z3_cand, self.converter.z3_args()) {
-            Some(model) => SearchStep::CorrectSample {
-                answer: self.converter.build_answer(&cand, &model),
-                cand,
+            Outcome::Found(model) => {
+                let answer = self.converter.build_answer(&cand, &model);
+                let answer = self.simplify_answer(answer);
+                // The candidate has been dealt with
+                self.synth.unknown_cand(&cand);
+                self.checked.insert(key, None);
+
+                if self.is_costly(&answer) {
+                    debug!("Too costly: {answer:?}");
+
+                    return Some(SearchStep::CostlySample { cand });
+                }
+                if let Some(model) = self.cost_model() {
+                    self.best_cost = Some(total_cost(model, &answer));
+                }
+
+                SearchStep::CorrectSample {
+                    answer,
+                    cand,
+                }
             },
-            None if !self.should_learn => {
+            Outcome::Unknown(reason) => {
+                warn!("Failed to check {cand:?}: {reason}");
+                self.synth.unknown_cand(&cand);
+                self.aborted = self.unknown_policy == UnknownPolicy::Abort;
+
+                SearchStep::UnknownSample {
+                    reason,
+                    cand,
+                }
+            },
+            Outcome::NotFound if !self.should_learn => {
+                self.synth.unknown_cand(&cand);
+                self.checked.insert(key, None);
+
                 SearchStep::IncorrectSample {
                     is_universally_wrong: false,
                     cand,
                 }
             },
-            None => {
+            Outcome::NotFound => {
                 let counterexample = self.oracle.counterexample(
                     &z3_cand,
                     self.converter.z3_consts()
                 );
 
-                if let Some(model) = &counterexample {
-                    let args = self.converter.build_counter_example(model);
+                let counterexample = counterexample.found().and_then(|model| {
+                    let args = self.converter.build_counter_example(&model);
                     let val = self.oracle.suitable_value(
                         self.converter.z3_args().iter(),
-                        args.iter().map(|x| *x),
-                    );
-                    info!("Counter-example: {args:?} -> {val}");
-                    self.synth.bad_cand(&cand, args, val);
+                        args.iter().copied(),
+                    ).found()?;
+                    info!("Counter-example: {args:?} -> {val:?}");
+                    self.synth.bad_cand(&cand, args.clone(), val.clone());
+
+                    Some((args, val))
+                });
+
+                // The synthesizer can't learn anything from the sample
+                if counterexample.is_none() {
+                    self.synth.unknown_cand(&cand);
                 }
 
+                let is_universally_wrong = counterexample.is_some();
+                self.checked.insert(key, counterexample);
+
                 SearchStep::IncorrectSample {
-                    is_universally_wrong: counterexample.is_some(),
+                    is_universally_wrong,
                     cand,
                 }
             },
         })
     }
+}
+
+/// Count the lines of the error from the source, which takes `lines`
+/// lines of the script after the first `skip` ones. The errors outside
+/// of the source lose their position.
+fn relocate_error(e: SpecError, skip: usize, lines: usize) -> SpecError {
+    SpecError {
+        pos: e.pos
+            .filter(|(line, _)| *line > skip && *line - skip <= lines)
+            .map(|(line, column)| (line - skip, column)),
+        ..e
+    }
 }
\ No newline at end of file
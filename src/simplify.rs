@@ -0,0 +1,274 @@
+use std::rc::Rc;
+
+use crate::expr::{
+    eval_binop, eval_cmp, eval_unop, BinopKind, BoolExpr, CmpKind, Expr, ExprVal,
+    Signedness, UnopKind, Value, Variable,
+};
+
+/// Variables that may carry a known constant.
+pub trait ConstVar: Clone + PartialEq {
+    fn as_const(&self) -> Option<ExprVal>;
+
+    fn from_const(val: ExprVal) -> Self;
+}
+
+impl ConstVar for Variable {
+    fn as_const(&self) -> Option<ExprVal> {
+        match self {
+            Variable::Const(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn from_const(val: ExprVal) -> Self {
+        Variable::Const(val)
+    }
+}
+
+impl ConstVar for Value {
+    fn as_const(&self) -> Option<ExprVal> {
+        match self {
+            Value::Const(x) => Some(*x),
+            Value::Arg(_) => None,
+        }
+    }
+
+    fn from_const(val: ExprVal) -> Self {
+        Value::Const(val)
+    }
+}
+
+/// A condition, which may have been decided during simplification.
+enum Cond<V> {
+    Known(bool),
+    Cmp(CmpKind, Rc<Expr<V>>, Rc<Expr<V>>),
+}
+
+/// Bottom-up rewriter that cleans up the noise synthesizers tend to
+/// produce: constant subterms, neutral elements, double negations and
+/// chained shifts. Every rewrite preserves the value of the expression
+/// for all inputs at the given width.
+pub struct Simplifier {
+    width: u32,
+    signedness: Signedness,
+}
+
+impl Simplifier {
+    pub fn new(width: u32, signedness: Signedness) -> Self {
+        Self { width, signedness }
+    }
+
+    pub fn simplify<V: ConstVar>(&self, expr: &Expr<V>) -> Expr<V> {
+        let res = expr.walk_expr(
+            &mut |v: &V| match v.as_const() {
+                Some(x) => self.constant(x),
+                None => Rc::new(Expr::Variable(v.clone())),
+            },
+            &mut |unop_kind, e| self.unop(unop_kind, e),
+            &mut |binop_kind, l, r| self.binop(binop_kind, l, r),
+            &mut |cmp_kind, l, r| self.cmp(cmp_kind, l, r),
+            &mut |c, t, e| self.ite(c, t, e),
+            &mut |x| x,
+        );
+
+        Rc::unwrap_or_clone(res)
+    }
+
+    fn constant<V: ConstVar>(&self, val: ExprVal) -> Rc<Expr<V>> {
+        let val = self.signedness.truncate(val, self.width);
+
+        Rc::new(Expr::Variable(V::from_const(val)))
+    }
+
+    fn bits(&self, val: ExprVal) -> u128 {
+        Signedness::Unsigned.truncate(val, self.width) as u128
+    }
+
+    /// The bits of `e`, if it is a constant.
+    fn const_bits<V: ConstVar>(&self, e: &Expr<V>) -> Option<u128> {
+        match e {
+            Expr::Variable(v) => v.as_const().map(|x| self.bits(x)),
+            _ => None,
+        }
+    }
+
+    fn is_const<V: ConstVar>(&self, e: &Expr<V>, val: ExprVal) -> bool {
+        self.const_bits(e) == Some(self.bits(val))
+    }
+
+    /// Checks if `l` is the bitwise complement of `r` or vice versa.
+    fn is_complement<V: ConstVar>(l: &Expr<V>, r: &Rc<Expr<V>>) -> bool {
+        match (l, &**r) {
+            (Expr::Unop(UnopKind::Not, x), _) => x == r,
+            (x, Expr::Unop(UnopKind::Not, y)) => x == &**y,
+            _ => false,
+        }
+    }
+
+    fn unop<V: ConstVar>(&self, kind: UnopKind, e: Rc<Expr<V>>) -> Rc<Expr<V>> {
+        if let Some(x) = self.const_bits(&e) {
+            return self.constant(eval_unop(kind, x as ExprVal, self.width));
+        }
+
+        match (kind, &*e) {
+            (UnopKind::Not, Expr::Unop(UnopKind::Not, x)) |
+            (UnopKind::Negate, Expr::Unop(UnopKind::Negate, x)) => x.clone(),
+            _ => Rc::new(Expr::Unop(kind, e)),
+        }
+    }
+
+    fn binop<V: ConstVar>(
+        &self,
+        kind: BinopKind,
+        l: Rc<Expr<V>>,
+        r: Rc<Expr<V>>,
+    ) -> Rc<Expr<V>> {
+        use BinopKind::*;
+
+        let width = self.width as u128;
+
+        match (self.const_bits(&l), self.const_bits(&r)) {
+            (Some(l), Some(r)) => {
+                return self.constant(eval_binop(kind, l as ExprVal, r as ExprVal, self.width));
+            },
+            // Keep the constant on the right, so that the rules
+            // below only need to look there.
+            (Some(_), None) if kind.is_commutative() => {
+                return self.binop(kind, r, l);
+            },
+            _ => (),
+        }
+
+        let c = self.const_bits(&r);
+        let zero = self.constant(0);
+        let ones = self.constant(-1);
+
+        match kind {
+            And if c == Some(0) => zero,
+            And if self.is_const(&r, -1) => l,
+            And if l == r => l,
+            And if Self::is_complement(&l, &r) => zero,
+            Or if c == Some(0) => l,
+            Or if self.is_const(&r, -1) => ones,
+            Or if l == r => l,
+            Or if Self::is_complement(&l, &r) => ones,
+            Xor if c == Some(0) => l,
+            Xor if self.is_const(&r, -1) => self.unop(UnopKind::Not, l),
+            Xor if l == r => zero,
+            Xor if Self::is_complement(&l, &r) => ones,
+            Plus | Minus if c == Some(0) => l,
+            Plus if Self::is_complement(&l, &r) => ones,
+            // !x + 1 is the two's complement negation
+            Plus if c == Some(1) && matches!(&*l, Expr::Unop(UnopKind::Not, _)) => {
+                let Expr::Unop(_, x) = &*l else { unreachable!() };
+                self.unop(UnopKind::Negate, x.clone())
+            },
+            Minus if l == r => zero,
+            Minus if self.is_const(&l, 0) => self.unop(UnopKind::Negate, r),
+            Plus | Minus if c.is_some() => self.merge_offsets(kind, l, r),
+            Mul if c == Some(0) => zero,
+            Mul | SDiv | UDiv if c == Some(1) => l,
+            Mul | SDiv if self.is_const(&r, -1) => self.unop(UnopKind::Negate, l),
+            SRem | URem if c == Some(1) => zero,
+            SRem if self.is_const(&r, -1) => zero,
+            Shl | ShrA | ShrL | Rotl | Rotr if c == Some(0) => l,
+            Shl | ShrA | ShrL | Rotl | Rotr if self.is_const(&l, 0) => l,
+            ShrA | Rotl | Rotr if self.is_const(&l, -1) => l,
+            Rotl | Rotr if c.is_some_and(|c| c % width == 0) => l,
+            // Shifting out every bit
+            Shl | ShrL if c.is_some_and(|c| c >= width) => zero,
+            ShrA if c.is_some_and(|c| c >= width) => {
+                Rc::new(Expr::Binop(ShrA, l, self.constant(self.width as ExprVal - 1)))
+            },
+            Shl | ShrA | ShrL | Rotl | Rotr if c.is_some() => self.merge_shifts(kind, l, r),
+            _ => Rc::new(Expr::Binop(kind, l, r)),
+        }
+    }
+
+    /// Fold `(x ± c1) ± c2` into a single offset.
+    fn merge_offsets<V: ConstVar>(
+        &self,
+        kind: BinopKind,
+        l: Rc<Expr<V>>,
+        r: Rc<Expr<V>>,
+    ) -> Rc<Expr<V>> {
+        let Some(c2) = self.const_bits(&r) else { unreachable!() };
+
+        let (x, c1, inner) = match &*l {
+            Expr::Binop(inner @ (BinopKind::Plus | BinopKind::Minus), x, c1) => {
+                match self.const_bits(c1) {
+                    Some(c1) => (x.clone(), c1, *inner),
+                    None => return Rc::new(Expr::Binop(kind, l, r)),
+                }
+            },
+            _ => return Rc::new(Expr::Binop(kind, l, r)),
+        };
+
+        let (c1, c2) = (c1 as ExprVal, c2 as ExprVal);
+        let offset = match (inner, kind) {
+            (BinopKind::Plus, BinopKind::Plus) => c1.wrapping_add(c2),
+            (BinopKind::Plus, _) => c1.wrapping_sub(c2),
+            (_, BinopKind::Plus) => c2.wrapping_sub(c1),
+            _ => c1.wrapping_add(c2).wrapping_neg(),
+        };
+
+        self.binop(BinopKind::Plus, x, self.constant(offset))
+    }
+
+    /// Fold `(x op c1) op c2` into a single shift or rotation.
+    fn merge_shifts<V: ConstVar>(
+        &self,
+        kind: BinopKind,
+        l: Rc<Expr<V>>,
+        r: Rc<Expr<V>>,
+    ) -> Rc<Expr<V>> {
+        let Some(c2) = self.const_bits(&r) else { unreachable!() };
+
+        let (x, c1) = match &*l {
+            Expr::Binop(inner, x, c1) if *inner == kind => match self.const_bits(c1) {
+                Some(c1) => (x.clone(), c1),
+                None => return Rc::new(Expr::Binop(kind, l, r)),
+            },
+            _ => return Rc::new(Expr::Binop(kind, l, r)),
+        };
+
+        let width = self.width as u128;
+        let amount = match kind {
+            BinopKind::Rotl | BinopKind::Rotr => (c1 % width + c2 % width) % width,
+            _ => c1.saturating_add(c2),
+        };
+
+        self.binop(kind, x, self.constant(amount.min(width) as ExprVal))
+    }
+
+    fn cmp<V: ConstVar>(
+        &self,
+        kind: CmpKind,
+        l: Rc<Expr<V>>,
+        r: Rc<Expr<V>>,
+    ) -> Cond<V> {
+        match (self.const_bits(&l), self.const_bits(&r)) {
+            (Some(l), Some(r)) => {
+                Cond::Known(eval_cmp(kind, l as ExprVal, r as ExprVal, self.width))
+            },
+            _ if l == r => Cond::Known(kind == CmpKind::Eq),
+            // Nothing is below zero when unsigned
+            (_, Some(0)) if kind == CmpKind::Ult => Cond::Known(false),
+            _ => Cond::Cmp(kind, l, r),
+        }
+    }
+
+    fn ite<V: ConstVar>(
+        &self,
+        cond: Cond<V>,
+        t: Rc<Expr<V>>,
+        e: Rc<Expr<V>>,
+    ) -> Rc<Expr<V>> {
+        match cond {
+            Cond::Known(true) => t,
+            Cond::Known(false) => e,
+            Cond::Cmp(..) if t == e => t,
+            Cond::Cmp(kind, l, r) => Rc::new(Expr::Ite(BoolExpr::Cmp(kind, l, r), t, e)),
+        }
+    }
+}
@@ -0,0 +1,516 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::expr::{BinopKind, CmpKind, OpSet, UnopKind, MAX_BITS_PER_VAL};
+
+/// The error produced by [parse_sygus]. `line` is the line the
+/// offending command starts at, the lines are counted from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SygusError {
+    pub line: Option<usize>,
+    pub kind: SygusErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SygusErrorKind {
+    /// A parenthesis, a string or a quoted symbol is left open
+    UnexpectedEnd,
+    UnexpectedParen,
+    /// A command doesn't have the expected shape
+    Malformed(&'static str),
+    /// A command, a logic or a sort bitsynth can't handle
+    Unsupported(String),
+    /// All the bitvectors must be of the same width
+    WidthMismatch {
+        expected: u32,
+        found: u32,
+    },
+    /// A function to synthesize is applied to something else than the
+    /// declared variables, or not the same way everywhere
+    Invocation(String),
+    /// A declared variable is used, but not passed to
+    /// the functions to synthesize
+    UnboundVariable(String),
+    /// The name is declared twice
+    Redeclared(String),
+    NoSynthFun,
+}
+
+impl fmt::Display for SygusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+
+        match &self.kind {
+            SygusErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            SygusErrorKind::UnexpectedParen => write!(f, "unexpected \")\""),
+            SygusErrorKind::Malformed(expected) => write!(f, "expected {expected}"),
+            SygusErrorKind::Unsupported(what) => write!(f, "unsupported {what}"),
+            SygusErrorKind::WidthMismatch { expected, found } =>
+                write!(f, "expected a {expected}-bit vector, found a {found}-bit one"),
+            SygusErrorKind::Invocation(name) =>
+                write!(f, "{name:?} must be applied to the same distinct variables everywhere"),
+            SygusErrorKind::UnboundVariable(name) =>
+                write!(f, "variable {name:?} is not passed to the functions to synthesize"),
+            SygusErrorKind::Redeclared(name) => write!(f, "{name:?} is declared twice"),
+            SygusErrorKind::NoSynthFun => write!(f, "nothing to synthesize"),
+        }
+    }
+}
+
+impl std::error::Error for SygusError {}
+
+/// An SMT-LIB s-expression. The atoms keep their spelling,
+/// e.g. the bars of the quoted symbols.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Sexp::Atom(x) => Some(x),
+            Sexp::List(_) => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[Sexp]> {
+        match self {
+            Sexp::Atom(_) => None,
+            Sexp::List(x) => Some(x),
+        }
+    }
+
+    fn atoms<'a>(&'a self, res: &mut HashSet<&'a str>) {
+        match self {
+            Sexp::Atom(x) => { res.insert(x); },
+            Sexp::List(xs) => xs.iter().for_each(|x| x.atoms(res)),
+        }
+    }
+}
+
+impl fmt::Display for Sexp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sexp::Atom(x) => write!(f, "{x}"),
+            Sexp::List(xs) => {
+                write!(f, "(")?;
+                for (idx, x) in xs.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{x}")?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+/// Split `src` into the top-level s-expressions, each
+/// with the line it starts at.
+fn read_sexps(src: &str) -> Result<Vec<(usize, Sexp)>, SygusError> {
+    let mut res = Vec::new();
+    // The open lists with the lines they start at
+    let mut open: Vec<(usize, Vec<Sexp>)> = Vec::new();
+    let mut line = 1;
+    let mut chars = src.char_indices().peekable();
+
+    let err = |line, kind| SygusError { line: Some(line), kind };
+    while let Some((start, c)) = chars.next() {
+        let atom = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            },
+            ';' => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                continue;
+            },
+            c if c.is_whitespace() => continue,
+            '(' => {
+                open.push((line, Vec::new()));
+                continue;
+            },
+            ')' => {
+                let (start_line, list) = open.pop()
+                    .ok_or(err(line, SygusErrorKind::UnexpectedParen))?;
+
+                match open.last_mut() {
+                    Some((_, parent)) => parent.push(Sexp::List(list)),
+                    None => res.push((start_line, Sexp::List(list))),
+                }
+                continue;
+            },
+            '|' | '"' => {
+                let start_line = line;
+                let mut end = None;
+                while let Some((idx, x)) = chars.next() {
+                    line += (x == '\n') as usize;
+                    // A quote inside a string is doubled
+                    if x == c && !(c == '"' && chars.next_if(|(_, x)| *x == '"').is_some()) {
+                        end = Some(idx + 1);
+                        break;
+                    }
+                }
+
+                let end = end.ok_or(err(start_line, SygusErrorKind::UnexpectedEnd))?;
+                &src[start..end]
+            },
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((idx, x)) = chars.next_if(|(_, x)| !x.is_whitespace() && !"()|\";".contains(*x)) {
+                    end = idx + x.len_utf8();
+                }
+
+                &src[start..end]
+            },
+        };
+
+        match open.last_mut() {
+            Some((_, list)) => list.push(Sexp::Atom(atom.to_string())),
+            None => res.push((line, Sexp::Atom(atom.to_string()))),
+        }
+    }
+
+    if let Some((start_line, _)) = open.first() {
+        return Err(err(*start_line, SygusErrorKind::UnexpectedEnd));
+    }
+
+    Ok(res)
+}
+
+/// The SMT-LIB spelling of the operators, see [SynthFun::grammar].
+const UNOP_SYMBOLS: [(UnopKind, &str); 2] = [
+    (UnopKind::Not, "bvnot"),
+    (UnopKind::Negate, "bvneg"),
+];
+
+const BINOP_SYMBOLS: [(BinopKind, &str); 15] = [
+    (BinopKind::And, "bvand"),
+    (BinopKind::Or, "bvor"),
+    (BinopKind::Xor, "bvxor"),
+    (BinopKind::Plus, "bvadd"),
+    (BinopKind::Minus, "bvsub"),
+    (BinopKind::Shl, "bvshl"),
+    (BinopKind::ShrA, "bvashr"),
+    (BinopKind::ShrL, "bvlshr"),
+    (BinopKind::Mul, "bvmul"),
+    (BinopKind::SDiv, "bvsdiv"),
+    (BinopKind::UDiv, "bvudiv"),
+    (BinopKind::SRem, "bvsrem"),
+    (BinopKind::URem, "bvurem"),
+    (BinopKind::Rotl, "rotate_left"),
+    (BinopKind::Rotr, "rotate_right"),
+];
+
+const CMP_SYMBOLS: [(CmpKind, &[&str]); 3] = [
+    (CmpKind::Eq, &["=", "distinct"]),
+    (CmpKind::Slt, &["bvslt", "bvsle", "bvsgt", "bvsge"]),
+    (CmpKind::Ult, &["bvult", "bvule", "bvugt", "bvuge"]),
+];
+
+/// The operators mentioned in a grammar. The comparisons
+/// only count, when the grammar has an if-then-else.
+fn grammar_ops(grammar: &[Sexp]) -> OpSet {
+    let mut symbols = HashSet::new();
+    grammar.iter().for_each(|x| x.atoms(&mut symbols));
+
+    OpSet {
+        unops: UNOP_SYMBOLS.iter()
+            .filter(|(_, x)| symbols.contains(x))
+            .map(|(kind, _)| *kind)
+            .collect(),
+        binops: BINOP_SYMBOLS.iter()
+            .filter(|(_, x)| symbols.contains(x))
+            .map(|(kind, _)| *kind)
+            .collect(),
+        cmps: CMP_SYMBOLS.iter()
+            .filter(|(_, x)| symbols.contains("ite") && x.iter().any(|x| symbols.contains(x)))
+            .map(|(kind, _)| *kind)
+            .collect(),
+    }
+}
+
+/// A function to synthesize.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynthFun {
+    pub name: String,
+    pub params: Vec<String>,
+    /// The operators the grammar of the function allows, if it has one.
+    /// The rest of the grammar, e.g. which constants it allows, isn't
+    /// enforced.
+    pub grammar: Option<OpSet>,
+}
+
+/// A SyGuS problem over bitvectors, translated into the terms of
+/// [crate::search::BithackSearch]: the functions to synthesize are the
+/// results, the variables they are applied to are the arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SygusProblem {
+    pub logic: Option<String>,
+    pub width: u32,
+    /// The declared variables in the order the functions take them
+    pub args: Vec<String>,
+    pub funs: Vec<SynthFun>,
+    /// The constraints as a prompt for [crate::search::BithackSearch::parse_prompt],
+    /// the applications of the functions are replaced with the results
+    pub prompt: String,
+    /// The assumptions as a prompt for [crate::search::BithackSearch::parse_assumptions],
+    /// empty if there are none
+    pub assumptions: String,
+}
+
+impl SygusProblem {
+    /// The result variables, named after the functions.
+    pub fn results(&self) -> Vec<String> {
+        self.funs.iter().map(|x| x.name.clone()).collect()
+    }
+
+    /// The operators allowed by any of the grammars. Without
+    /// a grammar, every operator is allowed.
+    pub fn ops(&self) -> OpSet {
+        let grammars = self.funs.iter()
+            .map(|x| x.grammar.as_ref())
+            .collect::<Option<Vec<_>>>();
+        let Some(grammars) = grammars else {
+            return OpSet::all();
+        };
+
+        let mut res = OpSet::all();
+        res.unops.retain(|x| grammars.iter().any(|g| g.unops.contains(x)));
+        res.binops.retain(|x| grammars.iter().any(|g| g.binops.contains(x)));
+        res.cmps.retain(|x| grammars.iter().any(|g| g.cmps.contains(x)));
+
+        res
+    }
+}
+
+/// The state of [parse_sygus] between the commands.
+struct SygusReader {
+    line: usize,
+    logic: Option<String>,
+    width: Option<u32>,
+    vars: Vec<String>,
+    funs: Vec<SynthFun>,
+    /// The variables the functions are applied to
+    invocation: Option<Vec<String>>,
+    /// The variables used outside of the applications,
+    /// with the lines they are first used at
+    used: Vec<(usize, String)>,
+    prompt: Vec<String>,
+    /// The definitions and the assumptions
+    assumptions: Vec<String>,
+    /// Whether there are any assumptions among the above
+    assumed: bool,
+}
+
+impl SygusReader {
+    fn err(&self, kind: SygusErrorKind) -> SygusError {
+        SygusError { line: Some(self.line), kind }
+    }
+
+    /// Read a bitvector sort, checking that its width is the same as
+    /// the width of the others.
+    fn sort(&mut self, sort: &Sexp) -> Result<(), SygusError> {
+        let width = match sort.list() {
+            Some([Sexp::Atom(under), Sexp::Atom(bv), Sexp::Atom(width)])
+                if under == "_" && bv == "BitVec" => width.parse::<u32>().ok(),
+            _ => None,
+        };
+        let Some(width) = width else {
+            return Err(self.err(SygusErrorKind::Unsupported(format!("sort {sort}"))));
+        };
+        if width == 0 || width > MAX_BITS_PER_VAL {
+            return Err(self.err(SygusErrorKind::Unsupported(format!("width {width}"))));
+        }
+
+        match self.width {
+            Some(expected) if expected != width => {
+                Err(self.err(SygusErrorKind::WidthMismatch { expected, found: width }))
+            },
+            _ => {
+                self.width = Some(width);
+                Ok(())
+            },
+        }
+    }
+
+    fn declare(&self, name: &str) -> Result<(), SygusError> {
+        let taken = self.vars.iter()
+            .chain(self.funs.iter().map(|x| &x.name))
+            .any(|x| x == name);
+
+        match taken {
+            true => Err(self.err(SygusErrorKind::Redeclared(name.to_string()))),
+            false => Ok(()),
+        }
+    }
+
+    fn command(&mut self, cmd: &Sexp) -> Result<(), SygusError> {
+        let Some([Sexp::Atom(head), rest @ ..]) = cmd.list() else {
+            return Err(self.err(SygusErrorKind::Malformed("a command")));
+        };
+
+        match (head.as_str(), rest) {
+            ("set-logic", [Sexp::Atom(logic)]) => {
+                if !matches!(logic.as_str(), "BV" | "QF_BV" | "ALL") {
+                    return Err(self.err(SygusErrorKind::Unsupported(format!("logic {logic}"))));
+                }
+                self.logic = Some(logic.clone());
+            },
+            ("set-logic", _) => return Err(self.err(SygusErrorKind::Malformed("(set-logic <logic>)"))),
+            ("set-info" | "set-option" | "set-feature" | "check-synth", _) => (),
+            ("declare-var", [Sexp::Atom(name), sort]) => {
+                self.declare(name)?;
+                self.sort(sort)?;
+                self.vars.push(name.clone());
+            },
+            ("declare-var", _) => return Err(self.err(SygusErrorKind::Malformed("(declare-var <name> <sort>)"))),
+            ("synth-fun", [Sexp::Atom(name), Sexp::List(params), sort, grammar @ ..]) => {
+                self.declare(name)?;
+                let params = params.iter()
+                    .map(|x| match x.list() {
+                        Some([Sexp::Atom(param), sort]) => self.sort(sort).map(|_| param.clone()),
+                        _ => Err(self.err(SygusErrorKind::Malformed("a parameter (<name> <sort>)"))),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.sort(sort)?;
+
+                self.funs.push(SynthFun {
+                    name: name.clone(),
+                    params,
+                    grammar: (!grammar.is_empty()).then(|| grammar_ops(grammar)),
+                });
+            },
+            ("synth-fun", _) => {
+                return Err(self.err(SygusErrorKind::Malformed("(synth-fun <name> (<param>*) <sort> <grammar>?)")));
+            },
+            // The definitions are needed by the constraints as they are
+            ("define-fun", _) => {
+                self.prompt.push(cmd.to_string());
+                self.assumptions.push(cmd.to_string());
+            },
+            ("constraint", [term]) => {
+                let term = self.rewrite(term)?;
+                self.prompt.push(format!("(assert {term})"));
+            },
+            ("constraint", _) => return Err(self.err(SygusErrorKind::Malformed("(constraint <term>)"))),
+            ("assume", [term]) => {
+                if self.rewrite(term)? != *term {
+                    return Err(self.err(SygusErrorKind::Malformed("an assumption without the functions to synthesize")));
+                }
+                self.assumptions.push(format!("(assert {term})"));
+                self.assumed = true;
+            },
+            ("assume", _) => return Err(self.err(SygusErrorKind::Malformed("(assume <term>)"))),
+            (head, _) => return Err(self.err(SygusErrorKind::Unsupported(format!("command {head}")))),
+        }
+
+        Ok(())
+    }
+
+    /// Replace the applications of the functions to synthesize with the
+    /// results, checking that they are applied to the same variables.
+    fn rewrite(&mut self, term: &Sexp) -> Result<Sexp, SygusError> {
+        let (name, args) = match term {
+            Sexp::Atom(x) => (Some(x), &[] as &[Sexp]),
+            Sexp::List(xs) => match xs.as_slice() {
+                [Sexp::Atom(x), args @ ..] => (Some(x), args),
+                _ => (None, &[] as &[Sexp]),
+            },
+        };
+        let Some(name) = name.filter(|x| self.funs.iter().any(|f| &f.name == *x)) else {
+            return match term {
+                Sexp::Atom(x) => {
+                    if self.vars.contains(x) && !self.used.iter().any(|(_, v)| v == x) {
+                        self.used.push((self.line, x.clone()));
+                    }
+
+                    Ok(term.clone())
+                },
+                Sexp::List(xs) => xs.iter()
+                    .map(|x| self.rewrite(x))
+                    .collect::<Result<_, _>>()
+                    .map(Sexp::List),
+            };
+        };
+
+        let invocation = args.iter()
+            .map(|x| x.atom().filter(|x| self.vars.iter().any(|v| v == x)).map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .filter(|xs| xs.iter().collect::<HashSet<_>>().len() == xs.len());
+        let invocation_err = || self.err(SygusErrorKind::Invocation(name.clone()));
+        let invocation = invocation.ok_or_else(invocation_err)?;
+
+        match &self.invocation {
+            Some(expected) if *expected != invocation => return Err(invocation_err()),
+            Some(_) => (),
+            None => self.invocation = Some(invocation),
+        }
+
+        Ok(Sexp::Atom(name.clone()))
+    }
+
+    fn finish(self) -> Result<SygusProblem, SygusError> {
+        if self.funs.is_empty() {
+            return Err(SygusError { line: None, kind: SygusErrorKind::NoSynthFun });
+        }
+
+        // Without constraints, the parameters of the first function will do
+        let args = self.invocation.unwrap_or_else(|| self.funs[0].params.clone());
+        if let Some(fun) = self.funs.iter().find(|x| x.params.len() != args.len()) {
+            return Err(SygusError {
+                line: None,
+                kind: SygusErrorKind::Invocation(fun.name.clone()),
+            });
+        }
+        if let Some((line, var)) = self.used.iter().find(|(_, x)| !args.contains(x)) {
+            return Err(SygusError {
+                line: Some(*line),
+                kind: SygusErrorKind::UnboundVariable(var.clone()),
+            });
+        }
+
+        Ok(SygusProblem {
+            logic: self.logic,
+            width: self.width.unwrap(),
+            args,
+            funs: self.funs,
+            prompt: self.prompt.join("\n"),
+            assumptions: match self.assumed {
+                true => self.assumptions.join("\n"),
+                false => String::new(),
+            },
+        })
+    }
+}
+
+/// Read a SyGuS-IF v2 problem over bitvectors of a single width. The
+/// functions to synthesize have to be applied to the same distinct
+/// variables everywhere, like `(f x y)`, so that their results can
+/// stand for them in the constraints.
+pub fn parse_sygus(src: &str) -> Result<SygusProblem, SygusError> {
+    let mut reader = SygusReader {
+        line: 0,
+        logic: None,
+        width: None,
+        vars: Vec::new(),
+        funs: Vec::new(),
+        invocation: None,
+        used: Vec::new(),
+        prompt: Vec::new(),
+        assumptions: Vec::new(),
+        assumed: false,
+    };
+
+    for (line, cmd) in read_sexps(src)? {
+        reader.line = line;
+        reader.command(&cmd)?;
+    }
+
+    reader.finish()
+}
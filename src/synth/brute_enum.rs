@@ -1,8 +1,8 @@
 use std::{iter::FusedIterator, rc::Rc};
 
-use crate::expr::{BinopKind, Expr, ExprSkeleton, ExprVal, UnopKind, Variable};
+use crate::expr::{BoolExpr, Expr, ExprSkeleton, ExprVal, OpSet, Signedness, Variable};
 
-use super::Synthesizer;
+use super::{Synthesizer, Tuples};
 
 /// Structure for enumerating all possible
 /// argument and constant subtitutions.
@@ -90,14 +90,16 @@ pub struct SkeletonIdx {
     depth_limit: usize,
     skeleton_idx: usize,
     skeletons: Vec<Rc<ExprSkeleton>>,
+    hole_substs: Vec<ExprSkeleton>,
 }
 
 impl SkeletonIdx {
-    pub fn new(depth_limit: usize) -> Self {
+    pub fn new(depth_limit: usize, ops: &OpSet) -> Self {
         Self {
             depth_limit,
             skeleton_idx: 0,
             skeletons: vec![Rc::new(Expr::Variable(()))],
+            hole_substs: Self::all_hole_substs(ops).collect(),
         }
     }
 
@@ -105,7 +107,7 @@ impl SkeletonIdx {
         self.skeleton_idx = 0;
 
         let new_skeletons = self.skeletons.iter()
-            .flat_map(|x| Self::grow_skeleton(&x))
+            .flat_map(|x| Self::grow_skeleton(x, &self.hole_substs))
             .map(Rc::new)
             .collect();
 
@@ -123,47 +125,47 @@ impl SkeletonIdx {
     /// 2. `_ + (_ ^ _)`
     /// 3. `(_ & _) + _`
     /// 4. `_ + (_ >> _)`
+    ///
     /// ...
     /// And so on
-    fn grow_skeleton(skele: &'_ ExprSkeleton) -> impl Iterator<Item = ExprSkeleton> + '_ {
+    fn grow_skeleton<'a>(
+        skele: &'a ExprSkeleton,
+        hole_substs: &'a [ExprSkeleton],
+    ) -> impl Iterator<Item = ExprSkeleton> + 'a {
         let hole_count = skele.count_holes();
 
         // TODO: more than likely this is where we can filter off a large chunk of candidates.
         (0..hole_count)
-            .flat_map(move |hole_idx| Self::all_hole_substs()
-                .map(move |subst| skele.subst_hole(hole_idx, &subst))
+            .flat_map(move |hole_idx| hole_substs.iter()
+                .map(move |subst| skele.subst_hole(hole_idx, subst))
             )
     }
 
-    fn all_hole_substs() -> impl Iterator<Item = ExprSkeleton> {
-        // FIXME: it's better to use some crate for iterating over enum variants
-        let all_unops = [
-            UnopKind::Negate,
-            UnopKind::Not,
-        ];
-        let all_binops = [
-            BinopKind::And,
-            BinopKind::Or,
-            BinopKind::Xor,
-            BinopKind::Plus,
-            BinopKind::Minus,
-            BinopKind::Shl,
-            BinopKind::ShrA,
-        ];
-
-        let unop_substs = all_unops.into_iter()
+    fn all_hole_substs(ops: &OpSet) -> impl Iterator<Item = ExprSkeleton> + '_ {
+        let unop_substs = ops.unops.iter()
             .map(|x| ExprSkeleton::Unop(
-                x,
+                *x,
                 Rc::new(ExprSkeleton::Variable(()))
             ));
-        let binop_substs = all_binops.into_iter()
+        let binop_substs = ops.binops.iter()
             .map(|x| ExprSkeleton::Binop(
-                x,
+                *x,
                 Rc::new(ExprSkeleton::Variable(())),
                 Rc::new(ExprSkeleton::Variable(())),
             ));
 
-        unop_substs.chain(binop_substs)
+        let ite_substs = ops.cmps.iter()
+            .map(|x| ExprSkeleton::Ite(
+                BoolExpr::Cmp(
+                    *x,
+                    Rc::new(ExprSkeleton::Variable(())),
+                    Rc::new(ExprSkeleton::Variable(())),
+                ),
+                Rc::new(ExprSkeleton::Variable(())),
+                Rc::new(ExprSkeleton::Variable(())),
+            ));
+
+        unop_substs.chain(binop_substs).chain(ite_substs)
     }
 }
 
@@ -190,14 +192,18 @@ pub struct ExprBreadth {
 }
 
 impl ExprBreadth {
-    pub fn new(arg_count: usize, depth_limit: usize) -> Self {
+    pub fn new(arg_count: usize, depth_limit: usize, ops: &OpSet) -> Self {
         Self {
             expr_iter: ExprIdx::new(arg_count),
-            skele_iter: SkeletonIdx::new(depth_limit),
+            skele_iter: SkeletonIdx::new(depth_limit, ops),
         }
     }
+}
+
+impl Iterator for ExprBreadth {
+    type Item = Expr;
 
-    pub fn next(&mut self) -> Option<Expr> {
+    fn next(&mut self) -> Option<Self::Item> {
         if self.expr_iter.is_empty() {
             let skele = self.skele_iter.next()
                 .or_else(|| {
@@ -213,21 +219,29 @@ impl ExprBreadth {
 }
 
 pub struct BruteEnum {
-    breadth: ExprBreadth,
+    breadth: Tuples<ExprBreadth>,
 }
 
 impl<'ctx> Synthesizer<'ctx> for BruteEnum {
-    fn build(_z3: &'ctx z3::Context, var_count: usize, depth_limit: usize) -> Self {
+    fn build(
+        _z3: &'ctx z3::Context,
+        _width: u32,
+        _signedness: Signedness,
+        ops: &OpSet,
+        var_count: usize,
+        out_count: usize,
+        depth_limit: usize,
+    ) -> Self {
         Self {
-            breadth: ExprBreadth::new(var_count, depth_limit),
+            breadth: Tuples::new(ExprBreadth::new(var_count, depth_limit, ops), out_count),
         }
     }
 
-    fn bad_cand(&mut self, _expr: &Expr, _args: Vec<ExprVal>, _val: ExprVal) {
+    fn bad_cand(&mut self, _cand: &[Expr], _args: Vec<ExprVal>, _val: Vec<ExprVal>) {
         // A brute doesn't learn
     }
 
-    fn next_expr(&mut self) -> Option<Expr> {
+    fn next_expr(&mut self) -> Option<Vec<Expr>> {
         self.breadth.next()
     }
 }
\ No newline at end of file
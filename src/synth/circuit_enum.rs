@@ -1,9 +1,10 @@
-use std::{ops::Deref, rc::Rc};
+use std::{cell::RefCell, ops::Deref, rc::Rc};
 
-use log::info;
+use log::{debug, info, warn};
 use z3::ast::Ast;
 
-use crate::expr::{BinopKind, Expr, ExprVal, Variable, BITS_PER_VAL};
+use crate::dag::{Dag, Node, NodeId};
+use crate::expr::{val_from_z3, val_to_z3, BinopKind, BoolExpr, CmpKind, Expr, ExprVal, OpSet, Signedness, UnopKind, Variable};
 
 use super::Synthesizer;
 
@@ -18,6 +19,7 @@ struct Connection<'ctx> {
 impl<'ctx> Connection<'ctx> {
     fn new(
         z3: &'ctx z3::Context,
+        width: u32,
         val_s: &'static str,
         loc_s: &'static str,
     ) -> Connection<'ctx> {
@@ -27,7 +29,7 @@ impl<'ctx> Connection<'ctx> {
             val: z3::ast::BV::fresh_const(
                 z3,
                 val_s,
-                BITS_PER_VAL
+                width
             ),
             loc: z3::ast::Int::fresh_const(
                 z3,
@@ -40,41 +42,45 @@ impl<'ctx> Connection<'ctx> {
         Connection {
             val_s: self.val_s,
             loc_s: self.loc_s,
-            val: z3::ast::BV::fresh_const(z3, self.val_s, BITS_PER_VAL),
+            val: z3::ast::BV::fresh_const(z3, self.val_s, self.val.get_size()),
             loc: self.loc.clone(),
         }
     }
 }
 
-fn new_arg<'ctx>(z3: &'ctx z3::Context) -> Connection<'ctx> {
-    Connection::new(z3, "ca", "cal")
+fn new_arg(z3: &z3::Context, width: u32) -> Connection<'_> {
+    Connection::new(z3, width, "ca", "cal")
 }
 
-fn new_const<'ctx>(z3: &'ctx z3::Context) -> z3::ast::BV<'ctx> {
-    z3::ast::BV::fresh_const(z3, "cc", BITS_PER_VAL)
+fn new_const(z3: &z3::Context, width: u32) -> z3::ast::BV<'_> {
+    z3::ast::BV::fresh_const(z3, "cc", width)
 }
 
-fn new_input<'ctx>(z3: &'ctx z3::Context) -> Connection<'ctx> {
-    Connection::new(z3, "ci", "cil")
+fn new_input(z3: &z3::Context, width: u32) -> Connection<'_> {
+    Connection::new(z3, width, "ci", "cil")
 }
 
-fn new_output<'ctx>(z3: &'ctx z3::Context) -> Connection<'ctx> {
-    Connection::new(z3, "co", "col")
+fn new_output(z3: &z3::Context, width: u32) -> Connection<'_> {
+    Connection::new(z3, width, "co", "col")
 }
 
-fn new_result<'ctx>(z3: &'ctx z3::Context) -> Connection<'ctx> {
-    Connection::new(z3, "cr", "crl")
+fn new_result(z3: &z3::Context, width: u32) -> Connection<'_> {
+    Connection::new(z3, width, "cr", "crl")
 }
 
 struct ComponentTemplate(Expr);
 
 impl ComponentTemplate {
-    fn is_shr_const(&self) -> bool {
+    fn is_shift_const(&self) -> bool {
         match &self.0 {
-            Expr::Binop(BinopKind::ShrA, l, r) => match (l.deref(), r.deref()) {
-                (Expr::Variable(Variable::Argument(_)), Expr::Variable(Variable::UnknownConst)) => true,
-                _ => false,
-            }
+            Expr::Binop(
+                BinopKind::ShrA | BinopKind::ShrL | BinopKind::Rotl | BinopKind::Rotr,
+                l,
+                r,
+            ) => matches!(
+                (l.deref(), r.deref()),
+                (Expr::Variable(Variable::Argument(_)), Expr::Variable(Variable::UnknownConst)),
+            ),
             _ => false,
         }
     }
@@ -82,25 +88,27 @@ impl ComponentTemplate {
     fn spec<'ctx>(
         &self,
         z3: &'ctx z3::Context,
+        width: u32,
         output: &Connection<'ctx>,
         inputs: &[Connection<'ctx>],
         constants: &[z3::ast::BV<'ctx>],
     ) -> z3::ast::Bool<'ctx> {
         let expr = self.0.to_z3(
             z3,
+            width,
             |_, idx| constants[idx].clone(),
             |_, idx| inputs[idx].val.clone(),
         );
 
-        // Z3 can bitshift values by more than 32 bits.
+        // Z3 can bitshift values by more than the bit width.
         // This constraint blocks it from doing so.
-        if self.is_shr_const() {
-            let top = z3::ast::BV::from_i64(&z3, 32, BITS_PER_VAL);
-            let bot = z3::ast::BV::from_i64(&z3, 0, BITS_PER_VAL);
+        if self.is_shift_const() {
+            let top = val_to_z3(z3, width as ExprVal, width);
+            let bot = val_to_z3(z3, 0, width);
 
             return z3::ast::Bool::and(z3, &[
-                &constants[0].bvslt(&top),
-                &constants[0].bvsgt(&bot),
+                &constants[0].bvult(&top),
+                &constants[0].bvugt(&bot),
                 &output.val._eq(&expr),
             ]);
         }
@@ -116,6 +124,8 @@ impl ComponentTemplate {
             },
             &mut |_, x| x,
             &mut |_, l, r| l + r,
+            &mut |_, l, r| l + r,
+            &mut |c, t, e| c + t + e,
             &mut |x| x,
         )
     }
@@ -128,6 +138,8 @@ impl ComponentTemplate {
             },
             &mut |_, x| x,
             &mut |_, l, r| l + r,
+            &mut |_, l, r| l + r,
+            &mut |c, t, e| c + t + e,
             &mut |x| x,
         )
     }
@@ -157,21 +169,38 @@ impl<'ctx> Component<'ctx> {
 struct LibrarySpec<'ctx> {
     components: Vec<Component<'ctx>>,
     args: Vec<Connection<'ctx>>,
-    result: Connection<'ctx>,
+    results: Vec<Connection<'ctx>>,
 }
 
 struct Library {
+    width: u32,
+    signedness: Signedness,
+    /// The operators the components may use
+    ops: OpSet,
     template: Vec<ComponentTemplate>,
     components: Vec<usize>,
 }
 
 impl Library {
+    /// Add a component for every optional template, which uses only
+    /// the operators from `extra`. The operators, which the search
+    /// doesn't allow, are left out.
+    fn add_components(&mut self, extra: &OpSet) {
+        for idx in OPTIONAL_TEMPLATES..self.template.len() {
+            let template = &self.template[idx].0;
+
+            if extra.allows(template) && self.ops.allows(template) && !self.components.contains(&idx) {
+                self.components.push(idx);
+            }
+        }
+    }
+
     fn template_for(&self, comp_idx: usize) -> &ComponentTemplate {
         &self.template[self.components[comp_idx]]
     }
 
     /// Constructs a circuit constraint for a given "run", described
-    /// by `values` (the inputs) and `expected` (expected output).
+    /// by `values` (the inputs) and `expected` (expected outputs).
     ///
     /// Since we can't re-use the variables from previous runs, this
     /// leads us to construcing new `Connection`s, which use same
@@ -182,12 +211,14 @@ impl Library {
         solver: &z3::Solver<'ctx>,
         lib_spec: &LibrarySpec<'ctx>,
         values: &[ExprVal],
-        expected: ExprVal,
+        expected: &[ExprVal],
     ) {
         let args = lib_spec.args.iter()
             .map(|x| x.new_case(z3))
             .collect::<Vec<_>>();
-        let result = lib_spec.result.new_case(z3);
+        let results = lib_spec.results.iter()
+            .map(|x| x.new_case(z3))
+            .collect::<Vec<_>>();
         let components = lib_spec.components.iter()
             .map(|x| x.new_case(z3))
             .collect::<Vec<_>>();
@@ -195,6 +226,7 @@ impl Library {
             let template = &self.template[*template_idx];
             solver.assert(&template.spec(
                 z3,
+                self.width,
                 &component.output,
                 &component.inputs,
                 &component.constants,
@@ -206,13 +238,13 @@ impl Library {
             components.iter()
                 .flat_map(|x| x.all_connections())
                 .chain(&args)
-                .chain(std::iter::once(&result));
+                .chain(&results);
         for (i_x, x) in all_connections.into_iter().enumerate() {
             let all_connections =
                 components.iter()
                     .flat_map(|x| x.all_connections())
                     .chain(&args)
-                    .chain(std::iter::once(&result));
+                    .chain(&results);
             for y in all_connections.skip(i_x + 1) {
                 solver.assert(
                     &(x.loc._eq(&y.loc))
@@ -222,36 +254,68 @@ impl Library {
         }
 
         /* The test */
-        let expected = z3::ast::BV::from_i64(
-            z3,
-            expected as i64,
-            BITS_PER_VAL
-        );
-        solver.assert(&result.val._eq(&expected));
+        for (expected, result) in expected.iter().zip(&results) {
+            solver.assert(&result.val._eq(&val_to_z3(z3, *expected, self.width)));
+        }
         for (arg, conn) in values.iter().zip(args) {
-            solver.assert(&conn.val._eq(&z3::ast::BV::from_i64(
-                z3,
-                *arg as i64,
-                BITS_PER_VAL
-            )));
+            solver.assert(&conn.val._eq(&val_to_z3(z3, *arg, self.width)));
         }
     }
 
+    /// Forbid the live part of `circuit` from being synthesized again.
+    /// The components, which no result depends on, are free to change.
+    fn block_spec<'ctx>(
+        &self,
+        z3: &'ctx z3::Context,
+        solver: &z3::Solver<'ctx>,
+        lib_spec: &LibrarySpec<'ctx>,
+        circuit: &Circuit,
+    ) {
+        let at = |x: &Connection<'ctx>, loc: usize| x.loc._eq(&z3::ast::Int::from_u64(z3, loc as u64));
+        let live = circuit.live();
+
+        let results = lib_spec.results.iter()
+            .zip(&circuit.results)
+            .map(|(x, loc)| at(x, *loc));
+        let components = lib_spec.components.iter()
+            .zip(&circuit.components)
+            .filter(|(_, decoded)| live[decoded.output])
+            .flat_map(|(component, decoded)| {
+                let inputs = component.inputs.iter()
+                    .zip(&decoded.inputs)
+                    .map(|(x, loc)| at(x, *loc));
+                let constants = component.constants.iter()
+                    .zip(&decoded.constants)
+                    .map(|(x, val)| x._eq(&val_to_z3(z3, *val, self.width)));
+
+                std::iter::once(at(&component.output, decoded.output))
+                    .chain(inputs)
+                    .chain(constants)
+                    .collect::<Vec<_>>()
+            });
+        let same = results.chain(components).collect::<Vec<_>>();
+
+        solver.assert(&!z3::ast::Bool::and(z3, &same.iter().collect::<Vec<_>>()));
+    }
+
     /// A bunch of constrains to put Z3 on the right track.
     fn wf_spec<'ctx>(
         &self,
         arg_count: usize,
+        out_count: usize,
         z3: &'ctx z3::Context,
         solver: &z3::Solver<'ctx>,
     ) -> LibrarySpec<'ctx> {
         let loc_count = arg_count + self.components.len();
-        let result = new_result(z3);
-        let args = std::iter::from_fn(|| Some(new_arg(z3)))
+        let results = std::iter::from_fn(|| Some(new_result(z3, self.width)))
+            .take(out_count)
+            .collect::<Vec<_>>();
+        let args = std::iter::from_fn(|| Some(new_arg(z3, self.width)))
             .take(arg_count)
             .collect::<Vec<_>>();
-        let zero = z3::ast::Int::from_u64(&z3, 0);
-        let loc_count = z3::ast::Int::from_u64(&z3, loc_count as u64);
-        let arg_count = z3::ast::Int::from_u64(&z3, arg_count as u64);
+        let zero = z3::ast::Int::from_u64(z3, 0);
+        let loc_count = z3::ast::Int::from_u64(z3, loc_count as u64);
+        let arg_count = z3::ast::Int::from_u64(z3, arg_count as u64);
 
         let mut components = Vec::<Component<'ctx>>::new();
 
@@ -259,11 +323,11 @@ impl Library {
         for component in &self.components {
             let template = &self.template[*component];
             let component = Component {
-                output: new_output(z3),
-                constants: std::iter::from_fn(|| Some(new_const(z3)))
+                output: new_output(z3, self.width),
+                constants: std::iter::from_fn(|| Some(new_const(z3, self.width)))
                     .take(template.const_count())
                     .collect(),
-                inputs: std::iter::from_fn(|| Some(new_input(z3)))
+                inputs: std::iter::from_fn(|| Some(new_input(z3, self.width)))
                     .take(template.input_count())
                     .collect(),
             };
@@ -307,19 +371,21 @@ impl Library {
             solver.assert(&zero.le(&x.loc));
             solver.assert(&x.loc.lt(&arg_count));
         }
-        solver.assert(&zero.le(&result.loc));
-        solver.assert(&result.loc.lt(&loc_count));
+        for x in results.iter() {
+            solver.assert(&zero.le(&x.loc));
+            solver.assert(&x.loc.lt(&loc_count));
+        }
 
         LibrarySpec {
             args,
             components,
-            result,
+            results,
         }
     }
 }
 
 struct TestStorage {
-    tests: Vec<(Vec<ExprVal>, ExprVal)>,
+    tests: Vec<(Vec<ExprVal>, Vec<ExprVal>)>,
 }
 
 impl TestStorage {
@@ -329,7 +395,7 @@ impl TestStorage {
         }
     }
 
-    fn add_test(&mut self, args: Vec<ExprVal>, expected: ExprVal) {
+    fn add_test(&mut self, args: Vec<ExprVal>, expected: Vec<ExprVal>) {
         self.tests.push((args, expected))
     }
 
@@ -346,7 +412,7 @@ impl TestStorage {
                 solver,
                 lib_spec,
                 values,
-                *expected,
+                expected,
             );
         }
     }
@@ -358,6 +424,125 @@ enum ComponentIdx {
     Argument(usize),
 }
 
+/// A component placed into a [Circuit].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircuitComponent {
+    /// The operator of the component. Its [Variable::Argument]s are
+    /// the inputs and its [Variable::UnknownConst]s are the constants,
+    /// in the order [Expr::walk_expr] visits them.
+    pub template: Expr,
+    /// The location of the output
+    pub output: usize,
+    /// The locations the inputs are wired to
+    pub inputs: Vec<usize>,
+    pub constants: Vec<ExprVal>,
+}
+
+/// A circuit decoded from a z3 model. Every argument and every
+/// component output gets its own location, the arguments come first.
+/// The components, which no result depends on, are kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Circuit {
+    /// The locations of the arguments
+    pub args: Vec<usize>,
+    pub components: Vec<CircuitComponent>,
+    /// The locations the results are read from
+    pub results: Vec<usize>,
+}
+
+impl Circuit {
+    fn locations(&self) -> Vec<ComponentIdx> {
+        let mut res = vec![
+            ComponentIdx::Argument(0); self.components.len() + self.args.len()
+        ];
+
+        for (idx, component) in self.components.iter().enumerate() {
+            res[component.output] = ComponentIdx::Common(idx);
+        }
+
+        for (idx, loc) in self.args.iter().enumerate() {
+            res[*loc] = ComponentIdx::Argument(idx);
+        }
+
+        res
+    }
+
+    /// Whether any result depends on the location, for every location.
+    pub fn live(&self) -> Vec<bool> {
+        let mut live = vec![false; self.args.len() + self.components.len()];
+        for result in &self.results {
+            live[*result] = true;
+        }
+
+        // The inputs are located before the output, so going back
+        // from the last location finds everything the results use
+        let mut by_output = self.components.iter().collect::<Vec<_>>();
+        by_output.sort_by_key(|x| std::cmp::Reverse(x.output));
+        for component in by_output {
+            if live[component.output] {
+                for inp in &component.inputs {
+                    live[*inp] = true;
+                }
+            }
+        }
+
+        live
+    }
+
+    /// The expressions computed at the result locations. A component
+    /// output used several times becomes a shared subterm, also
+    /// between the results.
+    pub fn to_exprs(&self) -> Vec<Expr> {
+        // The inputs of a component are located before its output, so
+        // going through the locations in order adds every operand first.
+        let mut dag = Dag::new();
+        let mut loc_nodes = Vec::with_capacity(self.args.len() + self.components.len());
+        for idx in self.locations() {
+            let node = match idx {
+                ComponentIdx::Argument(arg) => dag.add(Node::Variable(Variable::Argument(arg))),
+                ComponentIdx::Common(comp_idx) => Self::add_component(
+                    &mut dag,
+                    &loc_nodes,
+                    &self.components[comp_idx],
+                ),
+            };
+
+            loc_nodes.push(node);
+        }
+
+        self.results.iter()
+            .map(|x| dag.to_expr(loc_nodes[*x]))
+            .collect()
+    }
+
+    fn add_component(
+        dag: &mut Dag,
+        loc_nodes: &[NodeId],
+        component: &CircuitComponent,
+    ) -> NodeId {
+        let mut constants = component.constants.iter();
+        let dag = RefCell::new(dag);
+        let add = |node| dag.borrow_mut().add(node);
+
+        component.template.walk_expr(
+            &mut |v| match v {
+                Variable::UnknownConst => {
+                    let val = *constants.next().unwrap();
+
+                    add(Node::Variable(Variable::Const(val)))
+                },
+                Variable::Const(x) => add(Node::Variable(Variable::Const(*x))),
+                Variable::Argument(inp) => loc_nodes[component.inputs[*inp]],
+            },
+            &mut |kind, e| add(Node::Unop(kind, e)),
+            &mut |kind, l, r| add(Node::Binop(kind, l, r)),
+            &mut |kind, l, r| (kind, l, r),
+            &mut |(kind, l, r), t, e| add(Node::Ite(kind, l, r, t, e)),
+            &mut |x| x,
+        )
+    }
+}
+
 /// Z3-powered component based synthesis.
 ///
 /// This synthesizer stores circuits, which later get transformed
@@ -367,23 +552,45 @@ enum ComponentIdx {
 /// https://www.cs.cmu.edu/~clegoues/courses/15-819O-16sp/notes/notes09-io-synthesis.pdf
 pub struct CircuitEnum<'ctx> {
     arg_count: usize,
+    out_count: usize,
     solver: z3::Solver<'ctx>,
     z3: &'ctx z3::Context,
     library: Library,
     tests: TestStorage,
+    last_circuit: Option<Circuit>,
+    /// The circuits of the candidates the oracle has failed to decide on
+    blocked: Vec<Circuit>,
 }
 
 impl<'ctx> CircuitEnum<'ctx> {
-    fn synth_expr(&self) -> Option<Expr> {
+    /// The circuit of the last submitted candidate.
+    pub fn last_circuit(&self) -> Option<&Circuit> {
+        self.last_circuit.as_ref()
+    }
+
+    /// Extend the library with the optional components, which use only
+    /// the operators from `extra`: multiplication, division, rotations,
+    /// selects on the comparisons and the intrinsics. Every component
+    /// slows the synthesis down, so only the default library is used
+    /// otherwise. The components, which the operators of the search
+    /// don't allow, are not added.
+    pub fn add_components(&mut self, extra: &OpSet) {
+        self.library.add_components(extra);
+    }
+
+    fn synth_expr(&mut self) -> Option<Vec<Expr>> {
         let Some((model, lib_spec)) = self.synth_circuit()
             else {
                 info!("I have failed");
                 return None;
             };
 
-        let e = self.circuit_model_to_expr(&lib_spec, &model);
+        let circuit = self.decode_circuit(&lib_spec, &model);
+        let e = circuit.to_exprs();
 
         info!("Submitted: {e:?}");
+        debug!("Circuit:\n{}", circuit.to_dot());
+        self.last_circuit = Some(circuit);
 
         Some(e)
     }
@@ -395,6 +602,10 @@ impl<'ctx> CircuitEnum<'ctx> {
         let lib_spec = self.prepare_spec();
         let check_result = self.solver.check();
         info!("Z3 syntesizing: {check_result:?}");
+        if check_result == z3::SatResult::Unknown {
+            let reason = self.solver.get_reason_unknown().unwrap_or_default();
+            warn!("Circuit synthesis was inconclusive: {reason}");
+        }
         if check_result == z3::SatResult::Sat {
             model = Some((
                 self.solver.get_model().unwrap(),
@@ -409,6 +620,7 @@ impl<'ctx> CircuitEnum<'ctx> {
     fn prepare_spec(&self) -> LibrarySpec<'ctx> {
         let lib_spec = self.library.wf_spec(
             self.arg_count,
+            self.out_count,
             self.z3,
             &self.solver,
         );
@@ -420,118 +632,59 @@ impl<'ctx> CircuitEnum<'ctx> {
             &self.solver,
         );
 
+        for circuit in &self.blocked {
+            self.library.block_spec(self.z3, &self.solver, &lib_spec, circuit);
+        }
+
         lib_spec
     }
 
-    fn learn(&mut self, args: Vec<ExprVal>, res: ExprVal) {
+    fn learn(&mut self, args: Vec<ExprVal>, res: Vec<ExprVal>) {
         self.tests.add_test(args, res);
     }
 
-    fn circuit_model_to_expr(
+    fn decode_circuit(
         &self,
         lib_spec: &LibrarySpec<'ctx>,
         model: &z3::Model<'ctx>,
-    ) -> Expr {
-        let start_loc_idx = model.get_const_interp(
-            &lib_spec.result.loc,
-            )
+    ) -> Circuit {
+        let loc = |x: &z3::ast::Int<'ctx>| model.get_const_interp(x)
             .unwrap()
             .as_u64()
             .unwrap() as usize;
-        let mut component_idx = vec![
-            ComponentIdx::Argument(0); lib_spec.components.len() + lib_spec.args.len()
-        ];
-
-        for (idx, component) in lib_spec.components.iter().enumerate() {
-            let loc = model.get_const_interp(&component.output.loc)
-                .unwrap()
-                .as_u64()
-                .unwrap() as usize;
-            component_idx[loc] = ComponentIdx::Common(idx);
-        }
-
-        for (idx, arg) in lib_spec.args.iter().enumerate() {
-            let loc = model.get_const_interp(&arg.loc)
-                .unwrap()
-                .as_u64()
-                .unwrap() as usize;
-            component_idx[loc] = ComponentIdx::Argument(idx);
-        }
-
-        info!("start: {start_loc_idx:}");
-        info!("assign {component_idx:?}");
 
-        self.build_expr_from_model_rec(
-            start_loc_idx,
-            &component_idx,
-            lib_spec,
-            model,
-        )
-    }
-
-    fn build_expr_from_model_rec(
-        &self,
-        loc: usize,
-        component_idx: &[ComponentIdx],
-        lib_spec: &LibrarySpec<'ctx>,
-        model: &z3::Model<'ctx>,
-    ) -> Expr {
-        let idx = component_idx[loc];
-        let comp_idx = match idx {
-            ComponentIdx::Common(x) => x,
-            ComponentIdx::Argument(arg) => {
-                return Expr::Variable(
-                    Variable::Argument(arg)
-                )
-            },
+        let components = lib_spec.components.iter()
+            .enumerate()
+            .map(|(comp_idx, component)| CircuitComponent {
+                template: self.library.template_for(comp_idx).0.clone(),
+                output: loc(&component.output.loc),
+                inputs: component.inputs.iter().map(|x| loc(&x.loc)).collect(),
+                // The constants of the unused components may be
+                // left out of the model, so it gets completed
+                constants: component.constants.iter()
+                    .map(|c| model.eval(c, true)
+                        .and_then(|x| val_from_z3(&x, self.library.signedness))
+                        .unwrap())
+                    .collect(),
+            })
+            .collect();
+        let circuit = Circuit {
+            args: lib_spec.args.iter().map(|x| loc(&x.loc)).collect(),
+            components,
+            results: lib_spec.results.iter().map(|x| loc(&x.loc)).collect(),
         };
 
-        let mut const_idx = 0;
-        let template_idx = self.library.template_for(comp_idx);
-        let component = &lib_spec.components[comp_idx];
+        info!("start: {:?}", circuit.results);
+        info!("assign {:?}", circuit.locations());
 
-        template_idx.0.walk_expr(
-            &mut |v| match v {
-                Variable::UnknownConst => {
-                    let c = &component.constants[const_idx];
-                    let val = model.get_const_interp(c)
-                        .unwrap()
-                        .as_i64()
-                        .unwrap() as ExprVal;
-                    const_idx += 1;
-
-                    Expr::Variable(Variable::Const(val))
-                },
-                Variable::Const(x) => Expr::Variable(Variable::Const(*x)),
-                Variable::Argument(inp) => {
-                    let inp = &component.inputs[*inp];
-                    let loc = model.get_const_interp(
-                            &inp.loc
-                        )
-                        .unwrap()
-                        .as_u64()
-                        .unwrap() as usize;
-
-                    self.build_expr_from_model_rec(
-                        loc,
-                        component_idx,
-                        lib_spec,
-                        model,
-                    )
-                },
-            },
-            &mut |kind, e| {
-                Expr::Unop(kind, Rc::new(e))
-            },
-            &mut |kind, l, r| {
-                Expr::Binop(kind, Rc::new(l), Rc::new(r))
-            },
-            &mut |x| x,
-        )
+        circuit
     }
 }
 
-fn default_lib() -> Library {
+/// The templates before this one make up the default library
+const OPTIONAL_TEMPLATES: usize = 8;
+
+fn default_lib(width: u32, signedness: Signedness, ops: &OpSet) -> Library {
     let template = vec![
         ComponentTemplate(Expr::Binop(
             BinopKind::And,
@@ -568,40 +721,152 @@ fn default_lib() -> Library {
             Rc::new(Expr::Variable(Variable::Argument(0))),
             Rc::new(Expr::Variable(Variable::UnknownConst)),
         )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::ShrL,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::UnknownConst)),
+        )),
+
+        // Optional components. They are not part of the default
+        // library, since every extra component slows the solver down.
+        // See [CircuitEnum::add_components].
+        ComponentTemplate(Expr::Binop(
+            BinopKind::Mul,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::UnknownConst)),
+        )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::Mul,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::Argument(1))),
+        )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::SDiv,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::Argument(1))),
+        )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::UDiv,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::Argument(1))),
+        )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::SRem,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::Argument(1))),
+        )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::URem,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::Argument(1))),
+        )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::Rotl,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::UnknownConst)),
+        )),
+        ComponentTemplate(Expr::Binop(
+            BinopKind::Rotr,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+            Rc::new(Expr::Variable(Variable::UnknownConst)),
+        )),
+        // Select on the sign, i.e. `x < 0 ? a : b`
+        ComponentTemplate(Expr::Ite(
+            BoolExpr::Cmp(
+                CmpKind::Slt,
+                Rc::new(Expr::Variable(Variable::Argument(0))),
+                Rc::new(Expr::Variable(Variable::Const(0))),
+            ),
+            Rc::new(Expr::Variable(Variable::Argument(1))),
+            Rc::new(Expr::Variable(Variable::Argument(2))),
+        )),
+        ComponentTemplate(Expr::Ite(
+            BoolExpr::Cmp(
+                CmpKind::Ult,
+                Rc::new(Expr::Variable(Variable::Argument(0))),
+                Rc::new(Expr::Variable(Variable::Argument(1))),
+            ),
+            Rc::new(Expr::Variable(Variable::Argument(2))),
+            Rc::new(Expr::Variable(Variable::Argument(3))),
+        )),
+        ComponentTemplate(Expr::Unop(
+            UnopKind::Popcnt,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+        )),
+        ComponentTemplate(Expr::Unop(
+            UnopKind::Clz,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+        )),
+        ComponentTemplate(Expr::Unop(
+            UnopKind::Ctz,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+        )),
+        ComponentTemplate(Expr::Unop(
+            UnopKind::Bswap,
+            Rc::new(Expr::Variable(Variable::Argument(0))),
+        )),
     ];
 
+    // Unsigned problems are far more likely to need
+    // zeroes shifted in rather than the sign bit.
+    let shr = match signedness {
+        Signedness::Signed => 6,
+        Signedness::Unsigned => 7,
+    };
+
+    let mut components = vec![
+        2, 3, shr, shr,
+
+        // This is the old "library". Turned out
+        // to cause too much runtime for the synthesizer
+        // 0, 0, 0,
+        // 1, 1, 1,
+        // 2, 2, 2,
+        // 3, 3, 3,
+        // 4, 5, 6,
+    ];
+    components.retain(|x: &usize| ops.allows(&template[*x].0));
+
     Library {
+        width,
+        signedness,
+        ops: ops.clone(),
         template,
-        components: vec![
-            2, 3, 6, 6,
-
-            // This is the old "library". Turned out
-            // to cause too much runtime for the synthesizer
-            // 0, 0, 0,
-            // 1, 1, 1,
-            // 2, 2, 2,
-            // 3, 3, 3,
-            // 4, 5, 6,
-        ],
+        components,
     }
 }
 
 impl<'ctx> Synthesizer<'ctx> for CircuitEnum<'ctx> {
-    fn build(z3: &'ctx z3::Context, var_count: usize, _depth_limit: usize) -> Self {
+    fn build(
+        z3: &'ctx z3::Context,
+        width: u32,
+        signedness: Signedness,
+        ops: &OpSet,
+        var_count: usize,
+        out_count: usize,
+        _depth_limit: usize,
+    ) -> Self {
         Self {
             arg_count: var_count,
+            out_count,
             solver: z3::Solver::new(z3),
             z3,
-            library: default_lib(),
+            library: default_lib(width, signedness, ops),
             tests: TestStorage::new(),
+            last_circuit: None,
+            blocked: Vec::new(),
         }
     }
 
-    fn bad_cand(&mut self, _cand: &Expr, args: Vec<ExprVal>, expected: ExprVal) {
+    fn bad_cand(&mut self, _cand: &[Expr], args: Vec<ExprVal>, expected: Vec<ExprVal>) {
         self.learn(args, expected);
     }
 
-    fn next_expr(&mut self) -> Option<Expr> {
+    fn unknown_cand(&mut self, _cand: &[Expr]) {
+        self.blocked.extend(self.last_circuit.clone());
+    }
+
+    fn next_expr(&mut self) -> Option<Vec<Expr>> {
         self.synth_expr()
     }
 }
\ No newline at end of file
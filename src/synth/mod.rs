@@ -2,22 +2,131 @@ pub mod simple_search;
 pub mod brute_enum;
 pub mod circuit_enum;
 
-use crate::expr::{Expr, ExprVal};
+use std::iter::FusedIterator;
+
+use crate::expr::{Expr, ExprVal, OpSet, Signedness};
 
 /// A synthesizer is an iterator-like structure. It can generate
 /// new expression candidates, but in addition to that it can also
 /// be provided with examples to "learn". This allows synthesizers
 /// to speed up the search.
+///
+/// A candidate has an expression for every output of the function.
 pub trait Synthesizer<'ctx> {
     /// Construct the synthesizer, letting it do all the required initialisation
-    /// routines.
-    fn build(z3: &'ctx z3::Context, var_count: usize, depth_limit: usize) -> Self;
+    /// routines. The synthesized expressions operate on `width`-bit values,
+    /// interpreted according to `signedness`, and may only use the operators
+    /// from `ops`. The candidates have `out_count` expressions.
+    fn build(
+        z3: &'ctx z3::Context,
+        width: u32,
+        signedness: Signedness,
+        ops: &OpSet,
+        var_count: usize,
+        out_count: usize,
+        depth_limit: usize,
+    ) -> Self;
 
     /// Reports to the synthesizer, that the produced candidate is
-    /// "universally bad". This can be used to reduce the search space.
-    fn bad_cand(&mut self, cand: &Expr, args: Vec<ExprVal>, expected: ExprVal);
+    /// "universally bad": on `args` the outputs should have been `expected`.
+    /// This can be used to reduce the search space.
+    fn bad_cand(&mut self, cand: &[Expr], args: Vec<ExprVal>, expected: Vec<ExprVal>);
+
+    /// Reports to the synthesizer, that the produced candidate has no
+    /// input to learn from: either the oracle has failed to check it,
+    /// e.g. because of the timeout, or it is wrong without a universal
+    /// counterexample, or it has been dealt with already: accepted or
+    /// skipped as too costly. The synthesizer shouldn't produce it again.
+    fn unknown_cand(&mut self, _cand: &[Expr]) {}
 
-    /// Query the synthesizer for a next expression to try. The synthesizer
+    /// Query the synthesizer for a next candidate to try. The synthesizer
     /// may return `None` if it can no longer provide any new candidate.
-    fn next_expr(&mut self) -> Option<Expr>;
+    fn next_expr(&mut self) -> Option<Vec<Expr>>;
+}
+
+/// Enumerates the tuples of `out_count` expressions, drawn from
+/// an enumeration of single expressions. The tuples are ordered by
+/// the latest expression they use, so every tuple comes up eventually,
+/// even if the enumeration is endless. With a single output, the
+/// expressions are passed through in order.
+pub struct Tuples<I> {
+    exprs: I,
+    seen: Vec<Expr>,
+    /// The indices into `seen` of the current tuple
+    digits: Vec<usize>,
+    /// The first position of the latest expression in the current tuple.
+    /// The positions before it use only the older expressions.
+    first: usize,
+}
+
+impl<I: Iterator<Item = Expr>> Tuples<I> {
+    pub fn new(exprs: I, out_count: usize) -> Self {
+        assert!(out_count > 0, "No outputs");
+
+        Self {
+            exprs,
+            seen: Vec::new(),
+            digits: vec![0; out_count],
+            first: out_count,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.digits.fill(0);
+        self.digits[self.first] = self.seen.len() - 1;
+    }
+
+    fn increment(&mut self) {
+        let latest = self.seen.len() - 1;
+
+        for (pos, digit) in self.digits.iter_mut().enumerate() {
+            let bound = match pos.cmp(&self.first) {
+                std::cmp::Ordering::Less => latest,
+                std::cmp::Ordering::Equal => continue,
+                std::cmp::Ordering::Greater => latest + 1,
+            };
+
+            if *digit + 1 < bound {
+                *digit += 1;
+                return;
+            }
+
+            *digit = 0;
+        }
+
+        // With a single expression, only the first position can hold it
+        self.first = if latest == 0 { self.digits.len() } else { self.first + 1 };
+        if self.first < self.digits.len() {
+            self.reset();
+        }
+    }
+}
+
+impl<I: Iterator<Item = Expr>> Iterator for Tuples<I> {
+    type Item = Vec<Expr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.first == self.digits.len() {
+            // A single output never uses the older expressions again
+            if self.digits.len() == 1 {
+                self.seen.clear();
+            }
+
+            self.seen.push(self.exprs.next()?);
+            self.first = 0;
+            self.reset();
+        }
+
+        let res = self.digits.iter()
+            .map(|x| self.seen[*x].clone())
+            .collect();
+
+        self.increment();
+
+        Some(res)
+    }
+}
+
+impl<I: FusedIterator<Item = Expr>> FusedIterator for Tuples<I> {
+
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
 use std::rc::Rc;
 
-use crate::expr::{Expr, ExprVal, Variable};
+use crate::expr::{Expr, ExprVal, OpSet, Signedness, Variable};
 
-use super::Synthesizer;
+use super::{Synthesizer, Tuples};
 
 
 /// This synthesizer simply tries a few
@@ -10,48 +10,52 @@ use super::Synthesizer;
 /// functions.
 pub struct SimpleSearch {
     arg_cnt: usize,
-    db: Vec<Expr>,
-    last_tried: usize,
+    db: Tuples<std::vec::IntoIter<Expr>>,
 }
 
 impl SimpleSearch {
-    pub fn new(arg_cnt: usize) -> Self {
+    pub fn new(arg_cnt: usize, out_count: usize, ops: &OpSet) -> Self {
+        let mut db = vec![
+            Expr::Variable(Variable::UnknownConst),
+            Expr::Variable(Variable::Argument(0)),
+            Expr::Binop(
+                crate::expr::BinopKind::And,
+                Rc::new(Expr::Variable(Variable::Argument(0))),
+                Rc::new(Expr::Variable(Variable::UnknownConst)),
+            )
+        ];
+
+        db.retain(|x| ops.allows(x));
+
         SimpleSearch {
             arg_cnt,
-            last_tried: 0,
-            db: vec![
-                Expr::Variable(Variable::UnknownConst),
-                Expr::Variable(Variable::Argument(0)),
-                Expr::Binop(
-                    crate::expr::BinopKind::And,
-                    Rc::new(Expr::Variable(Variable::Argument(0))),
-                    Rc::new(Expr::Variable(Variable::UnknownConst)),
-                )
-            ],
-         }
+            db: Tuples::new(db.into_iter(), out_count),
+        }
     }
 }
 
 impl<'ctx> Synthesizer<'ctx> for SimpleSearch {
-    fn build(_z3: &'ctx z3::Context, var_count: usize, _depth_limit: usize) -> Self {
-        Self::new(var_count)
+    fn build(
+        _z3: &'ctx z3::Context,
+        _width: u32,
+        _signedness: Signedness,
+        ops: &OpSet,
+        var_count: usize,
+        out_count: usize,
+        _depth_limit: usize,
+    ) -> Self {
+        Self::new(var_count, out_count, ops)
     }
 
-    fn bad_cand(&mut self, _expr: &Expr, _args: Vec<ExprVal>, _val: ExprVal) {
+    fn bad_cand(&mut self, _cand: &[Expr], _args: Vec<ExprVal>, _val: Vec<ExprVal>) {
         /* We do not learn. */
     }
 
-    fn next_expr(&mut self) -> Option<Expr> {
+    fn next_expr(&mut self) -> Option<Vec<Expr>> {
         if self.arg_cnt < 1 {
             return None;
         }
 
-        match self.db.get(self.last_tried) {
-            None => None,
-            Some(x) => {
-                self.last_tried += 1;
-                Some(x.clone())
-            },
-        }
+        self.db.next()
     }
 }
\ No newline at end of file
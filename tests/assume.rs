@@ -0,0 +1,73 @@
+mod common;
+
+use bitsynth::expr::OpSet;
+use bitsynth::oracle::Outcome;
+use bitsynth::search::{BithackSearch, Reference};
+use bitsynth::synth::brute_enum::BruteEnum;
+use bitsynth::synth::circuit_enum::CircuitEnum;
+use bitsynth::synth::Synthesizer;
+use common::{find_answer, parse};
+
+/// Dividing by 4 is shifting by 2, as long as `x` isn't negative
+const ASSUME_PROMPT: &str = "(assert (= res (bvsdiv x #x04)))";
+const ASSUME_NON_NEGATIVE: &str = "(assert (bvsge x #x00))";
+
+fn searcher<'ctx, S: Synthesizer<'ctx>>(z3: &'ctx z3::Context, should_learn: bool) -> BithackSearch<'ctx, S> {
+    let mut search = common::searcher(z3, should_learn, &OpSet::all(), &["x"], &["res"], 1);
+    search.parse_prompt(ASSUME_PROMPT).unwrap();
+
+    search
+}
+
+#[test]
+fn test_assume_check() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher::<BruteEnum>(&z3, false);
+    assert!(!search.check_answer(&[parse("(x >> 2)")]));
+
+    search.parse_assumptions(ASSUME_NON_NEGATIVE).unwrap();
+    assert_eq!(search.oracle().assumptions().len(), 1);
+    assert!(search.check_answer(&[parse("(x >> 2)")]));
+    assert!(search.check_answer(&[parse("(x >>> 2)")]));
+    assert!(!search.check_answer(&[parse("(x >> 1)")]));
+
+    // The reference is restricted the same way
+    search.parse_reference(&[Reference::Term("(bvsdiv x #x04)".to_string())]).unwrap();
+    assert!(search.check_answer(&[parse("(x >> 2)")]));
+
+    search.parse_assumptions("").unwrap();
+    assert!(search.oracle().assumptions().is_empty());
+    assert!(!search.check_answer(&[parse("(x >> 2)")]));
+}
+
+#[test]
+fn test_assume_counterexample() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher::<BruteEnum>(&z3, true);
+    let cand = [search.converter().ans_expr_to_z3(&parse("(x >> 2)"))];
+    let x = search.converter().z3_args()[0].clone();
+
+    // The counterexample is a negative number, which isn't a multiple of 4
+    let Outcome::Found(model) = search.oracle().counterexample(&cand, []) else {
+        panic!("No counterexample found");
+    };
+    let x_val = model.eval(&x, true).unwrap().as_i64().unwrap();
+    assert!(x_val >= 128, "{x_val}");
+
+    search.parse_assumptions(ASSUME_NON_NEGATIVE).unwrap();
+    assert!(matches!(search.oracle().counterexample(&cand, []), Outcome::NotFound));
+}
+
+#[test]
+fn test_assume_search() {
+    let z3 = z3::Context::new(&z3::Config::default());
+
+    let mut search = searcher::<BruteEnum>(&z3, false);
+    search.parse_assumptions(ASSUME_NON_NEGATIVE).unwrap();
+    assert_eq!(find_answer(&mut search), [parse("(x >> 2)")]);
+
+    let mut search = searcher::<CircuitEnum>(&z3, true);
+    search.parse_assumptions(ASSUME_NON_NEGATIVE).unwrap();
+    let answer = find_answer(&mut search);
+    assert!(search.check_answer(&answer));
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use std::rc::Rc;
+
+use bitsynth::canon::Canonicalizer;
+use bitsynth::expr::{BinopKind, BoolExpr, CmpKind, Expr, OpSet, Signedness, Variable};
+use bitsynth::search::SearchStep;
+use bitsynth::synth::brute_enum::BruteEnum;
+use common::{arg, constant, unknown, TEST_WIDTH};
+
+pub const CANON_SEARCH_LIMIT: usize = 10_000;
+
+fn canon(expr: &Expr) -> Expr {
+    Canonicalizer::new(TEST_WIDTH, Signedness::Signed).canonicalize(expr)
+}
+
+fn binop(kind: BinopKind, l: Rc<Expr>, r: Rc<Expr>) -> Rc<Expr> {
+    Rc::new(Expr::Binop(kind, l, r))
+}
+
+fn assert_same(l: &Expr, r: &Expr) {
+    assert_eq!(canon(l), canon(r), "{l:?} and {r:?}");
+}
+
+fn assert_different(l: &Expr, r: &Expr) {
+    assert_ne!(canon(l), canon(r), "{l:?} and {r:?}");
+}
+
+#[test]
+fn test_canon_commutative() {
+    assert_same(
+        &binop(BinopKind::And, arg(0), unknown()),
+        &binop(BinopKind::And, unknown(), arg(0)),
+    );
+    assert_same(
+        &binop(BinopKind::Mul, arg(1), arg(0)),
+        &binop(BinopKind::Mul, arg(0), arg(1)),
+    );
+    assert_same(
+        &Expr::Ite(BoolExpr::Cmp(CmpKind::Eq, arg(0), arg(1)), arg(0), arg(1)),
+        &Expr::Ite(BoolExpr::Cmp(CmpKind::Eq, arg(1), arg(0)), arg(0), arg(1)),
+    );
+    assert_different(
+        &binop(BinopKind::Minus, arg(0), arg(1)),
+        &binop(BinopKind::Minus, arg(1), arg(0)),
+    );
+    assert_different(
+        &Expr::Ite(BoolExpr::Cmp(CmpKind::Slt, arg(0), arg(1)), arg(0), arg(1)),
+        &Expr::Ite(BoolExpr::Cmp(CmpKind::Slt, arg(1), arg(0)), arg(0), arg(1)),
+    );
+}
+
+#[test]
+fn test_canon_associative() {
+    assert_same(
+        &binop(BinopKind::Plus, binop(BinopKind::Plus, arg(0), arg(1)), arg(2)),
+        &binop(BinopKind::Plus, arg(2), binop(BinopKind::Plus, arg(1), arg(0))),
+    );
+    assert_same(
+        &binop(BinopKind::Xor, binop(BinopKind::Xor, arg(0), arg(1)), binop(BinopKind::Xor, arg(2), arg(3))),
+        &binop(BinopKind::Xor, arg(3), binop(BinopKind::Xor, arg(2), binop(BinopKind::Xor, arg(1), arg(0)))),
+    );
+    assert_different(
+        &binop(BinopKind::Plus, binop(BinopKind::Xor, arg(0), arg(1)), arg(2)),
+        &binop(BinopKind::Xor, binop(BinopKind::Plus, arg(0), arg(1)), arg(2)),
+    );
+}
+
+#[test]
+fn test_canon_subtraction() {
+    assert_same(
+        &binop(BinopKind::Minus, arg(0), constant(3)),
+        &binop(BinopKind::Plus, constant(-3), arg(0)),
+    );
+    assert_same(
+        &binop(BinopKind::Minus, arg(0), constant(-128)),
+        &binop(BinopKind::Plus, arg(0), constant(-128)),
+    );
+    assert_same(
+        &binop(BinopKind::Minus, arg(0), unknown()),
+        &binop(BinopKind::Plus, unknown(), arg(0)),
+    );
+}
+
+#[test]
+fn test_canon_preserves_value() {
+    let exprs = [
+        binop(BinopKind::Minus, binop(BinopKind::Plus, arg(0), arg(1)), constant(7)),
+        binop(BinopKind::Mul, binop(BinopKind::Mul, arg(1), constant(3)), binop(BinopKind::Minus, arg(0), constant(-1))),
+        binop(BinopKind::Or, arg(1), binop(BinopKind::Shl, binop(BinopKind::And, arg(0), arg(1)), constant(2))),
+    ];
+
+    for expr in exprs {
+        let canonical = canon(&expr);
+
+        for (x, y) in [(0, 0), (1, -1), (-128, 127), (37, -90)] {
+            let eval = |e: &Expr| e.compute(TEST_WIDTH, Signedness::Signed, |v| match v {
+                Variable::Argument(0) => x,
+                Variable::Argument(_) => y,
+                Variable::Const(c) => c,
+                Variable::UnknownConst => unreachable!(),
+            });
+
+            assert_eq!(eval(&expr), eval(&canonical), "{expr:?} on x={x}, y={y}");
+        }
+    }
+}
+
+#[test]
+fn test_search_skips_duplicates() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<BruteEnum>(&z3, false, &OpSet::portable(), &["x", "y"], &["res"], 2);
+    search.parse_prompt("(assert (= res (bvand (bvor y x) #x0f)))").unwrap();
+
+    let mut duplicates = 0;
+    let mut found = false;
+    for step in std::iter::from_fn(|| search.step()).take(CANON_SEARCH_LIMIT) {
+        match step {
+            SearchStep::DuplicateSample { .. } => duplicates += 1,
+            SearchStep::IncorrectSample { .. }
+            | SearchStep::CostlySample { .. }
+            | SearchStep::UnknownSample { .. } => (),
+            SearchStep::CorrectSample { .. } => {
+                found = true;
+                break;
+            },
+        }
+    }
+
+    assert!(found);
+    assert!(duplicates > 0);
+}
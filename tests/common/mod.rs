@@ -0,0 +1,94 @@
+//! The fixtures shared by the integration tests. Not every test
+//! uses every one of them.
+#![allow(dead_code)]
+
+use std::rc::Rc;
+
+use bitsynth::expr::{val_from_z3, val_to_z3, AnswerExpr, Expr, ExprVal, OpSet, Signedness, Variable};
+use bitsynth::parse::parse_answer;
+use bitsynth::search::{BithackSearch, SearchStep};
+use bitsynth::synth::Synthesizer;
+use z3::ast::Ast;
+
+pub const TEST_WIDTH: u32 = 8;
+pub const TEST_SEARCH_LIMIT: usize = 1_000;
+
+/// The names `names` as owned strings
+pub fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|x| x.to_string()).collect()
+}
+
+/// The arguments `x` and `y`
+pub fn args() -> Vec<String> {
+    names(&["x", "y"])
+}
+
+/// Parse a signed answer over [args] at `width`
+pub fn parse_at(src: &str, width: u32) -> AnswerExpr {
+    parse_answer(src, &args(), width, Signedness::Signed).unwrap()
+}
+
+/// Parse a signed answer over [args] at [TEST_WIDTH]
+pub fn parse(src: &str) -> AnswerExpr {
+    parse_at(src, TEST_WIDTH)
+}
+
+/// A signed search at [TEST_WIDTH] for the results `results` over
+/// the arguments `arguments`, without a specification yet
+pub fn searcher<'ctx, S: Synthesizer<'ctx>>(
+    z3: &'ctx z3::Context,
+    should_learn: bool,
+    ops: &OpSet,
+    arguments: &[&str],
+    results: &[&str],
+    depth_limit: usize,
+) -> BithackSearch<'ctx, S> {
+    BithackSearch::with_results(
+        should_learn,
+        z3,
+        TEST_WIDTH,
+        Signedness::Signed,
+        ops,
+        names(arguments),
+        names(results),
+        depth_limit,
+    )
+}
+
+/// The first answer `search` finds within [TEST_SEARCH_LIMIT] steps
+pub fn find_answer<'ctx, S: Synthesizer<'ctx>>(search: &mut BithackSearch<'ctx, S>) -> Vec<AnswerExpr> {
+    std::iter::from_fn(|| search.step())
+        .take(TEST_SEARCH_LIMIT)
+        .find_map(|step| match step {
+            SearchStep::CorrectSample { answer, .. } => Some(answer),
+            _ => None,
+        })
+        .expect("No answer found")
+}
+
+pub fn arg(idx: usize) -> Rc<Expr> {
+    Rc::new(Expr::Variable(Variable::Argument(idx)))
+}
+
+pub fn constant(c: ExprVal) -> Rc<Expr> {
+    Rc::new(Expr::Variable(Variable::Const(c)))
+}
+
+pub fn unknown() -> Rc<Expr> {
+    Rc::new(Expr::Variable(Variable::UnknownConst))
+}
+
+/// The value z3 computes for `expr` on `args`
+pub fn z3_value(expr: &Expr, width: u32, args: &[ExprVal], signedness: Signedness) -> ExprVal {
+    let ctx = z3::Context::new(&z3::Config::default());
+    let bv = expr.to_z3(
+        &ctx,
+        width,
+        |_, _| unreachable!("No unknown constants are generated"),
+        |ctx, idx| val_to_z3(ctx, args[idx], width),
+    );
+
+    let val = val_from_z3(&bv.simplify(), signedness);
+
+    val.unwrap()
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::rc::Rc;
+
+use bitsynth::cost::{rank_by_cost, total_cost, CostModel, Profile};
+use bitsynth::expr::{BinopKind, Expr, ExprVal, OpSet, Signedness};
+use bitsynth::search::SearchStep;
+use bitsynth::synth::brute_enum::BruteEnum;
+use bitsynth::synth::circuit_enum::CircuitEnum;
+use bitsynth::synth::Synthesizer;
+use common::{arg, constant, parse, parse_at, unknown};
+
+pub const COST_SEARCH_LIMIT: usize = 5_000;
+pub const COST_CIRCUIT_STEPS: usize = 20;
+/// Wide enough for the immediates of every profile
+pub const COST_CONST_WIDTH: u32 = 64;
+
+/// Proposes the candidates it is given, in order
+struct Scripted(Vec<Vec<Expr>>);
+
+impl<'ctx> Synthesizer<'ctx> for Scripted {
+    fn build(_: &'ctx z3::Context, _: u32, _: Signedness, _: &OpSet, _: usize, _: usize, _: usize) -> Self {
+        Scripted(Vec::new())
+    }
+
+    fn bad_cand(&mut self, _cand: &[Expr], _args: Vec<ExprVal>, _expected: Vec<ExprVal>) {}
+
+    fn next_expr(&mut self) -> Option<Vec<Expr>> {
+        (!self.0.is_empty()).then(|| self.0.remove(0))
+    }
+}
+
+#[test]
+fn test_cost_profiles() {
+    let expr = parse("(((x * y) / 3) + popcnt(x))");
+
+    assert_eq!(expr.cost(&Profile::Nodes), 4);
+    assert_eq!(expr.cost(&Profile::X86Latency), 3 + 26 + 1 + 3);
+    assert_eq!(expr.cost(&Profile::Aarch64), 2 + 12 + 1 + 8);
+    assert_eq!(expr.cost(&Profile::Riscv), 3 + 20 + 1 + 12);
+
+    let ite = parse("((x == y) ? x : y)");
+    assert_eq!(ite.cost(&Profile::Nodes), 1);
+    assert_eq!(ite.cost(&Profile::Riscv), 5);
+}
+
+#[test]
+fn test_cost_constants() {
+    assert_eq!(parse_at("(x + 2047)", COST_CONST_WIDTH).cost(&Profile::Riscv), 1);
+    assert_eq!(parse_at("(x + 2048)", COST_CONST_WIDTH).cost(&Profile::Riscv), 3);
+    assert_eq!(parse_at("(x & 4294967296)", COST_CONST_WIDTH).cost(&Profile::X86Latency), 2);
+    assert_eq!(parse_at("(x & 65537)", COST_CONST_WIDTH).cost(&Profile::Aarch64), 3);
+
+    // An unknown constant is a lower bound
+    let cand = Expr::Binop(
+        BinopKind::Plus,
+        arg(0),
+        unknown(),
+    );
+    assert_eq!(cand.cost(&Profile::Riscv), Profile::Riscv.binop(BinopKind::Plus));
+}
+
+#[test]
+fn test_cost_ranking() {
+    let mut answers = vec![
+        parse("(x * 3)"),
+        parse("((x << 1) + x)"),
+        parse("((x + x) + x)"),
+    ];
+
+    rank_by_cost(&Profile::X86Latency, &mut answers);
+    assert_eq!(answers, vec![parse("((x << 1) + x)"), parse("((x + x) + x)"), parse("(x * 3)")]);
+
+    rank_by_cost(&Profile::Nodes, &mut answers);
+    assert_eq!(answers, vec![parse("(x * 3)"), parse("((x << 1) + x)"), parse("((x + x) + x)")]);
+}
+
+#[test]
+fn test_search_prefers_cheaper() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    // Few operators, as the candidates with constants are
+    // checked, unless they can't be simplified
+    let ops = OpSet { unops: Vec::new(), binops: vec![BinopKind::Mul, BinopKind::Plus], cmps: Vec::new() };
+    let mut search = common::searcher::<BruteEnum>(&z3, false, &ops, &["x"], &["res"], 1);
+    search.parse_prompt("(assert (= res (bvmul x #x02)))").unwrap();
+    search.set_cost_model(Box::new(Profile::X86Latency));
+
+    let mut costs = Vec::new();
+    let mut costly = 0;
+    for step in std::iter::from_fn(|| search.step()).take(COST_SEARCH_LIMIT) {
+        match step {
+            SearchStep::CostlySample { .. } => costly += 1,
+            SearchStep::IncorrectSample { .. }
+            | SearchStep::DuplicateSample { .. }
+            | SearchStep::UnknownSample { .. } => (),
+            SearchStep::CorrectSample { answer, .. } => {
+                costs.push(total_cost(&Profile::X86Latency, &answer));
+            },
+        }
+    }
+
+    // `x * 2` comes first, then only the cheaper addition is accepted
+    assert_eq!(costs, vec![3, 1]);
+    assert!(costly > 0);
+}
+
+#[test]
+fn test_search_costs_simplified() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let sum = Expr::Binop(BinopKind::Plus, arg(0), Rc::new(Expr::Binop(BinopKind::Plus, arg(0), arg(0))));
+    // As costly as the sum, until the xor is simplified away
+    let mul = Expr::Binop(BinopKind::Mul, Rc::new(Expr::Binop(BinopKind::Xor, arg(0), constant(0))), constant(3));
+
+    let steps = |should_simplify| {
+        let mut search = common::searcher::<Scripted>(&z3, false, &OpSet::all(), &["x"], &["res"], 0);
+        search.parse_prompt("(assert (= res (bvmul x #x03)))").unwrap();
+        search.set_cost_model(Box::new(Profile::Nodes));
+        search.set_simplify(should_simplify);
+        search.synth_mut().0 = vec![vec![sum.clone()], vec![mul.clone()], vec![sum.clone()]];
+
+        std::iter::from_fn(|| search.step()).collect::<Vec<_>>()
+    };
+
+    let steps_simplified = steps(true);
+    assert!(matches!(steps_simplified[0], SearchStep::CorrectSample { .. }));
+    assert!(matches!(&steps_simplified[1], SearchStep::CorrectSample { answer, .. } if *answer == [parse("(x * 3)")]));
+    // An accepted candidate isn't accepted again
+    assert!(matches!(steps_simplified[2], SearchStep::DuplicateSample { .. }));
+
+    let steps = steps(false);
+    assert!(matches!(steps[1], SearchStep::CostlySample { .. }));
+    assert!(matches!(steps[2], SearchStep::DuplicateSample { .. }));
+}
+
+#[test]
+fn test_search_circuit_moves_on() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<CircuitEnum>(&z3, true, &OpSet::all(), &["x"], &["res"], 1);
+    search.parse_prompt("(assert (= res (bvxor x (bvashr x #x03))))").unwrap();
+    search.set_cost_model(Box::new(Profile::Nodes));
+
+    // Once a circuit is accepted or too costly, it isn't proposed again
+    let mut cands = Vec::new();
+    let mut found = false;
+    for step in std::iter::from_fn(|| search.step()).take(COST_CIRCUIT_STEPS) {
+        match step {
+            SearchStep::CorrectSample { cand, .. } => {
+                found = true;
+                cands.push(cand);
+            },
+            SearchStep::CostlySample { cand } => cands.push(cand),
+            _ => (),
+        }
+    }
+
+    assert!(found);
+    let count = cands.len();
+    cands.sort();
+    cands.dedup();
+    assert_eq!(cands.len(), count);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::rc::Rc;
+
+use bitsynth::dag::{AnswerDag, Node};
+use bitsynth::expr::{val_from_z3, val_to_z3, AnswerExpr, Expr, Signedness, Value};
+use common::parse_at;
+use z3::ast::Ast;
+
+pub const DAG_WIDTH: u32 = 16;
+
+fn parse(src: &str) -> AnswerExpr {
+    parse_at(src, DAG_WIDTH)
+}
+
+#[test]
+fn test_dag_roundtrip() {
+    for src in [
+        "x",
+        "(((x + 1) & y) ^ (x + 1))",
+        "(((x < y) ? popcnt(x) : (x rotl 3)) - popcnt(x))",
+        "((((x - y) <u 5) ? bswap(x) : clz(y)) * (x - y))",
+    ] {
+        let expr = parse(src);
+        let (dag, root) = AnswerDag::from_expr(&expr);
+
+        assert_eq!(dag.to_expr(root), expr, "{src}");
+    }
+}
+
+#[test]
+fn test_dag_sharing() {
+    let (mut dag, root) = AnswerDag::from_expr(&parse("(((x + 1) & y) ^ (x + 1))"));
+
+    // x, 1, y, (x + 1), (... & y) and the root
+    assert_eq!(dag.len(), 6);
+
+    // Adding an existing subterm gives back its node
+    let sum = dag.add_expr(&parse("(x + 1)"));
+    assert_eq!(dag.len(), 6);
+    assert!(matches!(dag.node(root), Node::Binop(_, _, r) if *r == sum));
+
+    // The rebuilt tree shares the subterm as well
+    let expr = dag.to_expr(root);
+    let Expr::Binop(_, l, r) = &expr else { panic!() };
+    let Expr::Binop(_, ll, _) = l.as_ref() else { panic!() };
+    assert!(Rc::ptr_eq(ll, r));
+}
+
+#[test]
+fn test_dag_to_z3() {
+    let ctx = z3::Context::new(&z3::Config::new());
+    let expr = parse("((((x - y) <u 5) ? bswap(x) : (y rotr (x - y))) * (x - y))");
+    let (dag, root) = AnswerDag::from_expr(&expr);
+
+    for (x, y) in [(0, 0), (7, 3), (-1, 200), (1000, -32768)] {
+        let arg = |name: &str| if name == "x" { x } else { y };
+        let encoded = dag.to_z3(&ctx, root, DAG_WIDTH, |ctx, v| match v {
+            Value::Arg(name) => val_to_z3(ctx, arg(name), DAG_WIDTH),
+            Value::Const(c) => val_to_z3(ctx, *c, DAG_WIDTH),
+        });
+
+        assert_eq!(
+            val_from_z3(&encoded.simplify(), Signedness::Signed),
+            Some(expr.compute_ans(DAG_WIDTH, Signedness::Signed, arg)),
+        );
+    }
+}
+
+#[test]
+fn test_dag_straight_line() {
+    let (dag, root) = AnswerDag::from_expr(&parse("(((x + 1) & y) ^ (x + 1))"));
+
+    assert_eq!(
+        dag.straight_line(root).to_string(),
+        "t0 = (x + 1)
+t1 = (t0 & y)
+t2 = (t1 ^ t0)
+t2",
+    );
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use std::rc::Rc;
+
+use bitsynth::expr::{BinopKind, Expr, OpSet, Value, Variable};
+use bitsynth::search::SearchStep;
+use bitsynth::synth::circuit_enum::{Circuit, CircuitComponent, CircuitEnum};
+use common::parse;
+
+pub const DOT_SEARCH_LIMIT: usize = 100;
+
+fn binop(kind: BinopKind, l: Variable, r: Variable) -> Expr {
+    Expr::Binop(kind, Rc::new(Expr::Variable(l)), Rc::new(Expr::Variable(r)))
+}
+
+#[test]
+fn test_dot_expr() {
+    assert_eq!(parse("((x < 0) ? -(x) : x)").to_dot(), "\
+digraph expr {
+    ordering=out;
+    n0 [label=\"x\", shape=box];
+    n1 [label=\"0\", shape=box];
+    n2 [label=\"<\"];
+    n2 -> n0;
+    n2 -> n1;
+    n3 [label=\"x\", shape=box];
+    n4 [label=\"-\"];
+    n4 -> n3;
+    n5 [label=\"x\", shape=box];
+    n6 [label=\"?:\", shape=diamond];
+    n6 -> n2 [label=\"if\"];
+    n6 -> n4 [label=\"then\"];
+    n6 -> n5 [label=\"else\"];
+}
+");
+
+    let cand = binop(BinopKind::Rotl, Variable::Argument(1), Variable::UnknownConst);
+    assert!(cand.to_dot().contains("n0 [label=\"arg1\", shape=box];\n    n1 [label=\"?\", shape=box];"));
+}
+
+#[test]
+fn test_dot_circuit() {
+    let circuit = Circuit {
+        args: vec![0],
+        components: vec![
+            CircuitComponent {
+                template: binop(BinopKind::Xor, Variable::Argument(0), Variable::Argument(1)),
+                output: 2,
+                inputs: vec![1, 0],
+                constants: vec![],
+            },
+            CircuitComponent {
+                template: binop(BinopKind::ShrA, Variable::Argument(0), Variable::UnknownConst),
+                output: 1,
+                inputs: vec![0],
+                constants: vec![7],
+            },
+            CircuitComponent {
+                template: binop(BinopKind::Minus, Variable::Argument(0), Variable::Argument(1)),
+                output: 3,
+                inputs: vec![0, 0],
+                constants: vec![],
+            },
+        ],
+        results: vec![2, 1],
+    };
+
+    let answer = circuit.to_exprs()
+        .iter()
+        .map(|x| x.to_ans(|v| match v {
+            Variable::Argument(_) => Value::Arg("x".to_string()),
+            Variable::Const(x) => Value::Const(x),
+            Variable::UnknownConst => unreachable!(),
+        }))
+        .collect::<Vec<_>>();
+    assert_eq!(answer, vec![parse("((x >> 7) ^ x)"), parse("(x >> 7)")]);
+    assert_eq!(circuit.to_dot(), "\
+digraph circuit {
+    rankdir=LR;
+    l0 [label=\"0: arg0\", shape=box];
+    l2 [label=\"2: (i0 ^ i1)\"];
+    l1 -> l2 [label=\"i0\"];
+    l0 -> l2 [label=\"i1\"];
+    l1 [label=\"1: (i0 >> 7)\"];
+    l0 -> l1 [label=\"i0\"];
+    l3 [label=\"3: (i0 - i1)\", style=dashed];
+    l0 -> l3 [label=\"i0\", style=dashed];
+    l0 -> l3 [label=\"i1\", style=dashed];
+    res0 [shape=doublecircle];
+    l2 -> res0;
+    res1 [shape=doublecircle];
+    l1 -> res1;
+}
+");
+}
+
+#[test]
+fn test_dot_search_circuits() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<CircuitEnum>(&z3, true, &OpSet::all(), &["x"], &["res"], 0);
+    search.parse_prompt("(assert (= res (bvsub (bvxor x (bvashr x #x07)) (bvashr x #x07))))").unwrap();
+
+    let mut steps = 0;
+    while let Some(step) = search.step() {
+        let (SearchStep::IncorrectSample { cand, .. } | SearchStep::CorrectSample { cand, .. }) = &step
+            else { continue };
+        let circuit = search.synth().last_circuit().unwrap();
+
+        assert_eq!(&circuit.to_exprs(), cand);
+        assert!(circuit.to_dot().starts_with("digraph circuit {\n"));
+
+        if let SearchStep::CorrectSample { .. } = step {
+            break;
+        }
+
+        steps += 1;
+        assert!(steps < DOT_SEARCH_LIMIT, "no answer found");
+    }
+}
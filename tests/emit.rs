@@ -0,0 +1,189 @@
+mod common;
+
+use bitsynth::emit::{
+    c::emit_c,
+    llvm::emit_llvm,
+    python::emit_python,
+    rust::emit_rust,
+    smt::{emit_smt, emit_sygus},
+};
+use bitsynth::expr::Signedness;
+use bitsynth::parse::parse_answer;
+use common::{args, parse_at};
+
+#[test]
+fn test_emit_c() {
+    let answer = parse_at("((x >> y) + !(x))", 13);
+
+    assert_eq!(
+        emit_c(&answer, "f", &args(), 13, Signedness::Signed),
+        "#include <stdint.h>
+
+int32_t f(int32_t a_x, int32_t a_y) {
+    const uint32_t u_x = ((uint32_t)a_x) & 0x1fffu;
+    const uint32_t u_y = ((uint32_t)a_y) & 0x1fffu;
+    const uint32_t t0 = u_y < 13 ? u_y : 12;
+    const uint32_t t1 = (((u_x ^ 0x1000u) >> t0) - (0x1000u >> t0)) & 0x1fffu;
+    const uint32_t t2 = (~u_x) & 0x1fffu;
+    const uint32_t t3 = (t1 + t2) & 0x1fffu;
+    return (int32_t)((t3 ^ 0x1000u) - 0x1000u);
+}
+",
+    );
+}
+
+#[test]
+fn test_emit_rust() {
+    let answer = parse_at("((x < y) ? (x / 3) : -(y))", 8);
+
+    assert_eq!(
+        emit_rust(&answer, "f", &args(), 8, Signedness::Signed),
+        "pub fn f(u_x: i8, u_y: i8) -> i8 {
+    let u_x = u_x as u8;
+    let u_y = u_y as u8;
+    let t0: u8 = if 0x3u8 == 0 { if u_x >> 7 != 0 { 1 } else { 0xffu8 } } else { (u_x as i8).wrapping_div(0x3u8 as i8) as u8 };
+    let t1: u8 = u_y.wrapping_neg();
+    let t2: u8 = if (u_x ^ 0x80u8) < (u_y ^ 0x80u8) { t0 } else { t1 };
+    t2 as i8
+}
+",
+    );
+}
+
+#[test]
+fn test_emit_python() {
+    let answer = parse_at("(popcnt(x) rotl (y - 1))", 32);
+
+    assert_eq!(
+        emit_python(&answer, "f", &args(), 32, Signedness::Unsigned),
+        "def f(u_x, u_y):
+    u_x &= 0xffffffff
+    u_y &= 0xffffffff
+    t0 = bin(u_x).count(\"1\")
+    t1 = (u_y - 0x1) & 0xffffffff
+    t2 = ((t0 << (t1 % 32)) | (t0 >> (32 - t1 % 32))) & 0xffffffff
+    return t2
+",
+    );
+}
+
+#[test]
+fn test_emit_arg_names() {
+    // The arguments can't be confused with the temporaries or the keywords
+    let args = ["t0".to_string(), "type".to_string(), "lambda".to_string()];
+    let answer = parse_answer("((t0 + type) ^ lambda)", &args, 8, Signedness::Unsigned).unwrap();
+
+    assert_eq!(
+        emit_c(&answer, "f", &args, 8, Signedness::Unsigned),
+        "#include <stdint.h>
+
+uint8_t f(uint8_t a_t0, uint8_t a_type, uint8_t a_lambda) {
+    const uint32_t u_t0 = ((uint32_t)a_t0) & 0xffu;
+    const uint32_t u_type = ((uint32_t)a_type) & 0xffu;
+    const uint32_t u_lambda = ((uint32_t)a_lambda) & 0xffu;
+    const uint32_t t0 = (u_t0 + u_type) & 0xffu;
+    const uint32_t t1 = t0 ^ u_lambda;
+    return (uint8_t)t1;
+}
+",
+    );
+    assert_eq!(
+        emit_rust(&answer, "f", &args, 8, Signedness::Unsigned),
+        "pub fn f(u_t0: u8, u_type: u8, u_lambda: u8) -> u8 {
+    let t0: u8 = u_t0.wrapping_add(u_type);
+    let t1: u8 = t0 ^ u_lambda;
+    t1
+}
+",
+    );
+    assert_eq!(
+        emit_llvm(&answer, "f", &args, 8),
+        "define i8 @f(i8 %a.t0, i8 %a.type, i8 %a.lambda) {
+  %t0 = add i8 %a.t0, %a.type
+  %t1 = xor i8 %t0, %a.lambda
+  ret i8 %t1
+}
+",
+    );
+    assert_eq!(
+        emit_python(&answer, "f", &args, 8, Signedness::Unsigned),
+        "def f(u_t0, u_type, u_lambda):
+    u_t0 &= 0xff
+    u_type &= 0xff
+    u_lambda &= 0xff
+    t0 = (u_t0 + u_type) & 0xff
+    t1 = t0 ^ u_lambda
+    return t1
+",
+    );
+}
+
+#[test]
+fn test_emit_llvm() {
+    let answer = parse_at("((x << y) ^ clz((x << y)))", 16);
+
+    assert_eq!(
+        emit_llvm(&answer, "f", &args(), 16),
+        "define i16 @f(i16 %a.x, i16 %a.y) {
+  %t0 = icmp ult i16 %a.y, 16
+  %t1 = shl i16 %a.x, %a.y
+  %t2 = select i1 %t0, i16 %t1, i16 0
+  %t3 = call i16 @llvm.ctlz.i16(i16 %t2, i1 false)
+  %t4 = xor i16 %t2, %t3
+  ret i16 %t4
+}
+
+declare i16 @llvm.ctlz.i16(i16, i1)
+",
+    );
+}
+
+#[test]
+fn test_emit_smt() {
+    let answer = parse_at("((x rotl y) + 5)", 8);
+
+    assert_eq!(
+        emit_smt(&answer, "f", &args(), 8),
+        "(define-fun f ((x (_ BitVec 8)) (y (_ BitVec 8))) (_ BitVec 8)
+  (bvadd (bvor (bvshl x (bvurem y #x08)) (bvlshr x (bvsub #x08 (bvurem y #x08))))
+         #x05))
+",
+    );
+    assert_eq!(
+        emit_sygus(&answer, "f", &args(), 8),
+        format!("(\n{})\n", emit_smt(&answer, "f", &args(), 8)),
+    );
+}
+
+/// Read the definitions back with z3 and check them on a few points
+#[test]
+fn test_emit_smt_roundtrip() {
+    const POINTS: [(i128, i128); 6] = [(0, 0), (1, -1), (-128, 7), (42, 3), (-5, 12), (100, -100)];
+
+    for (src, width) in [
+        ("((x rotl y) ^ (x rotr 3))", 8),
+        ("(clz(x) + (ctz(y) * popcnt((x & y))))", 13),
+        ("bswap((x - y))", 13),
+        ("((x / y) | (x % y))", 8),
+        ("((x /u y) - (x %u y))", 13),
+        ("(((x >>> y) < (x >> y)) ? (x << y) : !(y))", 8),
+    ] {
+        let answer = parse_at(src, width);
+        let lit = |x: i128| format!("(_ bv{} {width})", Signedness::Unsigned.truncate(x, width));
+        let mut smt = emit_smt(&answer, "f", &args(), width);
+
+        for (x, y) in POINTS {
+            let expected = answer.compute_ans(width, Signedness::Signed, |arg| match arg {
+                "x" => x,
+                _ => y,
+            });
+            smt += &format!("(assert (= (f {} {}) {}))\n", lit(x), lit(y), lit(expected));
+        }
+
+        let ctx = z3::Context::new(&z3::Config::new());
+        let solver = z3::Solver::new(&ctx);
+        solver.from_string(smt.as_str());
+
+        assert_eq!(solver.check(), z3::SatResult::Sat, "{src}:\n{smt}");
+    }
+}
@@ -0,0 +1,273 @@
+mod common;
+
+use std::rc::Rc;
+
+use bitsynth::expr::{
+    BinopKind, BoolExpr, CmpKind, Expr, ExprVal, Signedness, UnopKind, Value, Variable,
+};
+use bitsynth::simplify::Simplifier;
+use common::{arg, constant, z3_value};
+
+pub const EVAL_WIDTHS: [u32; 8] = [1, 3, 8, 13, 32, 64, 65, 128];
+pub const EVAL_EXPRS_PER_WIDTH: usize = 150;
+pub const EVAL_INPUTS_PER_EXPR: usize = 6;
+pub const EVAL_ARG_COUNT: usize = 2;
+pub const EVAL_DEPTH: usize = 4;
+
+/// A small linear congruential generator, so that the test
+/// is reproducible and needs no extra dependencies.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() >> 33) as usize % n
+    }
+
+    /// Random values biased towards the edge cases of `width`.
+    fn value(&mut self, width: u32) -> ExprVal {
+        let raw = match self.below(8) {
+            0 => 0,
+            1 => -1,
+            2 => 1 << (width - 1),
+            3 => width as ExprVal + self.below(3) as ExprVal - 1,
+            4 => self.below(8) as ExprVal,
+            _ => ((self.next_u64() as u128) << 64 | self.next_u64() as u128) as ExprVal,
+        };
+
+        Signedness::Signed.truncate(raw, width)
+    }
+
+    fn expr(&mut self, width: u32, depth: usize) -> Expr {
+        let leaf = depth == 0 || self.below(4) == 0;
+
+        if leaf {
+            return match self.below(3) {
+                0 => Expr::Variable(Variable::Const(self.value(width))),
+                _ => Expr::Variable(Variable::Argument(self.below(EVAL_ARG_COUNT))),
+            };
+        }
+
+        let sub = |rng: &mut Self| Rc::new(rng.expr(width, depth - 1));
+
+        match self.below(4) {
+            0 => {
+                let kind = UnopKind::ALL[self.below(UnopKind::ALL.len())];
+                Expr::Unop(kind, sub(self))
+            },
+            1 => {
+                let kind = CmpKind::ALL[self.below(CmpKind::ALL.len())];
+                let cond = BoolExpr::Cmp(kind, sub(self), sub(self));
+                Expr::Ite(cond, sub(self), sub(self))
+            },
+            _ => {
+                let kind = BinopKind::ALL[self.below(BinopKind::ALL.len())];
+                Expr::Binop(kind, sub(self), sub(self))
+            },
+        }
+    }
+}
+
+/// Checks that both the evaluator and z3 compute `expected`
+fn check(expr: Expr, width: u32, signedness: Signedness, args: &[ExprVal], expected: ExprVal) {
+    let computed = expr.compute(width, signedness, |v| match v {
+        Variable::Const(x) => x,
+        Variable::Argument(idx) => args[idx],
+        Variable::UnknownConst => unreachable!(),
+    });
+
+    assert_eq!(computed, expected, "{expr:?} on {args:?} at width {width}");
+    assert_eq!(z3_value(&expr, width, args, signedness), expected, "z3: {expr:?} on {args:?} at width {width}");
+}
+
+#[test]
+fn test_compute_matches_z3() {
+    let mut rng = Lcg(0x5eed);
+
+    for width in EVAL_WIDTHS {
+        for _ in 0..EVAL_EXPRS_PER_WIDTH {
+            let expr = rng.expr(width, EVAL_DEPTH);
+            let signedness = match rng.below(2) {
+                0 => Signedness::Signed,
+                _ => Signedness::Unsigned,
+            };
+
+            for _ in 0..EVAL_INPUTS_PER_EXPR {
+                let args: Vec<_> = (0..EVAL_ARG_COUNT)
+                    .map(|_| rng.value(width))
+                    .collect();
+                let names = ["x", "y"];
+                let answer = expr.to_ans(|v| match v {
+                    Variable::Const(x) => Value::Const(x),
+                    Variable::Argument(idx) => Value::Arg(names[idx].to_string()),
+                    Variable::UnknownConst => unreachable!(),
+                });
+
+                let expected = z3_value(&expr, width, &args, signedness);
+                let computed = expr.compute(width, signedness, |v| match v {
+                    Variable::Const(x) => x,
+                    Variable::Argument(idx) => args[idx],
+                    Variable::UnknownConst => unreachable!(),
+                });
+                let computed_ans = answer.compute_ans(width, signedness, |name| {
+                    args[names.iter().position(|x| *x == name).unwrap()]
+                });
+
+                assert_eq!(computed, expected, "{answer} on {args:?} at width {width}");
+                assert_eq!(computed_ans, expected, "{answer} on {args:?} at width {width}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_simplify_preserves_value() {
+    let mut rng = Lcg(0xc0ffee);
+
+    for width in EVAL_WIDTHS {
+        for _ in 0..EVAL_EXPRS_PER_WIDTH {
+            let expr = rng.expr(width, EVAL_DEPTH);
+            let simplified = Simplifier::new(width, Signedness::Signed).simplify(&expr);
+
+            for _ in 0..EVAL_INPUTS_PER_EXPR {
+                let args: Vec<_> = (0..EVAL_ARG_COUNT)
+                    .map(|_| rng.value(width))
+                    .collect();
+                let eval = |e: &Expr| e.compute(width, Signedness::Signed, |v| match v {
+                    Variable::Const(x) => x,
+                    Variable::Argument(idx) => args[idx],
+                    Variable::UnknownConst => unreachable!(),
+                });
+
+                assert_eq!(eval(&expr), eval(&simplified), "{expr:?} on {args:?} at width {width}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_compute_edge_cases() {
+    let x = || Rc::new(Expr::Variable(Variable::Argument(0)));
+    let c = |v| Rc::new(Expr::Variable(Variable::Const(v)));
+    let eval = |expr: Expr, arg, signedness| expr.compute(8, signedness, |v| match v {
+        Variable::Const(x) => x,
+        Variable::Argument(_) => arg,
+        Variable::UnknownConst => unreachable!(),
+    });
+
+    assert_eq!(eval(Expr::Unop(UnopKind::Not, x()), 5, Signedness::Signed), -6);
+    assert_eq!(eval(Expr::Unop(UnopKind::Negate, x()), -128, Signedness::Signed), -128);
+    assert_eq!(eval(Expr::Binop(BinopKind::Plus, x(), c(1)), 127, Signedness::Signed), -128);
+    assert_eq!(eval(Expr::Binop(BinopKind::Minus, x(), c(1)), 0, Signedness::Unsigned), 255);
+    assert_eq!(eval(Expr::Binop(BinopKind::Shl, x(), c(8)), 1, Signedness::Signed), 0);
+    assert_eq!(eval(Expr::Binop(BinopKind::ShrL, x(), c(200)), -1, Signedness::Signed), 0);
+    assert_eq!(eval(Expr::Binop(BinopKind::ShrA, x(), c(9)), -3, Signedness::Signed), -1);
+    assert_eq!(eval(Expr::Binop(BinopKind::ShrA, x(), c(9)), 3, Signedness::Signed), 0);
+}
+
+#[test]
+fn test_compute_unsigned() {
+    let shrl = |r| Expr::Binop(BinopKind::ShrL, arg(0), constant(r));
+    let ult = || Expr::Ite(BoolExpr::Cmp(CmpKind::Ult, arg(0), arg(1)), constant(1), constant(0));
+    let slt = || Expr::Ite(BoolExpr::Cmp(CmpKind::Slt, arg(0), arg(1)), constant(1), constant(0));
+
+    // Zeroes are shifted in, the result is read back per signedness
+    check(shrl(1), 8, Signedness::Signed, &[-2], 127);
+    check(shrl(1), 8, Signedness::Unsigned, &[254], 127);
+    check(shrl(7), 8, Signedness::Signed, &[-1], 1);
+    check(shrl(0), 8, Signedness::Signed, &[-128], -128);
+    check(shrl(0), 8, Signedness::Unsigned, &[128], 128);
+    check(shrl(127), 128, Signedness::Unsigned, &[-1], 1);
+
+    // The negative numbers are the big unsigned ones
+    check(ult(), 8, Signedness::Signed, &[1, -1], 1);
+    check(slt(), 8, Signedness::Signed, &[1, -1], 0);
+    check(ult(), 8, Signedness::Unsigned, &[255, 1], 0);
+    check(slt(), 8, Signedness::Unsigned, &[255, 1], 1);
+}
+
+#[test]
+fn test_compute_mul_div_rem() {
+    let op = |kind| Expr::Binop(kind, arg(0), arg(1));
+
+    check(op(BinopKind::Mul), 8, Signedness::Signed, &[16, 16], 0);
+    check(op(BinopKind::Mul), 8, Signedness::Signed, &[-3, 5], -15);
+    check(op(BinopKind::Mul), 8, Signedness::Unsigned, &[253, 5], 241);
+    check(op(BinopKind::SDiv), 8, Signedness::Signed, &[-7, 2], -3);
+    check(op(BinopKind::SDiv), 8, Signedness::Signed, &[-128, -1], -128);
+    check(op(BinopKind::UDiv), 8, Signedness::Signed, &[-1, 16], 15);
+    check(op(BinopKind::SRem), 8, Signedness::Signed, &[-7, 2], -1);
+    check(op(BinopKind::SRem), 8, Signedness::Signed, &[7, -2], 1);
+    check(op(BinopKind::URem), 8, Signedness::Unsigned, &[255, 16], 15);
+
+    // Dividing by zero gives what z3 gives
+    check(op(BinopKind::SDiv), 8, Signedness::Signed, &[5, 0], -1);
+    check(op(BinopKind::SDiv), 8, Signedness::Signed, &[-5, 0], 1);
+    check(op(BinopKind::UDiv), 8, Signedness::Unsigned, &[5, 0], 255);
+    check(op(BinopKind::SRem), 8, Signedness::Signed, &[-5, 0], -5);
+    check(op(BinopKind::URem), 8, Signedness::Unsigned, &[200, 0], 200);
+    check(op(BinopKind::UDiv), 128, Signedness::Signed, &[7, 0], -1);
+}
+
+#[test]
+fn test_compute_rotate() {
+    let rotl = || Expr::Binop(BinopKind::Rotl, arg(0), arg(1));
+    let rotr = || Expr::Binop(BinopKind::Rotr, arg(0), arg(1));
+
+    check(rotl(), 8, Signedness::Signed, &[-127, 1], 3);
+    check(rotr(), 8, Signedness::Signed, &[1, 1], -128);
+    check(rotr(), 8, Signedness::Unsigned, &[1, 1], 128);
+    check(rotl(), 13, Signedness::Signed, &[1, 12], -4096);
+    check(rotl(), 128, Signedness::Signed, &[-2, 1], -3);
+
+    // The amount is taken modulo the width
+    check(rotl(), 8, Signedness::Signed, &[-127, 9], 3);
+    check(rotr(), 8, Signedness::Unsigned, &[1, 201], 128);
+    check(rotl(), 13, Signedness::Unsigned, &[1, 13], 1);
+}
+
+#[test]
+fn test_compute_ite() {
+    let ite = |kind| Expr::Ite(BoolExpr::Cmp(kind, arg(0), arg(1)), arg(0), arg(1));
+    // `x == 0 ? 1 : (x < 0 ? -1 : 1)` is the sign of non-zero `x`
+    let sign = Expr::Ite(
+        BoolExpr::Cmp(CmpKind::Eq, arg(0), constant(0)),
+        constant(1),
+        Rc::new(Expr::Ite(BoolExpr::Cmp(CmpKind::Slt, arg(0), constant(0)), constant(-1), constant(1))),
+    );
+
+    check(ite(CmpKind::Eq), 8, Signedness::Signed, &[3, 3], 3);
+    check(ite(CmpKind::Eq), 8, Signedness::Signed, &[3, 4], 4);
+    check(ite(CmpKind::Slt), 8, Signedness::Signed, &[-1, 1], -1);
+    check(ite(CmpKind::Ult), 8, Signedness::Signed, &[-1, 1], 1);
+    check(ite(CmpKind::Ult), 8, Signedness::Unsigned, &[255, 1], 1);
+    check(ite(CmpKind::Slt), 1, Signedness::Signed, &[-1, 0], -1);
+    check(sign.clone(), 8, Signedness::Signed, &[0], 1);
+    check(sign.clone(), 8, Signedness::Signed, &[-128], -1);
+    check(sign, 8, Signedness::Unsigned, &[200], 255);
+}
+
+#[test]
+fn test_compute_intrinsics() {
+    let op = |kind| Expr::Unop(kind, arg(0));
+
+    check(op(UnopKind::Popcnt), 8, Signedness::Signed, &[-1], 8);
+    check(op(UnopKind::Popcnt), 65, Signedness::Unsigned, &[1 << 64 | 5], 3);
+    check(op(UnopKind::Clz), 8, Signedness::Signed, &[1], 7);
+    check(op(UnopKind::Clz), 8, Signedness::Signed, &[0], 8);
+    check(op(UnopKind::Clz), 8, Signedness::Signed, &[-1], 0);
+    check(op(UnopKind::Ctz), 8, Signedness::Signed, &[-128], 7);
+    check(op(UnopKind::Ctz), 128, Signedness::Signed, &[0], 128);
+    check(op(UnopKind::Bswap), 8, Signedness::Signed, &[-2], -2);
+    check(op(UnopKind::Bswap), 16, Signedness::Unsigned, &[0x1234], 0x3412);
+
+    // Zero-extended to 16 bits, swapped and truncated back
+    check(op(UnopKind::Bswap), 13, Signedness::Unsigned, &[0x1234], 0x1412);
+}
@@ -2,17 +2,20 @@ use std::collections::HashSet;
 
 use bitsynth::synth::brute_enum::BruteEnum;
 use bitsynth::synth::simple_search::SimpleSearch;
-use bitsynth::{conv::Z3ToExpr, expr::BITS_PER_VAL, search::BithackSearch, synth::Synthesizer};
+use bitsynth::{conv::Z3ToExpr, expr::{OpSet, Signedness}, search::BithackSearch, synth::Synthesizer};
 use log::warn;
 use z3::ast::Ast;
 
 pub const EASY_DEPTH_LIMIT: usize = 5;
 pub const EASY_SEARCH_LIMIT: usize = 1_00;
 pub const EASY_Z3_TIMEOUT: u64 = 200;
+pub const EASY_WIDTH: u32 = 32;
+
+pub type FuneqBuilder = dyn for<'ctx> Fn(&'ctx z3::Context, &Z3ToExpr<'ctx>) -> z3::ast::BV<'ctx>;
 
 pub struct FuneqChallenge {
     args: Vec<String>,
-    builder: Box<dyn for<'ctx> Fn(&'ctx z3::Context, &Z3ToExpr<'ctx>) -> z3::ast::BV<'ctx>>,
+    builder: Box<FuneqBuilder>,
 }
 
 impl FuneqChallenge {
@@ -24,7 +27,7 @@ impl FuneqChallenge {
         let solver = z3::Solver::new(z3);
         let tester = move |l: &z3::ast::BV, r: &z3::ast::BV| {
             solver.push();
-            solver.assert(&!l._eq(&r));
+            solver.assert(&!l._eq(r));
             let veridct = solver.check();
             solver.pop(1);
 
@@ -51,14 +54,16 @@ impl FuneqChallenge {
         let mut search = BithackSearch::<S>::new(
             should_learn,
             z3,
+            EASY_WIDTH,
+            Signedness::Signed,
+            &OpSet::all(),
             self.args.clone(),
             EASY_DEPTH_LIMIT,
         );
         let fun = (self.builder)(z3, search.converter());
         let mut memory = HashSet::new();
 
-        let res_var = search.oracle().result_var().clone();
-        search.oracle().add_constraint(res_var._eq(&fun));
+        search.oracle().set_reference(vec![fun.clone()]);
 
         let mut found = false;
         let mut step_cnt = 0;
@@ -69,15 +74,18 @@ impl FuneqChallenge {
             }
             match step {
                 // TODO: fact check the synthesizer there?
-                bitsynth::search::SearchStep::IncorrectSample { cand, .. } => {
+                bitsynth::search::SearchStep::IncorrectSample { cand, .. } |
+                bitsynth::search::SearchStep::UnknownSample { cand, .. } => {
                     let is_new = memory.insert(cand);
                     assert!(is_new);
                 },
+                bitsynth::search::SearchStep::DuplicateSample { .. } |
+                bitsynth::search::SearchStep::CostlySample { .. } => (),
                 bitsynth::search::SearchStep::CorrectSample {
                     answer,
                     cand,
                 } => {
-                    let res = search.converter().ans_expr_to_z3(&answer);
+                    let res = search.converter().ans_expr_to_z3(&answer[0]);
                     assert!(tester(&fun, &res));
                     found = true;
                     let is_new = memory.insert(cand);
@@ -129,7 +137,7 @@ pub fn simple_funeq_challenges() -> Vec<FuneqChallenge> {
         FuneqChallenge {
             args: vec!["x".to_string()],
             builder: Box::new(|z3, _conv| {
-                z3::ast::BV::from_i64(z3, 123, BITS_PER_VAL)
+                z3::ast::BV::from_i64(z3, 123, EASY_WIDTH)
             }),
         },
     ]
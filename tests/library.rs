@@ -0,0 +1,71 @@
+mod common;
+
+use bitsynth::expr::{BinopKind, CmpKind, OpSet, UnopKind};
+use bitsynth::search::BithackSearch;
+use bitsynth::synth::circuit_enum::CircuitEnum;
+use common::{find_answer, parse, TEST_WIDTH};
+
+/// A circuit search with the optional components of `extra`
+fn searcher<'ctx>(z3: &'ctx z3::Context, prompt: &str, extra: &OpSet) -> BithackSearch<'ctx, CircuitEnum<'ctx>> {
+    let mut search = common::searcher::<CircuitEnum>(z3, true, &OpSet::all(), &["x"], &["res"], 1);
+    search.synth_mut().add_components(extra);
+    search.parse_prompt(prompt).unwrap();
+
+    search
+}
+
+fn binops(binops: &[BinopKind]) -> OpSet {
+    OpSet { unops: Vec::new(), binops: binops.to_vec(), cmps: Vec::new() }
+}
+
+#[test]
+fn test_library_rotate() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher(
+        &z3,
+        "(assert (= res ((_ rotate_left 3) x)))",
+        &binops(&[BinopKind::Rotl, BinopKind::Rotr]),
+    );
+
+    let answer = find_answer(&mut search);
+    assert!(search.check_answer(&answer));
+    assert!(search.check_answer(&[parse("(x rotl 3)")]));
+    assert!(search.check_answer(&[parse("(x rotr 5)")]));
+}
+
+#[test]
+fn test_library_mul() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher(&z3, "(assert (= res (bvmul x #x05)))", &binops(&[BinopKind::Mul]));
+
+    assert_eq!(find_answer(&mut search), [parse("(x * 5)")]);
+}
+
+#[test]
+fn test_library_select() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let selects = OpSet { unops: Vec::new(), binops: Vec::new(), cmps: vec![CmpKind::Slt, CmpKind::Ult] };
+    let mut search = searcher(&z3, "(assert (= res (ite (bvslt x #x00) (bvneg x) x)))", &selects);
+
+    // The absolute value comes out of the select component
+    let answer = find_answer(&mut search);
+    let straight = OpSet { cmps: Vec::new(), ..OpSet::all() };
+    assert!(search.check_answer(&answer));
+    assert!(!straight.allows(&answer[0]), "{}", answer[0]);
+}
+
+#[test]
+fn test_library_intrinsics() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let bits = (0..TEST_WIDTH)
+        .map(|i| format!("((_ zero_extend 7) ((_ extract {i} {i}) x))"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let prompt = format!("(assert (= res (bvxor x (bvadd {bits}))))");
+    let popcnt = OpSet { unops: vec![UnopKind::Popcnt], binops: Vec::new(), cmps: Vec::new() };
+
+    let mut search = searcher(&z3, &prompt, &popcnt);
+    let answer = find_answer(&mut search);
+    assert!(search.check_answer(&answer));
+    assert!(search.check_answer(&[parse("(x ^ popcnt(x))")]));
+}
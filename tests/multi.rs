@@ -0,0 +1,68 @@
+mod common;
+
+use bitsynth::expr::{Expr, OpSet, Variable};
+use bitsynth::synth::brute_enum::BruteEnum;
+use bitsynth::synth::circuit_enum::CircuitEnum;
+use bitsynth::synth::Tuples;
+use common::{find_answer, parse};
+
+#[test]
+fn test_tuples() {
+    let exprs = (0..3).map(|x| Expr::Variable(Variable::Const(x)));
+    let consts = |tuple: Vec<Expr>| tuple.iter()
+        .map(|x| match x {
+            Expr::Variable(Variable::Const(x)) => *x,
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(Tuples::new(exprs.clone(), 1).map(consts).collect::<Vec<_>>(), [[0], [1], [2]]);
+    assert_eq!(
+        Tuples::new(exprs.clone(), 2).map(consts).collect::<Vec<_>>(),
+        [[0, 0], [1, 0], [1, 1], [0, 1], [2, 0], [2, 1], [2, 2], [0, 2], [1, 2]],
+    );
+
+    // Every tuple comes up exactly once
+    let mut triples = Tuples::new(exprs, 3).map(consts).collect::<Vec<_>>();
+    assert_eq!(triples.len(), 27);
+    triples.sort();
+    triples.dedup();
+    assert_eq!(triples.len(), 27);
+}
+
+#[test]
+fn test_search_argument_order() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<BruteEnum>(&z3, false, &OpSet::portable(), &["x", "y"], &["res"], 1);
+    search.parse_prompt("(assert (= res (bvsub x y)))").unwrap();
+
+    assert_eq!(find_answer(&mut search), [parse("(x - y)")]);
+}
+
+#[test]
+fn test_multi_brute() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<BruteEnum>(&z3, false, &OpSet::portable(), &["x", "y"], &["d", "n"], 1);
+    search.parse_prompt("(assert (= d (bvneg x))) (assert (= n (bvnot x)))").unwrap();
+
+    assert_eq!(find_answer(&mut search), [parse("-(x)"), parse("!(x)")]);
+    assert!(search.check_answer(&[parse("(0 - x)"), parse("(-1 - x)")]));
+    assert!(!search.check_answer(&[parse("!(x)"), parse("-(x)")]));
+}
+
+#[test]
+fn test_multi_circuit() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<CircuitEnum>(&z3, true, &OpSet::all(), &["x"], &["q", "r"], 0);
+    search.parse_prompt("(assert (= q (bvashr x #x03))) (assert (= r (bvxor x (bvashr x #x03))))").unwrap();
+
+    let answer = find_answer(&mut search);
+    assert_eq!(answer, [parse("(x >> 3)"), parse("(x ^ (x >> 3))")]);
+
+    // Both results read the same shift
+    let circuit = search.synth().last_circuit().unwrap();
+    let shift = circuit.components.iter()
+        .find(|x| x.output == circuit.results[0])
+        .unwrap();
+    assert!(circuit.components.iter().any(|x| x.inputs.contains(&shift.output)));
+}
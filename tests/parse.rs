@@ -0,0 +1,105 @@
+mod common;
+
+use std::rc::Rc;
+
+use bitsynth::expr::{
+    AnswerExpr, BinopKind, BoolExpr, CmpKind, Expr, Signedness, UnopKind, Value,
+};
+use bitsynth::parse::{parse_answer, ParseError, ParseErrorKind};
+use bitsynth::random::ExprGen;
+use common::args;
+
+pub const PARSE_WIDTH: u32 = 32;
+pub const PARSE_RANDOM_EXPRS: usize = 300;
+
+fn parse(src: &str) -> Result<AnswerExpr, ParseError> {
+    parse_answer(src, &args(), PARSE_WIDTH, Signedness::Signed)
+}
+
+fn arg(name: &str) -> Rc<AnswerExpr> {
+    Rc::new(Expr::Variable(Value::Arg(name.to_string())))
+}
+
+fn constant(c: i128) -> Rc<AnswerExpr> {
+    Rc::new(Expr::Variable(Value::Const(c)))
+}
+
+fn all_operators() -> Vec<AnswerExpr> {
+    let unops = UnopKind::ALL.into_iter()
+        .map(|kind| Expr::Unop(kind, arg("x")));
+    let binops = BinopKind::ALL.into_iter()
+        .map(|kind| Expr::Binop(kind, arg("x"), constant(-5)));
+    let ites = CmpKind::ALL.into_iter()
+        .map(|kind| Expr::Ite(
+            BoolExpr::Cmp(kind, arg("x"), arg("y")),
+            Rc::new(Expr::Unop(UnopKind::Negate, arg("y"))),
+            constant(7),
+        ));
+
+    unops.chain(binops).chain(ites).collect()
+}
+
+#[test]
+fn test_parse_display_roundtrip() {
+    for expr in all_operators() {
+        let printed = expr.to_string();
+
+        assert_eq!(parse(&printed), Ok(expr), "{printed}");
+    }
+
+    let nested = Expr::Binop(
+        BinopKind::Minus,
+        Rc::new(Expr::Binop(BinopKind::Xor, arg("x"), constant(5))),
+        Rc::new(Expr::Unop(UnopKind::Not, arg("x"))),
+    );
+    assert_eq!(nested.to_string(), "((x ^ 5) - !(x))");
+    assert_eq!(parse("((x ^ 5) - !(x))"), Ok(nested));
+}
+
+#[test]
+fn test_parse_random_roundtrip() {
+    let mut gen = ExprGen::new(0xbeef, PARSE_WIDTH, Signedness::Signed, args().len());
+
+    for _ in 0..PARSE_RANDOM_EXPRS {
+        let expr = gen.answer(&args());
+        let printed = expr.to_string();
+
+        assert_eq!(parse(&printed), Ok(expr), "{printed}");
+    }
+}
+
+#[test]
+fn test_parse_constants() {
+    assert_eq!(parse("0x1f"), Ok(Expr::Variable(Value::Const(31))));
+    assert_eq!(parse("-0x10"), Ok(Expr::Variable(Value::Const(-16))));
+    assert_eq!(parse("0xffffffff"), Ok(Expr::Variable(Value::Const(-1))));
+    assert_eq!(
+        parse_answer("-1", &args(), PARSE_WIDTH, Signedness::Unsigned),
+        Ok(Expr::Variable(Value::Const(0xffffffff))),
+    );
+    assert_eq!(
+        parse("0x100000000").map_err(|e| e.kind),
+        Err(ParseErrorKind::ConstantOutOfRange("0x100000000".to_string())),
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(
+        parse("(x + z)"),
+        Err(ParseError { pos: 5, kind: ParseErrorKind::UnknownArgument("z".to_string()) }),
+    );
+    assert_eq!(
+        parse("(x + y"),
+        Err(ParseError { pos: 6, kind: ParseErrorKind::UnexpectedEnd }),
+    );
+    assert_eq!(
+        parse("(x < y)"),
+        Err(ParseError { pos: 0, kind: ParseErrorKind::ExpectedValue }),
+    );
+    assert_eq!(
+        parse("(x ? y : 1)"),
+        Err(ParseError { pos: 1, kind: ParseErrorKind::ExpectedCondition }),
+    );
+    assert_eq!(parse("(x $ y)").map_err(|e| e.pos), Err(3));
+}
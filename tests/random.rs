@@ -0,0 +1,132 @@
+mod common;
+
+use bitsynth::cost::Profile;
+use bitsynth::expr::{Expr, OpSet, Signedness, Variable};
+use bitsynth::random::{spec, ConstDist, ExprGen, Weights};
+use bitsynth::synth::brute_enum::BruteEnum;
+use common::{args, z3_value, TEST_WIDTH};
+
+pub const RANDOM_EXPRS: usize = 200;
+pub const RANDOM_SPECS: usize = 20;
+pub const TEST_WIDTHS: [u32; 4] = [1, 8, 65, 128];
+pub const RANDOM_INPUTS_PER_EXPR: usize = 4;
+
+fn depth(expr: &Expr) -> usize {
+    expr.walk_expr(
+        &mut |_| 0,
+        &mut |_, e: usize| e + 1,
+        &mut |_, l, r| l.max(r) + 1,
+        &mut |_, l, r| l.max(r),
+        &mut |c, t, e| c.max(t).max(e) + 1,
+        &mut |x| x,
+    )
+}
+
+fn constants(expr: &Expr) -> Vec<i128> {
+    let mut res = Vec::new();
+    expr.walk_expr(
+        &mut |v| if let Variable::Const(x) = v { res.push(*x) },
+        &mut |_, _| (),
+        &mut |_, _, _| (),
+        &mut |_, _, _| (),
+        &mut |_, _, _| (),
+        &mut |x| x,
+    );
+
+    res
+}
+
+#[test]
+fn test_random_reproducible() {
+    let exprs = |seed| {
+        let mut gen = ExprGen::new(seed, TEST_WIDTH, Signedness::Signed, 2);
+        (0..RANDOM_EXPRS).map(|_| gen.expr()).collect::<Vec<_>>()
+    };
+
+    assert_eq!(exprs(1), exprs(1));
+    assert_ne!(exprs(1), exprs(2));
+}
+
+#[test]
+fn test_random_shape() {
+    let mut ops = OpSet::portable();
+    ops.cmps.clear();
+    let mut gen = ExprGen::new(7, TEST_WIDTH, Signedness::Signed, 2);
+    gen.set_ops(&ops);
+    gen.set_max_depth(3);
+
+    for _ in 0..RANDOM_EXPRS {
+        let expr = gen.expr();
+
+        assert!(ops.allows(&expr), "{expr:?}");
+        assert!(depth(&expr) <= 3, "{expr:?}");
+    }
+
+    gen.set_max_depth(0);
+    gen.set_weights(Weights { constant: 0, ..Weights::default() });
+    for _ in 0..RANDOM_EXPRS {
+        assert!(matches!(gen.expr(), Expr::Variable(Variable::Argument(0 | 1))));
+    }
+}
+
+#[test]
+fn test_random_size() {
+    let mut gen = ExprGen::new(42, TEST_WIDTH, Signedness::Signed, 2);
+
+    for size in (0..50).chain([1_000]) {
+        let expr = gen.expr_of_size(size);
+
+        assert_eq!(expr.cost(&Profile::Nodes), size as u32, "{expr:?}");
+    }
+}
+
+#[test]
+fn test_random_consts() {
+    let mut gen = ExprGen::new(3, TEST_WIDTH, Signedness::Unsigned, 1);
+    for _ in 0..RANDOM_EXPRS {
+        assert!(constants(&gen.expr()).iter().all(|x| (0..256).contains(x)));
+    }
+
+    gen.set_consts(ConstDist::Range(-3, 3));
+    let mut values = (0..RANDOM_EXPRS).map(|_| gen.value()).collect::<Vec<_>>();
+    values.sort();
+    values.dedup();
+    assert_eq!(values, [0, 1, 2, 3, 253, 254, 255]);
+}
+
+#[test]
+fn test_random_spec() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut gen = ExprGen::new(99, TEST_WIDTH, Signedness::Signed, 2);
+
+    for _ in 0..RANDOM_SPECS {
+        let answer = gen.answer(&args());
+        let mut search = common::searcher::<BruteEnum>(&z3, false, &OpSet::all(), &["x", "y"], &["res"], 0);
+        search.parse_prompt(&spec(&answer, "res", TEST_WIDTH)).unwrap();
+
+        assert!(search.check_answer(std::slice::from_ref(&answer)), "{answer}");
+    }
+}
+
+#[test]
+fn test_random_compute() {
+    // The generated expressions and arguments hit the edge cases of the evaluator
+    for width in TEST_WIDTHS {
+        let mut gen = ExprGen::new(0x5eed, width, Signedness::Signed, 2);
+
+        for _ in 0..RANDOM_EXPRS {
+            let expr = gen.expr();
+
+            for _ in 0..RANDOM_INPUTS_PER_EXPR {
+                let args = gen.args();
+                let computed = expr.compute(width, Signedness::Signed, |v| match v {
+                    Variable::Const(x) => x,
+                    Variable::Argument(idx) => args[idx],
+                    Variable::UnknownConst => unreachable!(),
+                });
+
+                assert_eq!(computed, z3_value(&expr, width, &args, Signedness::Signed), "{expr:?} on {args:?} at width {width}");
+            }
+        }
+    }
+}
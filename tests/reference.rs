@@ -0,0 +1,130 @@
+mod common;
+
+use bitsynth::expr::{OpSet, Signedness};
+use bitsynth::oracle::{Outcome, SpecErrorKind};
+use bitsynth::search::{BithackSearch, Reference};
+use bitsynth::synth::brute_enum::BruteEnum;
+use bitsynth::synth::circuit_enum::CircuitEnum;
+use bitsynth::synth::simple_search::SimpleSearch;
+use bitsynth::synth::Synthesizer;
+use common::{args, find_answer, parse, TEST_WIDTH};
+
+fn searcher<'ctx, S: Synthesizer<'ctx>>(z3: &'ctx z3::Context, results: &[&str]) -> BithackSearch<'ctx, S> {
+    common::searcher(z3, true, &OpSet::all(), &["x", "y"], results, 1)
+}
+
+#[test]
+fn test_reference_smt() {
+    assert_eq!(Reference::smt("(bvneg x)"), Reference::Term("(bvneg x)".to_string()));
+    assert!(matches!(Reference::smt("  (define-fun f () (_ BitVec 8) x)"), Reference::DefineFun(_)));
+}
+
+#[test]
+fn test_reference_term() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher::<BruteEnum>(&z3, &["res"]);
+    search.parse_reference(&[Reference::Term("(bvsub x y)".to_string())]).unwrap();
+
+    assert_eq!(find_answer(&mut search), [parse("(x - y)")]);
+    assert!(search.check_answer(&[parse("(x + -(y))")]));
+    assert!(!search.check_answer(&[parse("(y - x)")]));
+}
+
+#[test]
+fn test_reference_define_fun() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher::<BruteEnum>(&z3, &["res"]);
+    // The parameters are named differently and
+    // applied to the arguments in order
+    search.parse_reference(&[Reference::DefineFun("\
+(define-fun sub ((b (_ BitVec 8)) (a (_ BitVec 8))) (_ BitVec 8)
+  (bvsub b a))".to_string())]).unwrap();
+
+    assert_eq!(find_answer(&mut search), [parse("(x - y)")]);
+}
+
+#[test]
+fn test_reference_answer() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher::<CircuitEnum>(&z3, &["q", "r"]);
+    search.parse_reference(&[
+        Reference::Answer(parse("(x >> 3)")),
+        Reference::Term("(bvxor x (bvashr x #x03))".to_string()),
+    ]).unwrap();
+
+    let answer = find_answer(&mut search);
+    assert!(search.check_answer(&answer));
+    assert!(search.check_answer(&[parse("(x >> 3)"), parse("(x ^ (x >> 3))")]));
+}
+
+#[test]
+fn test_reference_values() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = BithackSearch::<SimpleSearch>::new(
+        false,
+        &z3,
+        TEST_WIDTH,
+        Signedness::Unsigned,
+        &OpSet::all(),
+        args(),
+        0,
+    );
+    let values = |search: &mut BithackSearch<SimpleSearch>, x, y| {
+        let z3_args = search.converter().z3_args().to_vec();
+
+        match search.oracle().suitable_value(&z3_args, [x, y]) {
+            Outcome::Found(values) => values,
+            _ => panic!("No value for ({x}, {y})"),
+        }
+    };
+
+    // The solver and the reference agree
+    search.parse_prompt("(assert (= res (bvudiv x y)))").unwrap();
+    assert!(search.oracle().reference().is_none());
+    assert_eq!(values(&mut search, 200, 7), [28]);
+    assert_eq!(values(&mut search, 200, 0), [255]);
+
+    search.parse_reference(&[Reference::Term("(bvudiv x y)".to_string())]).unwrap();
+    assert!(search.oracle().reference().is_some());
+    assert_eq!(values(&mut search, 200, 7), [28]);
+    assert_eq!(values(&mut search, 200, 0), [255]);
+}
+
+#[test]
+fn test_reference_errors() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher::<SimpleSearch>(&z3, &["res"]);
+
+    let err = search.parse_reference(&[Reference::Term("(bvadd x\n  (bvneg z))".to_string())]).unwrap_err();
+    assert_eq!(err.to_string(), "reference 1, line 2, column 9: unknown symbol \"z\"");
+
+    let err = search.parse_reference(&[Reference::DefineFun("(bvneg x)".to_string())]).unwrap_err();
+    assert!(matches!(err.kind, SpecErrorKind::Syntax(_)));
+
+    // The function takes too few parameters
+    let err = search.parse_reference(&[Reference::DefineFun(
+        "(define-fun f ((a (_ BitVec 8))) (_ BitVec 8) (bvneg a))".to_string(),
+    )]).unwrap_err();
+    assert!(matches!(err.kind, SpecErrorKind::SortMismatch(_)));
+
+    let err = search.parse_reference(&[Reference::Term("#x0001".to_string())]).unwrap_err();
+    assert!(matches!(err.kind, SpecErrorKind::SortMismatch(_)));
+}
+
+#[test]
+fn test_reference_error_location() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = searcher::<SimpleSearch>(&z3, &["q", "r"]);
+
+    let err = search.parse_reference(&[Reference::Term("(bvneg x)".to_string())]).unwrap_err();
+    assert_eq!(err.kind, SpecErrorKind::ReferenceCount { expected: 2, found: 1 });
+    assert_eq!(err.reference, None);
+
+    // The lines are counted from the start of the failing reference
+    let err = search.parse_reference(&[
+        Reference::Term("(bvneg\n  x)".to_string()),
+        Reference::Term("(bvadd x\n  (bvneg z))".to_string()),
+    ]).unwrap_err();
+    assert_eq!(err.reference, Some(1));
+    assert_eq!(err.pos, Some((2, 9)));
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use bitsynth::expr::{BinopKind, Expr, OpSet, Signedness};
+use bitsynth::search::SearchStep;
+use bitsynth::synth::brute_enum::BruteEnum;
+use common::{arg, parse, parse_at, unknown, TEST_SEARCH_LIMIT};
+
+/// Wide enough for the constants, which don't fit into 64 bits
+pub const SERDE_WIDE_WIDTH: u32 = 128;
+
+fn roundtrip<T: serde::Serialize + serde::de::DeserializeOwned>(val: &T) -> T {
+    serde_json::from_str(&serde_json::to_string(val).unwrap()).unwrap()
+}
+
+#[test]
+fn test_serde_schema() {
+    assert_eq!(
+        serde_json::to_string(&parse("(x + 1)")).unwrap(),
+        r#"{"binop":["plus",{"variable":{"arg":"x"}},{"variable":{"const":1}}]}"#,
+    );
+    assert_eq!(
+        serde_json::to_string(&parse("((x < y) ? popcnt(x) : (y >>> 2))")).unwrap(),
+        concat!(
+            r#"{"ite":[{"cmp":["slt",{"variable":{"arg":"x"}},{"variable":{"arg":"y"}}]},"#,
+            r#"{"unop":["popcnt",{"variable":{"arg":"x"}}]},"#,
+            r#"{"binop":["shrl",{"variable":{"arg":"y"}},{"variable":{"const":2}}]}]}"#,
+        ),
+    );
+
+    let cand = Expr::Binop(
+        BinopKind::SDiv,
+        arg(1),
+        unknown(),
+    );
+    assert_eq!(
+        serde_json::to_string(&cand).unwrap(),
+        r#"{"binop":["sdiv",{"variable":{"argument":1}},{"variable":"unknown_const"}]}"#,
+    );
+}
+
+#[test]
+fn test_serde_roundtrip() {
+    for src in [
+        "((x * y) %u -(x))",
+        "((x == 3) ? (x rotl y) : bswap(!(y)))",
+        "(x & -170141183460469231731687303715884105728)",
+    ] {
+        let expr = parse_at(src, SERDE_WIDE_WIDTH);
+        assert_eq!(roundtrip(&expr), expr, "{src}");
+    }
+
+    let ops = OpSet::portable();
+    assert_eq!(roundtrip(&ops), ops);
+    assert_eq!(roundtrip(&Signedness::Unsigned), Signedness::Unsigned);
+}
+
+#[test]
+fn test_serde_search_steps() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<BruteEnum>(&z3, false, &OpSet::portable(), &["x"], &["res"], 1);
+    search.parse_prompt("(assert (= res (bvneg x)))").unwrap();
+
+    let trace = std::iter::from_fn(|| search.step())
+        .take(TEST_SEARCH_LIMIT)
+        .map(|step| serde_json::to_string(&step).unwrap())
+        .collect::<Vec<_>>();
+
+    assert!(trace.iter().any(|x| x.starts_with(r#"{"step":"incorrect_sample","cand":[{"#)));
+
+    let answer = trace.iter()
+        .map(|x| serde_json::from_str::<SearchStep>(x).unwrap())
+        .find_map(|step| match step {
+            SearchStep::CorrectSample { answer, .. } => Some(answer),
+            _ => None,
+        });
+    assert_eq!(answer, Some(vec![parse("-(x)")]));
+}
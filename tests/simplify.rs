@@ -0,0 +1,78 @@
+mod common;
+
+use bitsynth::expr::{AnswerExpr, ExprVal, Signedness};
+use bitsynth::simplify::Simplifier;
+use common::{parse, TEST_WIDTH};
+
+/// Simplify `src`, compare the result with `expected` and make
+/// sure both agree on every pair of 8-bit inputs.
+fn assert_simplifies(src: &str, expected: &str) {
+    let expr = parse(src);
+    let simplified = Simplifier::new(TEST_WIDTH, Signedness::Signed).simplify(&expr);
+
+    assert_eq!(simplified, parse(expected), "{src} simplified to {simplified}");
+
+    for x in ExprVal::from(i8::MIN)..=ExprVal::from(i8::MAX) {
+        for y in ExprVal::from(i8::MIN)..=ExprVal::from(i8::MAX) {
+            let eval = |e: &AnswerExpr| e.compute_ans(
+                TEST_WIDTH,
+                Signedness::Signed,
+                |name| if name == "x" { x } else { y },
+            );
+
+            assert_eq!(eval(&expr), eval(&simplified), "{src} on x={x}, y={y}");
+        }
+    }
+}
+
+#[test]
+fn test_simplify_constants() {
+    assert_simplifies("((3 * 5) + x)", "(x + 15)");
+    assert_simplifies("(x ^ (1 << 2))", "(x ^ 4)");
+    assert_simplifies("popcnt(0xff)", "8");
+    assert_simplifies("((1 < 2) ? x : y)", "x");
+    assert_simplifies("((x == x) ? (y + 0) : x)", "y");
+    assert_simplifies("((x < y) ? y : y)", "y");
+}
+
+#[test]
+fn test_simplify_identities() {
+    assert_simplifies("(x ^ 0)", "x");
+    assert_simplifies("(x >> 0)", "x");
+    assert_simplifies("(x & -1)", "x");
+    assert_simplifies("(0 | x)", "x");
+    assert_simplifies("(x * 0)", "0");
+    assert_simplifies("(x - x)", "0");
+    assert_simplifies("(x ^ -1)", "!(x)");
+    assert_simplifies("(x & !(x))", "0");
+    assert_simplifies("(!(x) + 1)", "-(x)");
+    assert_simplifies("(0 - y)", "-(y)");
+    assert_simplifies("(x %u 1)", "0");
+    assert_simplifies("(x rotl 16)", "x");
+}
+
+#[test]
+fn test_simplify_double_negation() {
+    assert_simplifies("!(!(x))", "x");
+    assert_simplifies("-(-((x & y)))", "(x & y)");
+    assert_simplifies("!(-(x))", "!(-(x))");
+}
+
+#[test]
+fn test_simplify_offsets() {
+    assert_simplifies("((x - 1) + 1)", "x");
+    assert_simplifies("((x + 1) - 1)", "x");
+    assert_simplifies("((x + 3) + 5)", "(x + 8)");
+    assert_simplifies("((x - 3) - 5)", "(x + -8)");
+    assert_simplifies("((x + 127) + 1)", "(x + -128)");
+}
+
+#[test]
+fn test_simplify_shifts() {
+    assert_simplifies("((x << 2) << 3)", "(x << 5)");
+    assert_simplifies("((x >>> 4) >>> 4)", "0");
+    assert_simplifies("((x >> 5) >> 5)", "(x >> 7)");
+    assert_simplifies("(x >> 100)", "(x >> 7)");
+    assert_simplifies("((x rotl 5) rotl 6)", "(x rotl 3)");
+    assert_simplifies("((x << 2) >>> 2)", "((x << 2) >>> 2)");
+}
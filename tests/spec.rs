@@ -0,0 +1,82 @@
+mod common;
+
+use bitsynth::expr::OpSet;
+use bitsynth::oracle::{SpecError, SpecErrorKind};
+use bitsynth::synth::simple_search::SimpleSearch;
+
+fn parse(arguments: &[&str], prompt: &str) -> Result<(), SpecError> {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<SimpleSearch>(&z3, false, &OpSet::all(), arguments, &["res"], 0);
+
+    search.parse_prompt(prompt)
+}
+
+fn error_kind(prompt: &str) -> SpecErrorKind {
+    parse(&["x"], prompt).unwrap_err().kind
+}
+
+#[test]
+fn test_spec_ok() {
+    assert_eq!(parse(&["x"], "(assert (= res (bvneg x)))"), Ok(()));
+    assert_eq!(
+        parse(&["x", "y"], "(declare-const t (_ BitVec 8))\n(assert (= t (bvadd x y)))\n(assert (= res t))"),
+        Ok(()),
+    );
+}
+
+#[test]
+fn test_spec_errors() {
+    assert_eq!(error_kind("(assert (= res (bvneg z)))"), SpecErrorKind::UnknownSymbol("z".to_string()));
+    assert_eq!(error_kind("(assert (= res (foo x)))"), SpecErrorKind::UnknownSymbol("foo".to_string()));
+    assert_eq!(error_kind("(assert res)"), SpecErrorKind::NotBoolean);
+    assert!(matches!(error_kind("(assert (= res #x0003))"), SpecErrorKind::SortMismatch(_)));
+    assert!(matches!(error_kind("(assert (= res (bvadd x #x0001)))"), SpecErrorKind::SortMismatch(_)));
+    assert!(matches!(
+        error_kind("(define-fun f ((a (_ BitVec 8)) (b (_ BitVec 8))) (_ BitVec 8) a) (assert (= res (f x)))"),
+        SpecErrorKind::SortMismatch(_),
+    ));
+    assert!(matches!(error_kind("(assert (= res (bvneg x))"), SpecErrorKind::Syntax(_)));
+    assert!(matches!(error_kind("(assert (= res x)) garbage"), SpecErrorKind::Syntax(_)));
+    assert_eq!(error_kind(""), SpecErrorKind::Empty);
+    assert_eq!(error_kind("(declare-const t (_ BitVec 8))"), SpecErrorKind::Empty);
+}
+
+#[test]
+fn test_spec_error_position() {
+    let err = parse(&["x"], "(assert (= res x))\n(assert (= res (bvneg z)))").unwrap_err();
+    assert_eq!(err.pos.map(|(line, _)| line), Some(2));
+    assert_eq!(err.to_string(), "line 2, column 22: unknown symbol \"z\"");
+
+    // The declarations come before the prompt, so
+    // the errors in them have no position
+    let err = parse(&["x", "x"], "(assert (= res x))").unwrap_err();
+    assert_eq!(err.pos, None);
+    assert!(matches!(err.kind, SpecErrorKind::Syntax(_)));
+}
+
+#[test]
+fn test_spec_script_errors() {
+    // The bound variables are known
+    assert_eq!(
+        error_kind("(assert (let ((t (bvneg x))) (= res (bvadd t q))))"),
+        SpecErrorKind::UnknownSymbol("q".to_string()),
+    );
+    assert_eq!(error_kind("(assert (bvadd res x))"), SpecErrorKind::NotBoolean);
+    // The operators z3 knows are checked with z3
+    assert!(matches!(error_kind("(assert (= res (bvadd (bvredor x) #x0001)))"), SpecErrorKind::SortMismatch(_)));
+    assert!(matches!(error_kind("(assert (= res (ext_rotate_left x (bv2nat x))))"), SpecErrorKind::SortMismatch(_)));
+    assert_eq!(
+        error_kind("(assert (= res (bvadd (bvredand x) (bar x))))"),
+        SpecErrorKind::UnknownSymbol("bar".to_string()),
+    );
+    assert!(matches!(error_kind("(assert (= res x))\0"), SpecErrorKind::Syntax(_)));
+
+    let err = parse(&["x"], "(assert (= res x))\n  )").unwrap_err();
+    assert_eq!(err.to_string(), "line 2, column 2: unexpected \")\"");
+
+    // A malformed script without assertions isn't taken for an empty one
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<SimpleSearch>(&z3, false, &OpSet::all(), &["x"], &["res"], 0);
+    let err = search.parse_assumptions("(declare-const t Bool)\n(declare-const t Bool)").unwrap_err();
+    assert_eq!(err.to_string(), "line 2, column 0: invalid declare-const");
+}
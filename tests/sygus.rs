@@ -0,0 +1,175 @@
+mod common;
+
+use bitsynth::emit::smt::emit_sygus;
+use bitsynth::expr::{AnswerExpr, BinopKind, CmpKind, OpSet, Signedness, UnopKind};
+use bitsynth::parse::parse_answer;
+use bitsynth::search::BithackSearch;
+use bitsynth::sygus::{parse_sygus, SygusErrorKind, SygusProblem};
+use bitsynth::synth::brute_enum::BruteEnum;
+use common::find_answer;
+
+const HD01: &str = "\
+; Turn off the rightmost 1-bit
+(set-logic BV)
+(set-info :source |Hacker's Delight|)
+
+(define-fun hd01 ((x (_ BitVec 8))) (_ BitVec 8) (bvand x (bvsub x #x01)))
+
+(synth-fun f ((x (_ BitVec 8))) (_ BitVec 8)
+    ((Start (_ BitVec 8)))
+    ((Start (_ BitVec 8) ((bvand Start Start) (bvsub Start Start) (bvor Start Start) (bvnot Start) x #x01))))
+
+(declare-var y (_ BitVec 8))
+(constraint (= (hd01 y) (f y)))
+(check-synth)
+";
+
+const DIVMOD: &str = "\
+(set-logic BV)
+(synth-fun q ((a (_ BitVec 8)) (b (_ BitVec 8))) (_ BitVec 8))
+(synth-fun r ((a (_ BitVec 8)) (b (_ BitVec 8))) (_ BitVec 8))
+(declare-var x (_ BitVec 8))
+(declare-var y (_ BitVec 8))
+(constraint (=> (distinct y #x00) (= x (bvadd (bvmul (q x y) y) (r x y)))))
+(constraint (=> (distinct y #x00) (bvult (r x y) y)))
+(check-synth)
+";
+
+fn searcher<'ctx>(z3: &'ctx z3::Context, problem: &SygusProblem) -> BithackSearch<'ctx, BruteEnum> {
+    let mut search = BithackSearch::with_results(
+        false,
+        z3,
+        problem.width,
+        Signedness::Unsigned,
+        &problem.ops(),
+        problem.args.clone(),
+        problem.results(),
+        2,
+    );
+    search.parse_prompt(&problem.prompt).unwrap();
+    search.parse_assumptions(&problem.assumptions).unwrap();
+
+    search
+}
+
+fn parse(problem: &SygusProblem, src: &str) -> AnswerExpr {
+    parse_answer(src, &problem.args, problem.width, Signedness::Unsigned).unwrap()
+}
+
+fn error(src: &str) -> (Option<usize>, SygusErrorKind) {
+    let err = parse_sygus(src).unwrap_err();
+
+    (err.line, err.kind)
+}
+
+#[test]
+fn test_sygus_problem() {
+    let problem = parse_sygus(HD01).unwrap();
+
+    assert_eq!(problem.logic.as_deref(), Some("BV"));
+    assert_eq!(problem.width, 8);
+    assert_eq!(problem.args, ["y"]);
+    assert_eq!(problem.results(), ["f"]);
+    assert_eq!(problem.funs[0].params, ["x"]);
+    assert_eq!(problem.prompt, "\
+(define-fun hd01 ((x (_ BitVec 8))) (_ BitVec 8) (bvand x (bvsub x #x01)))
+(assert (= (hd01 y) f))");
+
+    assert_eq!(problem.assumptions, "");
+
+    let problem = parse_sygus(DIVMOD).unwrap();
+    assert_eq!(problem.args, ["x", "y"]);
+    assert_eq!(problem.results(), ["q", "r"]);
+    assert_eq!(problem.ops(), OpSet::all());
+
+    // The assumptions get the definitions too
+    let problem = parse_sygus(&HD01.replace("(check-synth)", "(assume (distinct (hd01 y) #x00))")).unwrap();
+    assert_eq!(problem.assumptions, "\
+(define-fun hd01 ((x (_ BitVec 8))) (_ BitVec 8) (bvand x (bvsub x #x01)))
+(assert (distinct (hd01 y) #x00))");
+}
+
+#[test]
+fn test_sygus_grammar() {
+    let grammar = parse_sygus(HD01).unwrap().funs[0].grammar.clone().unwrap();
+    assert_eq!(grammar.unops, [UnopKind::Not]);
+    assert_eq!(grammar.binops, [BinopKind::And, BinopKind::Or, BinopKind::Minus]);
+    assert_eq!(grammar.cmps, []);
+
+    // The comparisons only matter to the if-then-else
+    let problem = parse_sygus("\
+(synth-fun f ((x (_ BitVec 8))) (_ BitVec 8)
+    ((Start (_ BitVec 8)) (B Bool))
+    ((Start (_ BitVec 8) (x (ite B Start Start) (bvneg Start)))
+     (B Bool ((bvult Start Start) (bvsge Start Start)))))
+(synth-fun g ((x (_ BitVec 8))) (_ BitVec 8)
+    ((Start (_ BitVec 8) (x (bvadd Start Start)))))
+").unwrap();
+    let ops = problem.ops();
+    assert_eq!(ops.unops, [UnopKind::Negate]);
+    assert_eq!(ops.binops, [BinopKind::Plus]);
+    assert_eq!(ops.cmps, [CmpKind::Slt, CmpKind::Ult]);
+}
+
+#[test]
+fn test_sygus_errors() {
+    let bv8 = "(declare-var x (_ BitVec 8))\n(synth-fun f ((a (_ BitVec 8))) (_ BitVec 8))\n";
+
+    assert_eq!(error("(set-logic LIA)"), (Some(1), SygusErrorKind::Unsupported("logic LIA".to_string())));
+    assert_eq!(error(&format!("{bv8}(declare-datatype T ((A)))")), (Some(3), SygusErrorKind::Unsupported("command declare-datatype".to_string())));
+    assert_eq!(
+        error(&format!("{bv8}(assume (= (f x) x))")),
+        (Some(3), SygusErrorKind::Malformed("an assumption without the functions to synthesize")),
+    );
+    assert_eq!(error("(declare-var x Int)"), (Some(1), SygusErrorKind::Unsupported("sort Int".to_string())));
+    assert_eq!(
+        error(&format!("{bv8}(declare-var y (_ BitVec 16))")),
+        (Some(3), SygusErrorKind::WidthMismatch { expected: 8, found: 16 }),
+    );
+    assert_eq!(error(&format!("{bv8}(declare-var f (_ BitVec 8))")), (Some(3), SygusErrorKind::Redeclared("f".to_string())));
+    assert_eq!(error("(declare-var x (_ BitVec 8))\n(constraint (= x x))"), (None, SygusErrorKind::NoSynthFun));
+    assert_eq!(error("(synth-fun f)"), (Some(1), SygusErrorKind::Malformed("(synth-fun <name> (<param>*) <sort> <grammar>?)")));
+
+    // The functions must be applied to the same distinct variables
+    let invocation = SygusErrorKind::Invocation("f".to_string());
+    assert_eq!(error(&format!("{bv8}(constraint (= (f (bvneg x)) x))")).1, invocation.clone());
+    assert_eq!(
+        error(&format!("{bv8}(declare-var y (_ BitVec 8))\n(constraint (= (f x) x))\n(constraint (= (f y) y))")),
+        (Some(5), invocation.clone()),
+    );
+    assert_eq!(
+        error("(declare-var x (_ BitVec 8))\n(synth-fun f ((a (_ BitVec 8)) (b (_ BitVec 8))) (_ BitVec 8))\n(constraint (= (f x x) x))").1,
+        invocation,
+    );
+    assert_eq!(
+        error(&format!("{bv8}(declare-var y (_ BitVec 8))\n(constraint (= y #x00))\n(constraint (= (f x) x))")),
+        (Some(4), SygusErrorKind::UnboundVariable("y".to_string())),
+    );
+
+    assert_eq!(error(&format!("{bv8}(constraint (= (f x) x)")), (Some(3), SygusErrorKind::UnexpectedEnd));
+    assert_eq!(error("(set-info :source |open"), (Some(1), SygusErrorKind::UnexpectedEnd));
+    assert_eq!(error("(check-synth))"), (Some(1), SygusErrorKind::UnexpectedParen));
+}
+
+#[test]
+fn test_sygus_search() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let problem = parse_sygus(HD01).unwrap();
+    let mut search = searcher(&z3, &problem);
+
+    let answer = find_answer(&mut search);
+    assert!(problem.ops().allows(&answer[0]));
+    assert!(emit_sygus(&answer[0], "f", &problem.args, problem.width)
+        .starts_with("(\n(define-fun f ((y (_ BitVec 8))) (_ BitVec 8)\n"));
+
+    let problem = parse_sygus(DIVMOD).unwrap();
+    let search = searcher(&z3, &problem);
+    assert!(search.check_answer(&[parse(&problem, "(x /u y)"), parse(&problem, "(x %u y)")]));
+    assert!(!search.check_answer(&[parse(&problem, "(x /u y)"), parse(&problem, "0")]));
+
+    // Outside of the assumptions, anything goes
+    assert!(!search.check_answer(&[parse(&problem, "0"), parse(&problem, "x")]));
+    let problem = parse_sygus(&DIVMOD.replace("(check-synth)", "(assume (bvult x y))")).unwrap();
+    let search = searcher(&z3, &problem);
+    assert!(search.check_answer(&[parse(&problem, "0"), parse(&problem, "x")]));
+}
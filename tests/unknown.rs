@@ -0,0 +1,127 @@
+mod common;
+
+use bitsynth::expr::{Expr, ExprVal, OpSet, Signedness, Variable};
+use bitsynth::search::{BithackSearch, SearchStep, UnknownPolicy};
+use bitsynth::synth::brute_enum::BruteEnum;
+use bitsynth::synth::circuit_enum::CircuitEnum;
+use bitsynth::synth::Synthesizer;
+use common::args;
+
+pub const UNKNOWN_WIDTH: u32 = 64;
+pub const UNKNOWN_STEPS: usize = 8;
+/// Too short for z3 to decide anything at this width
+pub const UNKNOWN_TIMEOUT: u32 = 1;
+pub const UNKNOWN_SPEC: &str = "(assert (= res (bvmul (bvmul x y) (bvmul x y))))";
+
+/// Proposes the candidates it is given and counts the
+/// ones reported to have nothing to learn from
+#[derive(Default)]
+struct Counting {
+    cands: Vec<Vec<Expr>>,
+    unknown: usize,
+}
+
+impl<'ctx> Synthesizer<'ctx> for Counting {
+    fn build(_: &'ctx z3::Context, _: u32, _: Signedness, _: &OpSet, _: usize, _: usize, _: usize) -> Self {
+        Counting::default()
+    }
+
+    fn bad_cand(&mut self, _cand: &[Expr], _args: Vec<ExprVal>, _expected: Vec<ExprVal>) {}
+
+    fn unknown_cand(&mut self, _cand: &[Expr]) {
+        self.unknown += 1;
+    }
+
+    fn next_expr(&mut self) -> Option<Vec<Expr>> {
+        (!self.cands.is_empty()).then(|| self.cands.remove(0))
+    }
+}
+
+fn search<'ctx, S: Synthesizer<'ctx>>(
+    z3: &'ctx z3::Context,
+    should_learn: bool,
+    policy: UnknownPolicy,
+) -> BithackSearch<'ctx, S> {
+    let mut search = BithackSearch::<S>::new(
+        should_learn,
+        z3,
+        UNKNOWN_WIDTH,
+        Signedness::Signed,
+        &OpSet::all(),
+        args(),
+        1,
+    );
+    search.parse_prompt(UNKNOWN_SPEC).unwrap();
+    search.set_timeout(Some(UNKNOWN_TIMEOUT));
+    search.set_unknown_policy(policy);
+
+    search
+}
+
+fn unknown_steps<'ctx, S: Synthesizer<'ctx>>(search: &mut BithackSearch<'ctx, S>) -> usize {
+    std::iter::from_fn(|| search.step())
+        .take(UNKNOWN_STEPS)
+        .filter(|x| matches!(x, SearchStep::UnknownSample { .. }))
+        .count()
+}
+
+#[test]
+fn test_unknown_skip() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = search::<BruteEnum>(&z3, false, UnknownPolicy::Skip);
+
+    let unknown = unknown_steps(&mut search);
+    assert!(unknown > 0);
+    assert_eq!(search.inconclusive_count(), unknown);
+}
+
+#[test]
+fn test_unknown_retry() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = search::<BruteEnum>(&z3, false, UnknownPolicy::Retry(2));
+
+    // Every sample, which is still unknown, was checked three times
+    let unknown = unknown_steps(&mut search);
+    assert!(unknown > 0);
+    assert!(search.inconclusive_count() >= 3 * unknown);
+}
+
+#[test]
+fn test_unknown_abort() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = search::<BruteEnum>(&z3, false, UnknownPolicy::Abort);
+
+    let steps = std::iter::from_fn(|| search.step()).collect::<Vec<_>>();
+    assert!(matches!(steps.last(), Some(SearchStep::UnknownSample { .. })));
+    assert_eq!(search.inconclusive_count(), 1);
+    assert!(search.step().is_none());
+}
+
+#[test]
+fn test_unknown_circuit_blocked() {
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = search::<CircuitEnum>(&z3, true, UnknownPolicy::Skip);
+
+    let mut circuits = Vec::new();
+    for _ in 0..UNKNOWN_STEPS {
+        let Some(SearchStep::UnknownSample { .. }) = search.step() else { continue };
+        let circuit = search.synth().last_circuit().unwrap().clone();
+
+        assert!(!circuits.contains(&circuit), "{circuit:?} was synthesized twice");
+        circuits.push(circuit);
+    }
+    assert!(!circuits.is_empty());
+}
+
+#[test]
+fn test_unknown_not_learning() {
+    // Without learning, a wrong candidate has no counterexample either
+    let z3 = z3::Context::new(&z3::Config::default());
+    let mut search = common::searcher::<Counting>(&z3, false, &OpSet::all(), &["x"], &["res"], 0);
+    search.parse_prompt("(assert (= res (bvneg x)))").unwrap();
+    search.synth_mut().cands = vec![vec![Expr::Variable(Variable::Argument(0))]];
+
+    let step = search.step();
+    assert!(matches!(step, Some(SearchStep::IncorrectSample { is_universally_wrong: false, .. })), "{step:?}");
+    assert_eq!(search.synth().unknown, 1);
+}
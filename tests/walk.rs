@@ -0,0 +1,87 @@
+mod common;
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use bitsynth::dag::AnswerDag;
+use bitsynth::expr::{AnswerExpr, BinopKind, Expr, Signedness, UnopKind, Value};
+use bitsynth::simplify::Simplifier;
+use common::parse_at;
+
+/// Deep enough to overflow the stack of a test thread
+/// with a recursive traversal.
+pub const WALK_DEPTH: usize = 10_000;
+pub const WALK_WIDTH: u32 = 32;
+
+/// `(!(x ^ ...) + 1)`, `WALK_DEPTH` operators deep
+fn deep_expr() -> AnswerExpr {
+    deep_expr_over(Value::Arg("x".to_string()))
+}
+
+/// Like [deep_expr], with `innermost` at the bottom
+fn deep_expr_over(innermost: Value) -> AnswerExpr {
+    let x = Rc::new(Expr::Variable(Value::Arg("x".to_string())));
+    let mut expr = Rc::new(Expr::Variable(innermost));
+
+    for i in 0..WALK_DEPTH {
+        expr = Rc::new(match i % 3 {
+            0 => Expr::Binop(BinopKind::Plus, expr, Rc::new(Expr::Variable(Value::Const(1)))),
+            1 => Expr::Binop(BinopKind::Xor, x.clone(), expr),
+            _ => Expr::Unop(UnopKind::Not, expr),
+        });
+    }
+
+    Rc::unwrap_or_clone(expr)
+}
+
+#[test]
+fn test_walk_deep_expr() {
+    let expr = deep_expr();
+    let value = expr.compute_ans(WALK_WIDTH, Signedness::Signed, |_| 5);
+
+    assert_eq!(expr.expr_depth(), WALK_DEPTH);
+
+    let simplified = Simplifier::new(WALK_WIDTH, Signedness::Signed).simplify(&expr);
+    assert_eq!(simplified.compute_ans(WALK_WIDTH, Signedness::Signed, |_| 5), value);
+
+    let (dag, root) = AnswerDag::from_expr(&expr);
+    assert!(dag.to_expr(root) == expr);
+}
+
+#[test]
+fn test_walk_deep_traits() {
+    let expr = deep_expr();
+    let copy = deep_expr();
+    let other = deep_expr_over(Value::Const(0));
+
+    assert!(expr == copy && expr != other);
+    assert!(expr < other && other > copy);
+
+    let mut set = HashSet::new();
+    assert!(set.insert(expr.clone()));
+    assert!(!set.insert(copy));
+    assert!(set.insert(other));
+
+    let printed = format!("{expr:?}");
+    assert!(printed.starts_with(r#"Binop(Plus, Unop(Not, Binop(Xor, Variable(Arg("x")), Binop(Plus, "#));
+    assert!(printed.ends_with("), Variable(Const(1)))"));
+
+    assert!(parse_at(&expr.to_string(), WALK_WIDTH) == expr);
+
+    // Dropping doesn't recurse either
+    drop(set);
+    drop(expr);
+}
+
+#[test]
+fn test_walk_deep_display() {
+    let expr = deep_expr();
+    let printed = expr.to_string();
+
+    assert!(printed.starts_with("(!((x ^ (!((x ^ "));
+    assert!(printed.contains("(!((x ^ (x + 1))) + 1)))"));
+    assert!(printed.ends_with(" + 1))) + 1)"));
+
+    let small = "((popcnt(x) < -(x)) ? ((x rotl 3) %u x) : !((x >>> 1)))";
+    assert_eq!(parse_at(small, WALK_WIDTH).to_string(), small);
+}